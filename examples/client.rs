@@ -3,7 +3,7 @@ use futures::io::{AsyncReadExt, AsyncWriteExt};
 
 async fn http_get(addr: &str) -> Result<String, std::io::Error> {
     let mut conn = AsyncTcpStream::connect(addr)?;
-    let _ = conn.write_all(b"GET / HTTP/1.0\r\n\r\n").await?;
+    conn.write_all(b"GET / HTTP/1.0\r\n\r\n").await?;
     let mut page = Vec::new();
     loop {
         let mut buf = vec![0; 128];
@@ -11,6 +11,13 @@ async fn listen(addr: &str) {
     let mut incoming = listener.incoming();
 
     while let Some(stream) = incoming.next().await {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("accept failed: {}", err);
+                continue;
+            }
+        };
         fahrenheit::spawn(process(stream));
     }
 }
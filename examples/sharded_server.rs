@@ -0,0 +1,52 @@
+use std::net::SocketAddr;
+use std::thread;
+
+use fahrenheit::AsyncTcpListener;
+use fahrenheit::AsyncTcpStream;
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+use futures::stream::StreamExt;
+
+// one event loop per thread, each with its own SO_REUSEPORT listener
+async fn listen(id: usize, listener: AsyncTcpListener) {
+    let mut incoming = listener.incoming();
+
+    while let Some(stream) = incoming.next().await {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("accept failed: {}", err);
+                continue;
+            }
+        };
+        fahrenheit::spawn(process(id, stream));
+    }
+}
+
+async fn process(id: usize, mut stream: AsyncTcpStream) {
+    let mut buf = vec![0; 1024];
+    while let Ok(len) = stream.read(&mut buf).await {
+        if len == 0 {
+            break;
+        }
+        println!("worker {}: {}", id, String::from_utf8_lossy(&buf[..len]));
+        if stream.write_all(&buf[..len]).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn main() {
+    let addr: SocketAddr = "127.0.0.1:12345".parse().unwrap();
+    let workers = thread::available_parallelism().map_or(1, |n| n.get());
+    let listeners = AsyncTcpListener::bind_reuseport(addr, workers).unwrap();
+
+    let threads: Vec<_> = listeners
+        .into_iter()
+        .enumerate()
+        .map(|(id, listener)| thread::spawn(move || fahrenheit::run(listen(id, listener))))
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
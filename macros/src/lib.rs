@@ -0,0 +1,111 @@
+// #[fahrenheit::test]: an async test run to completion on the reactor.
+//
+//     #[fahrenheit::test(start_paused = true, timeout = "5s")]
+//     async fn retries() { ... }
+//
+// start_paused pauses the clock before the test starts, see
+// fahrenheit::time. timeout fails a test still running after that much
+// real time, paused clock or not. Durations take ms, s, m or h
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{Error, Expr, ExprLit, ItemFn, Lit, Meta, Token};
+
+#[proc_macro_attribute]
+pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
+    match expand(args, item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand(args: TokenStream, item: TokenStream) -> Result<proc_macro2::TokenStream, Error> {
+    let metas = Punctuated::<Meta, Token![,]>::parse_terminated.parse(args)?;
+    let mut start_paused = false;
+    let mut timeout = quote!(::core::option::Option::None);
+
+    for meta in metas {
+        let nv = match meta {
+            Meta::NameValue(nv) => nv,
+            other => return Err(Error::new_spanned(other, "expected `name = value`")),
+        };
+        let lit = match &nv.value {
+            Expr::Lit(ExprLit { lit, .. }) => lit,
+            other => return Err(Error::new_spanned(other, "expected a literal")),
+        };
+
+        if nv.path.is_ident("start_paused") {
+            start_paused = match lit {
+                Lit::Bool(b) => b.value,
+                other => return Err(Error::new_spanned(other, "expected true or false")),
+            };
+        } else if nv.path.is_ident("timeout") {
+            let millis = match lit {
+                Lit::Str(s) => {
+                    parse_duration(&s.value()).map_err(|msg| Error::new(s.span(), msg))?
+                }
+                other => return Err(Error::new_spanned(other, "expected a duration like \"5s\"")),
+            };
+            timeout = quote! {
+                ::core::option::Option::Some(::core::time::Duration::from_millis(#millis))
+            };
+        } else {
+            return Err(Error::new_spanned(
+                &nv.path,
+                "unknown option, expected start_paused or timeout",
+            ));
+        }
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = syn::parse(item)?;
+
+    if sig.asyncness.is_none() {
+        return Err(Error::new_spanned(
+            sig.fn_token,
+            "the test must be an async fn",
+        ));
+    }
+    if !sig.inputs.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.inputs,
+            "the test can't take arguments",
+        ));
+    }
+    if let syn::ReturnType::Type(_, ty) = &sig.output {
+        return Err(Error::new_spanned(ty, "the test must return ()"));
+    }
+
+    let name = &sig.ident;
+    Ok(quote! {
+        #[::core::prelude::v1::test]
+        #(#attrs)*
+        #vis fn #name() {
+            ::fahrenheit::__run_test(async move #block, #start_paused, #timeout)
+        }
+    })
+}
+
+// "250ms", "5s", "2m" or "1h" in milliseconds
+fn parse_duration(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, unit) = s.split_at(split);
+    let n: u64 = n
+        .parse()
+        .map_err(|_| format!("{:?} doesn't start with a number", s))?;
+
+    let scale = match unit.trim() {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        _ => return Err(format!("{:?} needs a unit: ms, s, m or h", s)),
+    };
+    n.checked_mul(scale)
+        .ok_or_else(|| format!("{:?} is too long", s))
+}
@@ -0,0 +1,155 @@
+// AsyncFd drives any non-blocking descriptor (serial ports, GPIO, D-Bus,
+// ...) from the reactor. It owns the io object and hands out readiness
+// guards; do the io through the guard and clear it on WouldBlock
+use std::fmt;
+use std::future::poll_fn;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::task::{Context, Poll};
+
+use crate::Interest;
+use crate::Ready;
+use crate::Registration;
+
+// the descriptor has to be in non-blocking mode already, AsyncFd doesn't
+// touch its flags
+pub struct AsyncFd<T: AsRawFd> {
+    registration: Registration,
+    inner: Option<T>,
+}
+
+impl<T: AsRawFd> AsyncFd<T> {
+    pub fn new(inner: T) -> Result<AsyncFd<T>, io::Error> {
+        let fd = inner.as_raw_fd();
+        if fd < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid fd"));
+        }
+
+        Ok(AsyncFd {
+            registration: Registration::new(fd),
+            inner: Some(inner),
+        })
+    }
+
+    pub fn get_ref(&self) -> &T {
+        self.inner.as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.as_mut().unwrap()
+    }
+
+    // take the io object back, the fd is removed from the reactor
+    pub fn into_inner(mut self) -> T {
+        self.inner.take().unwrap()
+    }
+
+    pub fn poll_ready(
+        &self,
+        ctx: &mut Context,
+        interest: Interest,
+    ) -> Poll<Result<AsyncFdReadyGuard<'_, T>, io::Error>> {
+        self.registration
+            .poll_ready(ctx, interest)
+            .map_ok(|ready| AsyncFdReadyGuard {
+                async_fd: self,
+                ready,
+            })
+    }
+
+    pub fn poll_read_ready(
+        &self,
+        ctx: &mut Context,
+    ) -> Poll<Result<AsyncFdReadyGuard<'_, T>, io::Error>> {
+        self.poll_ready(ctx, Interest::READABLE)
+    }
+
+    pub fn poll_write_ready(
+        &self,
+        ctx: &mut Context,
+    ) -> Poll<Result<AsyncFdReadyGuard<'_, T>, io::Error>> {
+        self.poll_ready(ctx, Interest::WRITABLE)
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<AsyncFdReadyGuard<'_, T>, io::Error> {
+        poll_fn(|ctx| self.poll_ready(ctx, interest)).await
+    }
+
+    pub async fn readable(&self) -> Result<AsyncFdReadyGuard<'_, T>, io::Error> {
+        self.ready(Interest::READABLE).await
+    }
+
+    pub async fn writable(&self) -> Result<AsyncFdReadyGuard<'_, T>, io::Error> {
+        self.ready(Interest::WRITABLE).await
+    }
+}
+
+impl<T: AsRawFd> AsRawFd for AsyncFd<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.registration.fd()
+    }
+}
+
+impl<T: AsRawFd + fmt::Debug> fmt::Debug for AsyncFd<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncFd")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// the fd was found ready for something. The reactor doesn't remember
+// readiness, every wait checks the descriptor again, so a stale guard
+// costs at most one extra WouldBlock round trip
+pub struct AsyncFdReadyGuard<'a, T: AsRawFd> {
+    async_fd: &'a AsyncFd<T>,
+    ready: Ready,
+}
+
+impl<'a, T: AsRawFd> AsyncFdReadyGuard<'a, T> {
+    // which conditions were seen
+    pub fn ready(&self) -> Ready {
+        self.ready
+    }
+
+    // the io hit WouldBlock, wait again before the next attempt
+    pub fn clear_ready(&mut self) {
+        self.ready = Ready::EMPTY;
+    }
+
+    // run f, if it returns WouldBlock the guard is cleared and Err is
+    // returned so the caller goes back to waiting
+    pub fn try_io<R>(
+        &mut self,
+        f: impl FnOnce(&'a AsyncFd<T>) -> Result<R, io::Error>,
+    ) -> Result<Result<R, io::Error>, TryIoError> {
+        match f(self.async_fd) {
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                self.clear_ready();
+                Err(TryIoError(()))
+            }
+            res => Ok(res),
+        }
+    }
+
+    pub fn get_ref(&self) -> &'a AsyncFd<T> {
+        self.async_fd
+    }
+
+    pub fn get_inner(&self) -> &'a T {
+        self.async_fd.get_ref()
+    }
+}
+
+impl<'a, T: AsRawFd> fmt::Debug for AsyncFdReadyGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AsyncFdReadyGuard")
+            .field("fd", &self.async_fd.as_raw_fd())
+            .field("ready", &self.ready)
+            .finish()
+    }
+}
+
+// the operation in try_io() would have blocked
+#[derive(Debug)]
+pub struct TryIoError(());
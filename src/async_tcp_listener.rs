@@ -1,18 +1,31 @@
+use std::convert::TryFrom;
+use std::future::Future;
 use std::io;
-use std::net::TcpListener;
 use std::net::ToSocketAddrs;
-use std::os::unix::io::AsRawFd;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::pin::Pin;
-use std::task::Context;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::task::Poll;
+use std::task::{Context, Waker};
+use std::time::{Duration, Instant};
 
 use futures_core::Stream;
 
+use crate::sys;
+use crate::time::{self, sleep, Sleep};
 use crate::AsyncTcpStream;
+use crate::TcpSocket;
 use crate::REACTOR;
 
 use log::debug;
 
+// same as std
+const DEFAULT_BACKLOG: u32 = 128;
+
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+
 // AsyncTcpListener just wraps std tcp listener
 #[derive(Debug)]
 pub struct AsyncTcpListener(TcpListener);
@@ -25,35 +38,406 @@ impl AsyncTcpListener {
         Ok(AsyncTcpListener(inner))
     }
 
+    // listen on 0.0.0.0:port
+    pub fn bind_any_v4(port: u16) -> Result<AsyncTcpListener, io::Error> {
+        let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+        AsyncTcpListener::bind_with(addr, None, DEFAULT_BACKLOG)
+    }
+
+    // listen on [::]:port for IPv6 connections only
+    pub fn bind_any_v6(port: u16) -> Result<AsyncTcpListener, io::Error> {
+        let addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+        AsyncTcpListener::bind_with(addr, Some(true), DEFAULT_BACKLOG)
+    }
+
+    // listen on [::]:port for both IPv6 and IPv4 connections,
+    // IPv4 peers show up as v4-mapped addresses (::ffff:a.b.c.d)
+    pub fn bind_dual_stack(port: u16) -> Result<AsyncTcpListener, io::Error> {
+        let addr = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+        AsyncTcpListener::bind_with(addr, Some(false), DEFAULT_BACKLOG)
+    }
+
+    // like bind, but with an explicit listen backlog: the number of
+    // connections the kernel queues up before we accept them
+    pub fn bind_with_backlog<A: ToSocketAddrs>(
+        addr: A,
+        backlog: u32,
+    ) -> Result<AsyncTcpListener, io::Error> {
+        let mut last_err = None;
+
+        // try every resolved address, same as std
+        for addr in addr.to_socket_addrs()? {
+            match AsyncTcpListener::bind_with(addr, None, backlog) {
+                Ok(listener) => return Ok(listener),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "could not resolve to any addresses",
+            )
+        }))
+    }
+
+    // bind n listeners to the same address with SO_REUSEPORT, the kernel
+    // spreads incoming connections between them. Move each one to its own
+    // thread running its own event loop to scale accept across cores.
+    // if addr has port 0 all listeners share the port picked for the first
+    pub fn bind_reuseport(addr: SocketAddr, n: usize) -> Result<Vec<AsyncTcpListener>, io::Error> {
+        let mut addr = addr;
+        let mut listeners = Vec::with_capacity(n);
+
+        for _ in 0..n {
+            let socket = TcpSocket::new_for_addr(&addr)?;
+            socket.set_reuseaddr(true)?;
+            socket.set_reuseport(true)?;
+            socket.bind(addr)?;
+            addr = socket.local_addr()?;
+
+            listeners.push(socket.listen(DEFAULT_BACKLOG)?);
+        }
+
+        Ok(listeners)
+    }
+
+    fn bind_with(
+        addr: SocketAddr,
+        only_v6: Option<bool>,
+        backlog: u32,
+    ) -> Result<AsyncTcpListener, io::Error> {
+        let socket = TcpSocket::new_for_addr(&addr)?;
+
+        socket.set_reuseaddr(true)?;
+        if let Some(only_v6) = only_v6 {
+            socket.set_only_v6(only_v6)?;
+        }
+        socket.bind(addr)?;
+        socket.listen(backlog)
+    }
+
+    pub fn from_std(listener: TcpListener) -> Result<AsyncTcpListener, io::Error> {
+        listener.set_nonblocking(true)?;
+        Ok(AsyncTcpListener(listener))
+    }
+
+    // the socket stays in non-blocking mode
+    pub fn into_std(self) -> TcpListener {
+        self.0
+    }
+
+    // ttl for packets of the accepted connections
+    pub fn set_ttl(&self, ttl: u32) -> Result<(), io::Error> {
+        self.0.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> Result<u32, io::Error> {
+        self.0.ttl()
+    }
+
+    // enable TCP Fast Open, queue_len bounds the number of pending
+    // connections that have carried data in their SYN
+    #[cfg(all(feature = "tcp-fastopen", target_os = "linux"))]
+    pub fn set_fastopen(&self, queue_len: u32) -> Result<(), io::Error> {
+        let value = queue_len as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_FASTOPEN,
+            value,
+        )
+    }
+
+    // TCP_DEFER_ACCEPT: don't report a connection as acceptable until the
+    // client has sent data (or the timeout expires), so idle connects
+    // don't wake the accept loop. The timeout is in seconds, a partial one
+    // is rounded up rather than turning the option off
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_defer_accept(&self, timeout: Option<Duration>) -> Result<(), io::Error> {
+        let value = timeout.map_or(0, sys::secs_rounded_up);
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_DEFER_ACCEPT,
+            value,
+        )
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn defer_accept(&self) -> Result<Option<Duration>, io::Error> {
+        let value: libc::c_int = sys::getsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_DEFER_ACCEPT,
+        )?;
+
+        if value == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_secs(value as u64)))
+        }
+    }
+
     pub fn incoming(self) -> Incoming {
-        Incoming(self.0)
+        Incoming {
+            listener: self.0,
+            backoff: DEFAULT_BACKOFF,
+            delay: None,
+        }
     }
 }
 
-pub struct Incoming(TcpListener);
+impl AsRawFd for AsyncTcpListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncTcpListener {
+    fn into_raw_fd(self) -> RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncTcpListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncTcpListener {
+        AsyncTcpListener(TcpListener::from(sys::adopt_fd(fd)))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncTcpListener {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncTcpListener, io::Error> {
+        AsyncTcpListener::from_std(TcpListener::from(fd))
+    }
+}
+
+pub struct Incoming {
+    listener: TcpListener,
+    // how long to pause accepting when we're out of file descriptors
+    backoff: Duration,
+    delay: Option<Sleep>,
+}
+
+// stop watching the listening socket once the stream is gone
+impl Drop for Incoming {
+    fn drop(&mut self) {
+        let fd = self.listener.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| reactor.remove_read_interest(fd));
+    }
+}
+
+impl Incoming {
+    // when accept fails because the process or system ran out of file
+    // descriptors (EMFILE/ENFILE) or memory, stop accepting for this long
+    // and retry. The pending connection stays queued in the backlog
+    pub fn backoff(mut self, backoff: Duration) -> Incoming {
+        self.backoff = backoff;
+        self
+    }
+
+    // stop accepting while max connections are being handled. Every
+    // connection comes with a permit, accepting resumes when one is dropped
+    pub fn limit(self, max: usize) -> LimitedIncoming {
+        LimitedIncoming {
+            incoming: self,
+            limit: Arc::new(Limit {
+                available: AtomicUsize::new(max),
+                waker: Mutex::new(None),
+            }),
+        }
+    }
+
+    // accept at most per_second connections per second (token bucket,
+    // bursts of up to per_second connections are let through). Pending
+    // connections wait in the listen backlog meanwhile
+    pub fn rate_limit(self, per_second: u32) -> RateLimitedIncoming {
+        assert!(per_second > 0, "rate must be positive");
+
+        let rate = per_second as f64;
+        RateLimitedIncoming {
+            incoming: self,
+            rate,
+            tokens: rate,
+            last_refill: time::now(),
+            delay: None,
+        }
+    }
+}
+
+impl AsRawFd for Incoming {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
 
 //Future 代表一个任务，Stream代表n个Future，可以通过poll_next来不断获取下一个任务
 //Stream类似Future Iterator，会不断调用poll_next来获取下一个future(或者说future任务)，listener socket需要不断accept连接，因此将其抽象为Stream比较合适(不太确定，没试过，但直接用原生socket不断accept然后把每个返回的连接分别封装进不同的future里再传给reactor也行)
+// errors other than running out of descriptors are passed on, the stream
+// keeps accepting after them
 impl Stream for Incoming {
-    type Item = AsyncTcpStream;
+    type Item = Result<AsyncTcpStream, io::Error>;
 
-    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
         debug!("poll_next() called");
 
-        let fd = self.0.as_raw_fd();
+        if let Some(delay) = self.delay.as_mut() {
+            if Pin::new(delay).poll(ctx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+
+        let fd = self.listener.as_raw_fd();
         let waker = ctx.waker();
 
-        match self.0.accept() {  //阻塞直到有连接来
-            Ok((conn, _)) => {
-                let stream = AsyncTcpStream::from_std(conn).unwrap();
-                Poll::Ready(Some(stream))  //返回stream
+        loop {
+            match self.listener.accept() {  //阻塞直到有连接来
+                Ok((conn, _)) => {
+                    return Poll::Ready(Some(AsyncTcpStream::from_std(conn)));  //返回stream
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {  //如果是EWOULDBLOCK，返回pending
+                    REACTOR.with(|reactor| reactor.add_read_interest(fd, waker.clone()));
+
+                    return Poll::Pending;
+                }
+                // the client gave up before we got to it, try the next one
+                Err(ref err) if err.raw_os_error() == Some(libc::ECONNABORTED) => {
+                    debug!("accept: connection aborted, retrying");
+                }
+                Err(ref err) if is_resource_exhausted(err) => {
+                    debug!("accept: {}, backing off for {:?}", err, self.backoff);
+
+                    let mut delay = sleep(self.backoff);
+                    if Pin::new(&mut delay).poll(ctx).is_ready() {
+                        continue;
+                    }
+                    self.delay = Some(delay);
+
+                    return Poll::Pending;
+                }
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+// errors that go away once some connections are closed
+fn is_resource_exhausted(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EMFILE) | Some(libc::ENFILE) | Some(libc::ENOBUFS) | Some(libc::ENOMEM)
+    )
+}
+
+// shared between LimitedIncoming and the permits it handed out
+struct Limit {
+    available: AtomicUsize,
+    // the accept loop, if it's waiting for a permit
+    waker: Mutex<Option<Waker>>,
+}
+
+pub struct LimitedIncoming {
+    incoming: Incoming,
+    limit: Arc<Limit>,
+}
+
+impl LimitedIncoming {
+    // connections that can be accepted before the limit is hit
+    pub fn available(&self) -> usize {
+        self.limit.available.load(Ordering::Acquire)
+    }
+}
+
+impl Stream for LimitedIncoming {
+    type Item = Result<(AsyncTcpStream, ConnectionPermit), io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        // register before checking so a permit dropped in between isn't missed
+        *self.limit.waker.lock().unwrap_or_else(|err| err.into_inner()) = Some(ctx.waker().clone());
+
+        if self.limit.available.load(Ordering::Acquire) == 0 {
+            debug!("connection limit reached");
+            return Poll::Pending;
+        }
+
+        match Pin::new(&mut self.incoming).poll_next(ctx) {
+            Poll::Ready(Some(Ok(stream))) => {
+                self.limit.available.fetch_sub(1, Ordering::AcqRel);
+                let permit = ConnectionPermit(self.limit.clone());
+
+                Poll::Ready(Some(Ok((stream, permit))))
             }
-            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {  //如果是EWOULDBLOCK，返回pending
-                REACTOR.with(|reactor| reactor.add_read_interest(fd, waker.clone()));
+            Poll::Ready(Some(Err(err))) => Poll::Ready(Some(Err(err))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// keep this alive for as long as the connection is being handled
+pub struct ConnectionPermit(Arc<Limit>);
 
-                Poll::Pending
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.0.available.fetch_add(1, Ordering::AcqRel);
+
+        let waker = self.0.waker.lock().unwrap_or_else(|err| err.into_inner()).take();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+pub struct RateLimitedIncoming {
+    incoming: Incoming,
+    // tokens added per second, also the bucket size
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+    // waiting for the next token
+    delay: Option<Sleep>,
+}
+
+impl RateLimitedIncoming {
+    fn refill(&mut self) {
+        let now = time::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last_refill = now;
+    }
+}
+
+impl Stream for RateLimitedIncoming {
+    type Item = Result<AsyncTcpStream, io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(delay) = self.delay.as_mut() {
+                if Pin::new(delay).poll(ctx).is_pending() {
+                    return Poll::Pending;
+                }
+                self.delay = None;
+            }
+
+            self.refill();
+            if self.tokens >= 1.0 {
+                break;
             }
-            Err(err) => panic!("error {:?}", err),  //如果不是 阻塞error ，panic
+
+            let wait = (1.0 - self.tokens) / self.rate;
+            debug!("accept rate limit hit, waiting {:.3}s", wait);
+            self.delay = Some(sleep(Duration::from_secs_f64(wait)));
+        }
+
+        let next = Pin::new(&mut self.incoming).poll_next(ctx);
+        if let Poll::Ready(Some(Ok(_))) = next {
+            self.tokens -= 1.0;
         }
+        next
     }
 }
@@ -1,44 +1,487 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::future::poll_fn;
 use std::io::Error;
-use std::io::{self, Read, Write};
-use std::net::TcpStream;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
 use std::net::ToSocketAddrs;
-use std::os::unix::io::AsRawFd;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
 
 use futures_io::{AsyncRead, AsyncWrite};
 
 use log::debug;
 
+use crate::dns;
+use crate::interest::{self, Interest, Ready};
+use crate::io::{BufReader, ReadBuf};
+use crate::sys;
+use crate::TcpSocket;
+#[cfg(target_os = "linux")]
+use crate::zerocopy::ZeroCopy;
+#[cfg(not(target_os = "linux"))]
+type ZeroCopy = ();
 use crate::REACTOR;
 
+// selected fields of the kernel's TCP_INFO for a connection
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone)]
+pub struct TcpInfo {
+    // smoothed round trip time and its variance
+    pub rtt: Duration,
+    pub rtt_var: Duration,
+    pub min_rtt: Duration,
+    // retransmitted segments over the lifetime of the connection
+    pub retransmits: u32,
+    pub lost: u32,
+    // congestion window and slow start threshold, in segments
+    pub cwnd: u32,
+    pub ssthresh: u32,
+    // most recent delivery rate estimate, bytes per second
+    pub delivery_rate: u64,
+    pub bytes_acked: u64,
+    pub bytes_received: u64,
+}
+
 // AsyncTcpStream just wraps std tcp stream
 #[derive(Debug)]
-pub struct AsyncTcpStream(TcpStream);
+pub struct AsyncTcpStream(pub(crate) TcpStream, pub(crate) ZeroCopy);
 
 impl AsyncTcpStream {
     pub fn connect<A: ToSocketAddrs>(addr: A) -> Result<AsyncTcpStream, io::Error> {
         let inner = TcpStream::connect(addr)?;
 
         inner.set_nonblocking(true)?;
-        Ok(AsyncTcpStream(inner))
+        Ok(AsyncTcpStream(inner, ZeroCopy::default()))
+    }
+
+    // resolve host without blocking the event loop (see dns::lookup_host)
+    // and connect to the first address that accepts
+    pub async fn connect_host(host: &str, port: u16) -> Result<AsyncTcpStream, io::Error> {
+        let mut last_err = None;
+
+        for ip in dns::lookup_host(host).await? {
+            let addr = SocketAddr::new(ip, port);
+            debug!("connect_host(): trying {}", addr);
+
+            match TcpSocket::new_for_addr(&addr)?.connect(addr).await {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "host has no addresses")
+        }))
     }
 
     pub fn from_std(stream: TcpStream) -> Result<AsyncTcpStream, io::Error> {
         stream.set_nonblocking(true)?;
-        Ok(AsyncTcpStream(stream))
+        Ok(AsyncTcpStream(stream, ZeroCopy::default()))
     }
-}
 
-impl Drop for AsyncTcpStream {
-    fn drop(&mut self) {
-        REACTOR.with(|reactor| {
-            let fd = self.0.as_raw_fd();
+    // hand the socket back to blocking-style code. Any interest registered
+    // with the reactor is dropped, the socket stays in non-blocking mode
+    pub fn into_std(self) -> TcpStream {
+        self.deregister();
+        unsafe { interest::take_io(self, |this| &this.0) }
+    }
+
+    // wrap the stream in a read buffer so it implements AsyncBufRead and
+    // read_until()/lines() work straight off the connection. Writes aren't
+    // buffered, get_ref()/into_inner() reach the stream itself
+    pub fn buffered(self) -> BufReader<AsyncTcpStream> {
+        BufReader::new(self)
+    }
+
+    // shut down the read, write, or both halves of the connection.
+    // Shutdown::Write sends FIN so the peer sees EOF while we can still read
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), io::Error> {
+        self.0.shutdown(how)
+    }
+
+    // disable Nagle's algorithm so small writes go out immediately
+    pub fn set_nodelay(&self, nodelay: bool) -> Result<(), io::Error> {
+        self.0.set_nodelay(nodelay)
+    }
+
+    pub fn nodelay(&self) -> Result<bool, io::Error> {
+        self.0.nodelay()
+    }
+
+    pub fn set_ttl(&self, ttl: u32) -> Result<(), io::Error> {
+        self.0.set_ttl(ttl)
+    }
+
+    pub fn ttl(&self) -> Result<u32, io::Error> {
+        self.0.ttl()
+    }
+
+    // SO_LINGER: with Some(timeout) close() blocks until unsent data is
+    // delivered or the timeout expires, Some(0) resets the connection on close.
+    // The kernel counts in seconds, a partial one is rounded up
+    pub fn set_linger(&self, linger: Option<Duration>) -> Result<(), io::Error> {
+        let value = libc::linger {
+            l_onoff: linger.is_some() as libc::c_int,
+            l_linger: linger.map_or(0, sys::secs_rounded_up),
+        };
+        sys::setsockopt(self.0.as_raw_fd(), libc::SOL_SOCKET, libc::SO_LINGER, value)
+    }
+
+    pub fn linger(&self) -> Result<Option<Duration>, io::Error> {
+        let value: libc::linger =
+            sys::getsockopt(self.0.as_raw_fd(), libc::SOL_SOCKET, libc::SO_LINGER)?;
+
+        if value.l_onoff == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_secs(value.l_linger as u64)))
+        }
+    }
+
+    // SO_KEEPALIVE: periodically probe idle connections so dead peers
+    // (and NAT entries that silently expired) are detected
+    pub fn set_keepalive(&self, keepalive: bool) -> Result<(), io::Error> {
+        let value = keepalive as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_KEEPALIVE,
+            value,
+        )
+    }
+
+    pub fn keepalive(&self) -> Result<bool, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::SOL_SOCKET, libc::SO_KEEPALIVE)?;
+        Ok(value != 0)
+    }
+
+    // how long the connection has to be idle before the first probe is sent
+    pub fn set_keepalive_idle(&self, idle: Duration) -> Result<(), io::Error> {
+        let value = sys::secs_rounded_up(idle);
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            sys::TCP_KEEPIDLE,
+            value,
+        )
+    }
+
+    pub fn keepalive_idle(&self) -> Result<Duration, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::IPPROTO_TCP, sys::TCP_KEEPIDLE)?;
+        Ok(Duration::from_secs(value as u64))
+    }
+
+    // time between unanswered probes
+    pub fn set_keepalive_interval(&self, interval: Duration) -> Result<(), io::Error> {
+        let value = sys::secs_rounded_up(interval);
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_KEEPINTVL,
+            value,
+        )
+    }
+
+    pub fn keepalive_interval(&self) -> Result<Duration, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_KEEPINTVL)?;
+        Ok(Duration::from_secs(value as u64))
+    }
+
+    // number of unanswered probes before the connection is dropped
+    pub fn set_keepalive_retries(&self, retries: u32) -> Result<(), io::Error> {
+        let value = retries as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_KEEPCNT,
+            value,
+        )
+    }
+
+    pub fn keepalive_retries(&self) -> Result<u32, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_KEEPCNT)?;
+        Ok(value as u32)
+    }
+
+    // TCP_USER_TIMEOUT: maximum time transmitted data may stay unacknowledged
+    // before the kernel gives up and fails the connection with ETIMEDOUT.
+    // None restores the system default. Rounded up to whole milliseconds,
+    // at least one, since 0 would mean the default
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_user_timeout(&self, timeout: Option<Duration>) -> Result<(), io::Error> {
+        let value = timeout.map_or(0, |d| sys::millis_rounded_up(d).max(1));
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_USER_TIMEOUT,
+            value,
+        )
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn user_timeout(&self) -> Result<Option<Duration>, io::Error> {
+        let value: libc::c_uint = sys::getsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_USER_TIMEOUT,
+        )?;
+
+        if value == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(Duration::from_millis(value as u64)))
+        }
+    }
+
+    // SO_BINDTODEVICE: only send and receive through the given interface
+    // (e.g. "eth0"). Usually requires CAP_NET_RAW
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_device(&self, interface: Option<&str>) -> Result<(), io::Error> {
+        sys::bind_device(self.0.as_raw_fd(), interface)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn device(&self) -> Result<Option<Vec<u8>>, io::Error> {
+        sys::device(self.0.as_raw_fd())
+    }
+
+    // congestion and health statistics from TCP_INFO
+    #[cfg(target_os = "linux")]
+    pub fn tcp_info(&self) -> Result<TcpInfo, io::Error> {
+        let info: libc::tcp_info =
+            sys::getsockopt(self.0.as_raw_fd(), libc::IPPROTO_TCP, libc::TCP_INFO)?;
+
+        Ok(TcpInfo {
+            rtt: Duration::from_micros(info.tcpi_rtt as u64),
+            rtt_var: Duration::from_micros(info.tcpi_rttvar as u64),
+            min_rtt: Duration::from_micros(info.tcpi_min_rtt as u64),
+            retransmits: info.tcpi_total_retrans,
+            lost: info.tcpi_lost,
+            cwnd: info.tcpi_snd_cwnd,
+            ssthresh: info.tcpi_snd_ssthresh,
+            delivery_rate: info.tcpi_delivery_rate,
+            bytes_acked: info.tcpi_bytes_acked,
+            bytes_received: info.tcpi_bytes_received,
+        })
+    }
+
+    // read into the unfilled part of buf without zeroing it first, e.g.
+    // the spare capacity of a Vec. Ok(0) at EOF or when buf is full
+    pub async fn read_uninit(&mut self, buf: &mut ReadBuf<'_>) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_read_uninit(ctx, buf)).await
+    }
+
+    pub fn poll_read_uninit(
+        &mut self,
+        ctx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<Result<usize, io::Error>> {
+        crate::io::poll_read_uninit(self.0.as_raw_fd(), ctx, buf)
+    }
+
+    // read into the spare capacity of buf and advance it, Ok(0) at EOF or
+    // when buf is full
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: bytes::BufMut + ?Sized>(
+        &mut self,
+        buf: &mut B,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_read_buf(ctx, buf)).await
+    }
+
+    #[cfg(feature = "bytes")]
+    pub fn poll_read_buf<B: bytes::BufMut + ?Sized>(
+        &mut self,
+        ctx: &mut Context,
+        buf: &mut B,
+    ) -> Poll<Result<usize, io::Error>> {
+        crate::io::bytes_io::poll_read_buf(self.0.as_raw_fd(), ctx, buf)
+    }
+
+    // write as much of buf as the socket takes and advance it past that
+    #[cfg(feature = "bytes")]
+    pub async fn write_buf<B: bytes::Buf + ?Sized>(
+        &mut self,
+        buf: &mut B,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_write_buf(ctx, buf)).await
+    }
+
+    #[cfg(feature = "bytes")]
+    pub fn poll_write_buf<B: bytes::Buf + ?Sized>(
+        &mut self,
+        ctx: &mut Context,
+        buf: &mut B,
+    ) -> Poll<Result<usize, io::Error>> {
+        crate::io::bytes_io::poll_write_buf(self.0.as_raw_fd(), ctx, buf)
+    }
+
+    // receive data without removing it from the socket queue (MSG_PEEK),
+    // useful for sniffing the protocol before handing the stream over
+    pub async fn peek(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_peek(ctx, buf)).await
+    }
+
+    pub fn poll_peek(&mut self, ctx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, Error>> {
+        debug!("poll_peek() called");
+
+        let fd = self.0.as_raw_fd();
+        let waker = ctx.waker();
+
+        match self.0.peek(buf) {
+            Ok(len) => Poll::Ready(Ok(len)),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                REACTOR.with(|reactor| reactor.add_read_interest(fd, waker.clone()));
+
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    // copy len bytes of file starting at offset to the socket with sendfile(2),
+    // without passing the data through user space. Returns the number of
+    // bytes sent, which is less than len only if the file ends early
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub async fn send_file(
+        &mut self,
+        file: &File,
+        offset: u64,
+        len: usize,
+    ) -> Result<usize, io::Error> {
+        let fd = self.0.as_raw_fd();
+        let mut offset = offset as libc::off_t;
+        let mut sent = 0;
+
+        poll_fn(|ctx| loop {
+            debug!("send_file() polled, {} of {} bytes sent", sent, len);
+
+            if sent == len {
+                return Poll::Ready(Ok(sent));
+            }
+
+            let rv = unsafe { libc::sendfile(fd, file.as_raw_fd(), &mut offset, len - sent) };
+            if rv == -1 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    REACTOR.with(|reactor| reactor.add_write_interest(fd, ctx.waker().clone()));
+
+                    return Poll::Pending;
+                }
+                return Poll::Ready(Err(err));
+            }
+
+            // end of file
+            if rv == 0 {
+                return Poll::Ready(Ok(sent));
+            }
+
+            sent += rv as usize;
+        })
+        .await
+    }
+
+    // wait for any of the conditions in interest, e.g.
+    // Interest::READABLE | Interest::WRITABLE, and report which ones fired
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| self.poll_ready(ctx, interest)).await
+    }
+
+    pub fn poll_ready(&self, ctx: &mut Context, interest: Interest) -> Poll<Result<Ready, Error>> {
+        interest::poll_ready(self.0.as_raw_fd(), interest, ctx)
+    }
+
+    // wait until the socket has data to read (or EOF/error). Pair with
+    // try_read() to drain the socket completely on every wakeup
+    pub async fn readable(&self) -> Result<(), io::Error> {
+        poll_fn(|ctx| self.poll_read_ready(ctx)).await
+    }
+
+    // wait until the socket can accept more data. Pair with try_write()
+    pub async fn writable(&self) -> Result<(), io::Error> {
+        poll_fn(|ctx| self.poll_write_ready(ctx)).await
+    }
+
+    pub fn poll_read_ready(&self, ctx: &mut Context) -> Poll<Result<(), Error>> {
+        self.poll_ready(ctx, Interest::READABLE).map_ok(|_| ())
+    }
+
+    pub fn poll_write_ready(&self, ctx: &mut Context) -> Poll<Result<(), Error>> {
+        self.poll_ready(ctx, Interest::WRITABLE).map_ok(|_| ())
+    }
+
+    // forget any wakers registered for our fd, otherwise select would keep
+    // watching a closed (or reused) descriptor. try_with because streams may
+    // be dropped while the thread local reactor itself is being destroyed
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
             reactor.remove_read_interest(fd);
             reactor.remove_write_interest(fd);
         });
     }
+
+    pub(crate) fn register_read(&self, ctx: &mut Context) {
+        let fd = self.0.as_raw_fd();
+        REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+    }
+
+    pub(crate) fn register_write(&self, ctx: &mut Context) {
+        let fd = self.0.as_raw_fd();
+        REACTOR.with(|reactor| reactor.add_write_interest(fd, ctx.waker().clone()));
+    }
+
+    // non-blocking read, never registers interest.
+    // returns ErrorKind::WouldBlock if there's nothing to read right now
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        (&self.0).read(buf)
+    }
+
+    // non-blocking write, returns ErrorKind::WouldBlock if the send buffer is full
+    pub fn try_write(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        (&self.0).write(buf)
+    }
+}
+
+impl Drop for AsyncTcpStream {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncTcpStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncTcpStream {
+    fn into_raw_fd(self) -> RawFd {
+        self.into_std().into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncTcpStream {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncTcpStream {
+        AsyncTcpStream(TcpStream::from(sys::adopt_fd(fd)), ZeroCopy::default())
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncTcpStream {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncTcpStream, io::Error> {
+        AsyncTcpStream::from_std(TcpStream::from(fd))
+    }
 }
 
 impl AsyncRead for AsyncTcpStream {
@@ -59,7 +502,29 @@ impl AsyncRead for AsyncTcpStream {
 
                 Poll::Pending
             }
-            Err(err) => panic!("error {:?}", err),
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    // scatter read straight into several buffers with readv(2)
+    fn poll_read_vectored(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &mut [IoSliceMut],
+    ) -> Poll<Result<usize, Error>> {
+        debug!("poll_read_vectored() called");
+
+        let fd = self.0.as_raw_fd();
+        let waker = ctx.waker();
+
+        match self.0.read_vectored(bufs) {
+            Ok(len) => Poll::Ready(Ok(len)),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                REACTOR.with(|reactor| reactor.add_read_interest(fd, waker.clone()));
+
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
         }
     }
 }
@@ -82,7 +547,30 @@ impl AsyncWrite for AsyncTcpStream {
 
                 Poll::Pending
             }
-            Err(err) => panic!("error {:?}", err),
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    // gather write with writev(2), e.g. header and body without copying
+    // them into one buffer first
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<Result<usize, Error>> {
+        debug!("poll_write_vectored() called");
+
+        let fd = self.0.as_raw_fd();
+        let waker = ctx.waker();
+
+        match self.0.write_vectored(bufs) {
+            Ok(len) => Poll::Ready(Ok(len)),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                REACTOR.with(|reactor| reactor.add_write_interest(fd, waker.clone()));
+
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
         }
     }
 
@@ -91,8 +579,9 @@ impl AsyncWrite for AsyncTcpStream {
         Poll::Ready(Ok(()))
     }
 
+    // closing the writer half-closes the connection, the read side stays open
     fn poll_close(self: Pin<&mut Self>, _lw: &mut Context) -> Poll<Result<(), Error>> {
         debug!("poll_close() called");
-        Poll::Ready(Ok(()))
+        Poll::Ready(self.0.shutdown(Shutdown::Write))
     }
 }
@@ -0,0 +1,373 @@
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::task::Context;
+use std::task::Poll;
+
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::sys;
+use crate::REACTOR;
+
+// AsyncUdpSocket just wraps std udp socket
+#[derive(Debug)]
+pub struct AsyncUdpSocket(pub(crate) UdpSocket);
+
+impl AsyncUdpSocket {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> Result<AsyncUdpSocket, io::Error> {
+        let inner = UdpSocket::bind(addr)?;
+
+        inner.set_nonblocking(true)?;
+        Ok(AsyncUdpSocket(inner))
+    }
+
+    pub fn from_std(socket: UdpSocket) -> Result<AsyncUdpSocket, io::Error> {
+        socket.set_nonblocking(true)?;
+        Ok(AsyncUdpSocket(socket))
+    }
+
+    // the socket stays in non-blocking mode
+    pub fn into_std(self) -> UdpSocket {
+        self.deregister();
+        unsafe { interest::take_io(self, |this| &this.0) }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.0.local_addr()
+    }
+
+    // set the default destination for send() and only receive datagrams
+    // from that address with recv(). A connected socket also gets ICMP
+    // errors (e.g. port unreachable) reported by the next send/recv
+    pub fn connect<A: ToSocketAddrs>(&self, addr: A) -> Result<(), io::Error> {
+        self.0.connect(addr)
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.0.peer_addr()
+    }
+
+    // SO_BROADCAST: needed to send_to() a broadcast address such as
+    // 255.255.255.255, otherwise the kernel fails the send with EACCES
+    pub fn set_broadcast(&self, on: bool) -> Result<(), io::Error> {
+        self.0.set_broadcast(on)
+    }
+
+    pub fn broadcast(&self) -> Result<bool, io::Error> {
+        self.0.broadcast()
+    }
+
+    // join a multicast group on the interface with the given address
+    // (Ipv4Addr::UNSPECIFIED lets the kernel pick one)
+    pub fn join_multicast_v4(&self, group: Ipv4Addr, interface: Ipv4Addr) -> Result<(), io::Error> {
+        self.0.join_multicast_v4(&group, &interface)
+    }
+
+    pub fn leave_multicast_v4(
+        &self,
+        group: Ipv4Addr,
+        interface: Ipv4Addr,
+    ) -> Result<(), io::Error> {
+        self.0.leave_multicast_v4(&group, &interface)
+    }
+
+    // interface is an interface index, 0 for the default one
+    pub fn join_multicast_v6(&self, group: Ipv6Addr, interface: u32) -> Result<(), io::Error> {
+        self.0.join_multicast_v6(&group, interface)
+    }
+
+    pub fn leave_multicast_v6(&self, group: Ipv6Addr, interface: u32) -> Result<(), io::Error> {
+        self.0.leave_multicast_v6(&group, interface)
+    }
+
+    // whether our own multicast datagrams are looped back to us
+    pub fn set_multicast_loop_v4(&self, on: bool) -> Result<(), io::Error> {
+        self.0.set_multicast_loop_v4(on)
+    }
+
+    pub fn multicast_loop_v4(&self) -> Result<bool, io::Error> {
+        self.0.multicast_loop_v4()
+    }
+
+    pub fn set_multicast_loop_v6(&self, on: bool) -> Result<(), io::Error> {
+        self.0.set_multicast_loop_v6(on)
+    }
+
+    pub fn multicast_loop_v6(&self) -> Result<bool, io::Error> {
+        self.0.multicast_loop_v6()
+    }
+
+    // how many hops outgoing multicast datagrams may travel, 1 (the
+    // default) keeps them on the local network
+    pub fn set_multicast_ttl_v4(&self, ttl: u32) -> Result<(), io::Error> {
+        self.0.set_multicast_ttl_v4(ttl)
+    }
+
+    pub fn multicast_ttl_v4(&self) -> Result<u32, io::Error> {
+        self.0.multicast_ttl_v4()
+    }
+
+    pub fn set_multicast_hops_v6(&self, hops: u32) -> Result<(), io::Error> {
+        let value = hops as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MULTICAST_HOPS,
+            value,
+        )
+    }
+
+    pub fn multicast_hops_v6(&self) -> Result<u32, io::Error> {
+        let value: libc::c_int = sys::getsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MULTICAST_HOPS,
+        )?;
+        Ok(value as u32)
+    }
+
+    // outgoing interface for IPv6 multicast, by index
+    pub fn set_multicast_if_v6(&self, interface: u32) -> Result<(), io::Error> {
+        let value = interface as libc::c_uint;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_MULTICAST_IF,
+            value,
+        )
+    }
+
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_to(ctx, buf, target)).await
+    }
+
+    pub fn poll_send_to(
+        &self,
+        ctx: &mut Context,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_to() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            self.0.send_to(buf, target)
+        })
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), io::Error> {
+        poll_fn(|ctx| self.poll_recv_from(ctx, buf)).await
+    }
+
+    pub fn poll_recv_from(
+        &self,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<(usize, SocketAddr), io::Error>> {
+        debug!("poll_recv_from() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            self.0.recv_from(buf)
+        })
+    }
+
+    // send to the connected peer
+    pub async fn send(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send(ctx, buf)).await
+    }
+
+    pub fn poll_send(&self, ctx: &mut Context, buf: &[u8]) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            self.0.send(buf)
+        })
+    }
+
+    // receive from the connected peer
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_recv(ctx, buf)).await
+    }
+
+    pub fn poll_recv(&self, ctx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_recv() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            self.0.recv(buf)
+        })
+    }
+
+    // receive a batch of datagrams with a single syscall, one per buffer.
+    // (len, sender) of each datagram is pushed onto out, returns how many
+    // were received. Waits only if nothing at all is queued
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub async fn recv_many(
+        &self,
+        bufs: &mut [&mut [u8]],
+        out: &mut Vec<(usize, SocketAddr)>,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_recv_many(ctx, bufs, out)).await
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn poll_recv_many(
+        &self,
+        ctx: &mut Context,
+        bufs: &mut [&mut [u8]],
+        out: &mut Vec<(usize, SocketAddr)>,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_recv_many() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            sys::recvmmsg(fd, bufs, out)
+        })
+    }
+
+    // send a batch of datagrams with a single syscall. Returns how many
+    // were sent, which may be less than msgs.len() if the socket buffer
+    // filled up on the way
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub async fn send_many(&self, msgs: &[(&[u8], SocketAddr)]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_many(ctx, msgs)).await
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn poll_send_many(
+        &self,
+        ctx: &mut Context,
+        msgs: &[(&[u8], SocketAddr)],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_many() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            sys::sendmmsg(fd, msgs)
+        })
+    }
+
+    // UDP_SEGMENT: default segment size for generic segmentation offload,
+    // every send larger than it is split into datagrams of that size by
+    // the kernel (or the NIC). 0 turns it off
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_segment_size(&self, size: u16) -> Result<(), io::Error> {
+        let value = size as libc::c_int;
+        sys::setsockopt(self.0.as_raw_fd(), libc::SOL_UDP, libc::UDP_SEGMENT, value)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn segment_size(&self) -> Result<u16, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::SOL_UDP, libc::UDP_SEGMENT)?;
+        Ok(value as u16)
+    }
+
+    // send buf as consecutive datagrams of segment bytes each (the last
+    // one may be shorter) with a single syscall. At most 64 segments
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub async fn send_to_segmented(
+        &self,
+        buf: &[u8],
+        target: SocketAddr,
+        segment: u16,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_to_segmented(ctx, buf, target, segment)).await
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn poll_send_to_segmented(
+        &self,
+        ctx: &mut Context,
+        buf: &[u8],
+        target: SocketAddr,
+        segment: u16,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_to_segmented() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            sys::send_segmented(fd, buf, &target, segment)
+        })
+    }
+
+    // UDP_GRO: let the kernel coalesce consecutive datagrams from the same
+    // sender into one receive. Use recv_from_gro() to learn where the
+    // datagram boundaries are
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn set_gro(&self, on: bool) -> Result<(), io::Error> {
+        let value = on as libc::c_int;
+        sys::setsockopt(self.0.as_raw_fd(), libc::SOL_UDP, libc::UDP_GRO, value)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn gro(&self) -> Result<bool, io::Error> {
+        let value: libc::c_int = sys::getsockopt(self.0.as_raw_fd(), libc::SOL_UDP, libc::UDP_GRO)?;
+        Ok(value != 0)
+    }
+
+    // like recv_from(), but also returns the segment size: buf[..len] holds
+    // datagrams of that many bytes each, the last one may be shorter. For a
+    // single datagram the segment size is len. buf should be 64KiB to fit
+    // whatever the kernel coalesced
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub async fn recv_from_gro(
+        &self,
+        buf: &mut [u8],
+    ) -> Result<(usize, SocketAddr, usize), io::Error> {
+        poll_fn(|ctx| self.poll_recv_from_gro(ctx, buf)).await
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn poll_recv_from_gro(
+        &self,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<(usize, SocketAddr, usize), io::Error>> {
+        debug!("poll_recv_from_gro() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            let (len, addr, segment) = sys::recv_gro(fd, buf)?;
+            Ok((len, addr, segment.unwrap_or(len)))
+        })
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| interest::poll_ready(self.0.as_raw_fd(), interest, ctx)).await
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncUdpSocket {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncUdpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncUdpSocket {
+    fn into_raw_fd(self) -> RawFd {
+        self.into_std().into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncUdpSocket {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncUdpSocket {
+        AsyncUdpSocket(UdpSocket::from(sys::adopt_fd(fd)))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncUdpSocket {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncUdpSocket, io::Error> {
+        AsyncUdpSocket::from_std(UdpSocket::from(fd))
+    }
+}
@@ -0,0 +1,200 @@
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::Path;
+use std::task::{Context, Poll};
+
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::sys;
+use crate::REACTOR;
+
+// AsyncUnixDatagram just wraps std unix datagram socket
+#[derive(Debug)]
+pub struct AsyncUnixDatagram(pub(crate) UnixDatagram);
+
+impl AsyncUnixDatagram {
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<AsyncUnixDatagram, io::Error> {
+        let inner = UnixDatagram::bind(path)?;
+
+        inner.set_nonblocking(true)?;
+        Ok(AsyncUnixDatagram(inner))
+    }
+
+    // a socket without a name, enough for sending. Peers can't reply
+    // unless it's connected to them
+    pub fn unbound() -> Result<AsyncUnixDatagram, io::Error> {
+        let inner = UnixDatagram::unbound()?;
+
+        inner.set_nonblocking(true)?;
+        Ok(AsyncUnixDatagram(inner))
+    }
+
+    pub fn from_std(socket: UnixDatagram) -> Result<AsyncUnixDatagram, io::Error> {
+        socket.set_nonblocking(true)?;
+        Ok(AsyncUnixDatagram(socket))
+    }
+
+    // the socket stays in non-blocking mode
+    pub fn into_std(self) -> UnixDatagram {
+        self.deregister();
+        unsafe { interest::take_io(self, |this| &this.0) }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.0.local_addr()
+    }
+
+    // set the default destination for send() and only receive datagrams
+    // from that socket with recv()
+    pub fn connect<P: AsRef<Path>>(&self, path: P) -> Result<(), io::Error> {
+        self.0.connect(path)
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.0.peer_addr()
+    }
+
+    pub async fn send_to<P: AsRef<Path>>(&self, buf: &[u8], path: P) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_to(ctx, buf, path.as_ref())).await
+    }
+
+    pub fn poll_send_to(
+        &self,
+        ctx: &mut Context,
+        buf: &[u8],
+        path: &Path,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_to() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            self.0.send_to(buf, path)
+        })
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), io::Error> {
+        poll_fn(|ctx| self.poll_recv_from(ctx, buf)).await
+    }
+
+    pub fn poll_recv_from(
+        &self,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<(usize, SocketAddr), io::Error>> {
+        debug!("poll_recv_from() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            self.0.recv_from(buf)
+        })
+    }
+
+    // send to the connected peer
+    pub async fn send(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send(ctx, buf)).await
+    }
+
+    pub fn poll_send(&self, ctx: &mut Context, buf: &[u8]) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            self.0.send(buf)
+        })
+    }
+
+    // receive from the connected peer
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_recv(ctx, buf)).await
+    }
+
+    pub fn poll_recv(&self, ctx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_recv() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            self.0.recv(buf)
+        })
+    }
+
+    // send buf along with duplicates of fds (SCM_RIGHTS), on a connected socket
+    pub async fn send_with_fds(&self, buf: &[u8], fds: &[RawFd]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_with_fds(ctx, buf, fds)).await
+    }
+
+    pub fn poll_send_with_fds(
+        &self,
+        ctx: &mut Context,
+        buf: &[u8],
+        fds: &[RawFd],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_with_fds() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            sys::send_with_fds(fd, buf, fds)
+        })
+    }
+
+    // receive into buf, descriptors that came along are pushed onto fds
+    pub async fn recv_with_fds(
+        &self,
+        buf: &mut [u8],
+        fds: &mut Vec<OwnedFd>,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_recv_with_fds(ctx, buf, fds)).await
+    }
+
+    pub fn poll_recv_with_fds(
+        &self,
+        ctx: &mut Context,
+        buf: &mut [u8],
+        fds: &mut Vec<OwnedFd>,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_recv_with_fds() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            sys::recv_with_fds(fd, buf, fds)
+        })
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| interest::poll_ready(self.0.as_raw_fd(), interest, ctx)).await
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncUnixDatagram {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncUnixDatagram {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncUnixDatagram {
+    fn into_raw_fd(self) -> RawFd {
+        self.into_std().into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncUnixDatagram {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncUnixDatagram {
+        AsyncUnixDatagram(UnixDatagram::from(sys::adopt_fd(fd)))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncUnixDatagram {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncUnixDatagram, io::Error> {
+        AsyncUnixDatagram::from_std(UnixDatagram::from(fd))
+    }
+}
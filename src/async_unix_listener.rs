@@ -0,0 +1,151 @@
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{SocketAddr, UnixListener};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use log::debug;
+
+use crate::interest;
+use crate::sys;
+use crate::AsyncUnixStream;
+use crate::REACTOR;
+
+// AsyncUnixListener just wraps std unix listener. The socket file isn't
+// removed when the listener goes away, same as std
+#[derive(Debug)]
+pub struct AsyncUnixListener(UnixListener);
+
+impl AsyncUnixListener {
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<AsyncUnixListener, io::Error> {
+        let inner = UnixListener::bind(path)?;
+
+        inner.set_nonblocking(true)?;
+        Ok(AsyncUnixListener(inner))
+    }
+
+    pub fn bind_addr(addr: &SocketAddr) -> Result<AsyncUnixListener, io::Error> {
+        let inner = UnixListener::bind_addr(addr)?;
+
+        inner.set_nonblocking(true)?;
+        Ok(AsyncUnixListener(inner))
+    }
+
+    // bind in the abstract namespace: the name has no file behind it, so
+    // there's nothing to clean up and it disappears with the last socket
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_abstract<N: AsRef<[u8]>>(name: N) -> Result<AsyncUnixListener, io::Error> {
+        AsyncUnixListener::bind_addr(&SocketAddr::from_abstract_name(name)?)
+    }
+
+    pub fn from_std(listener: UnixListener) -> Result<AsyncUnixListener, io::Error> {
+        listener.set_nonblocking(true)?;
+        Ok(AsyncUnixListener(listener))
+    }
+
+    // the socket stays in non-blocking mode
+    pub fn into_std(self) -> UnixListener {
+        self.deregister();
+        unsafe { interest::take_io(self, |this| &this.0) }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.0.local_addr()
+    }
+
+    pub async fn accept(&self) -> Result<(AsyncUnixStream, SocketAddr), io::Error> {
+        poll_fn(|ctx| self.poll_accept(ctx)).await
+    }
+
+    pub fn poll_accept(
+        &self,
+        ctx: &mut Context,
+    ) -> Poll<Result<(AsyncUnixStream, SocketAddr), io::Error>> {
+        debug!("poll_accept() called");
+
+        match self.0.accept() {
+            Ok((conn, addr)) => Poll::Ready(AsyncUnixStream::from_std(conn).map(|s| (s, addr))),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                let fd = self.0.as_raw_fd();
+                REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    pub fn incoming(self) -> UnixIncoming {
+        UnixIncoming { listener: self }
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| reactor.remove_read_interest(fd));
+    }
+}
+
+// stop watching the listening socket once it's gone
+impl Drop for AsyncUnixListener {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncUnixListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncUnixListener {
+    fn into_raw_fd(self) -> RawFd {
+        self.into_std().into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncUnixListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncUnixListener {
+        AsyncUnixListener(UnixListener::from(sys::adopt_fd(fd)))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncUnixListener {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncUnixListener, io::Error> {
+        AsyncUnixListener::from_std(UnixListener::from(fd))
+    }
+}
+
+// accepted connections, accept errors are passed on to the caller
+#[derive(Debug)]
+pub struct UnixIncoming {
+    listener: AsyncUnixListener,
+}
+
+impl AsRawFd for UnixIncoming {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Stream for UnixIncoming {
+    type Item = Result<AsyncUnixStream, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.listener
+            .poll_accept(ctx)
+            .map(|res| Some(res.map(|(stream, _)| stream)))
+    }
+}
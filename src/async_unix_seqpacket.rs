@@ -0,0 +1,219 @@
+// SOCK_SEQPACKET unix sockets: connection oriented and reliable like a
+// stream, but every send is delivered as one message like a datagram.
+// std has no seqpacket support so these own their descriptors directly
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::sys;
+use crate::time::sleep;
+use crate::Registration;
+use crate::REACTOR;
+
+// same as std
+const DEFAULT_BACKLOG: u32 = 128;
+// between connect attempts while the listener's backlog is full
+const BACKLOG_RETRY: Duration = Duration::from_millis(5);
+
+#[derive(Debug)]
+pub struct AsyncUnixSeqpacketListener(OwnedFd);
+
+impl AsyncUnixSeqpacketListener {
+    pub fn bind<P: AsRef<Path>>(path: P) -> Result<AsyncUnixSeqpacketListener, io::Error> {
+        let fd = sys::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET)?;
+
+        sys::bind_unix(fd.as_raw_fd(), path.as_ref())?;
+        sys::listen(fd.as_raw_fd(), DEFAULT_BACKLOG)?;
+        Ok(AsyncUnixSeqpacketListener(fd))
+    }
+
+    pub async fn accept(&self) -> Result<AsyncUnixSeqpacket, io::Error> {
+        poll_fn(|ctx| self.poll_accept(ctx)).await
+    }
+
+    pub fn poll_accept(&self, ctx: &mut Context) -> Poll<Result<AsyncUnixSeqpacket, io::Error>> {
+        debug!("poll_accept() called");
+        let fd = self.0.as_raw_fd();
+
+        match sys::accept(fd) {
+            Ok(conn) => Poll::Ready(Ok(AsyncUnixSeqpacket(conn))),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl Drop for AsyncUnixSeqpacketListener {
+    fn drop(&mut self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| reactor.remove_read_interest(fd));
+    }
+}
+
+impl AsRawFd for AsyncUnixSeqpacketListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncUnixSeqpacketListener {
+    fn into_raw_fd(self) -> RawFd {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| reactor.remove_read_interest(fd));
+
+        unsafe { interest::take_io(self, |this| &this.0) }.into_raw_fd()
+    }
+}
+
+// the fd must be a listening seqpacket socket, it's switched to
+// non-blocking mode
+impl FromRawFd for AsyncUnixSeqpacketListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncUnixSeqpacketListener {
+        AsyncUnixSeqpacketListener(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncUnixSeqpacketListener {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncUnixSeqpacketListener, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(AsyncUnixSeqpacketListener(fd))
+    }
+}
+
+#[derive(Debug)]
+pub struct AsyncUnixSeqpacket(OwnedFd);
+
+impl AsyncUnixSeqpacket {
+    // the socket stays non-blocking. A connect in progress waits for the
+    // socket to turn writable, like TcpSocket::connect. The kernel doesn't
+    // tell when a full backlog has room again, so that's retried every few
+    // milliseconds
+    pub async fn connect<P: AsRef<Path>>(path: P) -> Result<AsyncUnixSeqpacket, io::Error> {
+        let fd = sys::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET)?;
+        let path = path.as_ref();
+
+        loop {
+            match sys::connect_unix(fd.as_raw_fd(), path) {
+                Ok(true) => break,
+                Ok(false) => {
+                    // takes the interest back out if this future is dropped
+                    let registration = Registration::from_fd(&fd);
+                    registration.writable().await?;
+
+                    if let Some(err) = sys::take_error(fd.as_raw_fd())? {
+                        return Err(err);
+                    }
+                    break;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    debug!("backlog of {:?} full, retrying connect", path);
+                    sleep(BACKLOG_RETRY).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(AsyncUnixSeqpacket(fd))
+    }
+
+    // two connected sockets (socketpair(2)), no filesystem involved
+    pub fn pair() -> Result<(AsyncUnixSeqpacket, AsyncUnixSeqpacket), io::Error> {
+        let (a, b) = sys::socketpair(libc::AF_UNIX, libc::SOCK_SEQPACKET)?;
+
+        Ok((AsyncUnixSeqpacket(a), AsyncUnixSeqpacket(b)))
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), io::Error> {
+        sys::shutdown(self.0.as_raw_fd(), how)
+    }
+
+    // send buf as one message
+    pub async fn send(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send(ctx, buf)).await
+    }
+
+    pub fn poll_send(&self, ctx: &mut Context, buf: &[u8]) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            sys::send(fd, buf)
+        })
+    }
+
+    // receive one message, the part that doesn't fit into buf is discarded.
+    // 0 means the peer has shut down
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_recv(ctx, buf)).await
+    }
+
+    pub fn poll_recv(&self, ctx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_recv() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            sys::recv(fd, buf)
+        })
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| interest::poll_ready(self.0.as_raw_fd(), interest, ctx)).await
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncUnixSeqpacket {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncUnixSeqpacket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncUnixSeqpacket {
+    fn into_raw_fd(self) -> RawFd {
+        self.deregister();
+
+        unsafe { interest::take_io(self, |this| &this.0) }.into_raw_fd()
+    }
+}
+
+// the fd must be a connected seqpacket socket, it's switched to
+// non-blocking mode
+impl FromRawFd for AsyncUnixSeqpacket {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncUnixSeqpacket {
+        AsyncUnixSeqpacket(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncUnixSeqpacket {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncUnixSeqpacket, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(AsyncUnixSeqpacket(fd))
+    }
+}
@@ -0,0 +1,349 @@
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::net::Shutdown;
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{SocketAddr, UnixStream};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::io::ReadBuf;
+use crate::sys;
+use crate::REACTOR;
+
+// credentials of the process on the other end, as they were when it
+// connected (or created the pair)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UCred {
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    // not every platform reports the pid
+    pub pid: Option<libc::pid_t>,
+}
+
+// AsyncUnixStream just wraps std unix stream
+#[derive(Debug)]
+pub struct AsyncUnixStream(pub(crate) UnixStream);
+
+impl AsyncUnixStream {
+    // connecting to a local socket doesn't wait for the network, the
+    // listener's backlog takes the connection right away
+    pub fn connect<P: AsRef<Path>>(path: P) -> Result<AsyncUnixStream, io::Error> {
+        let inner = UnixStream::connect(path)?;
+
+        inner.set_nonblocking(true)?;
+        Ok(AsyncUnixStream(inner))
+    }
+
+    pub fn connect_addr(addr: &SocketAddr) -> Result<AsyncUnixStream, io::Error> {
+        let inner = UnixStream::connect_addr(addr)?;
+
+        inner.set_nonblocking(true)?;
+        Ok(AsyncUnixStream(inner))
+    }
+
+    // connect to a listener in the abstract namespace (see
+    // AsyncUnixListener::bind_abstract)
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn connect_abstract<N: AsRef<[u8]>>(name: N) -> Result<AsyncUnixStream, io::Error> {
+        AsyncUnixStream::connect_addr(&SocketAddr::from_abstract_name(name)?)
+    }
+
+    // two connected sockets (socketpair(2)), no filesystem involved
+    pub fn pair() -> Result<(AsyncUnixStream, AsyncUnixStream), io::Error> {
+        let (a, b) = UnixStream::pair()?;
+
+        Ok((AsyncUnixStream::from_std(a)?, AsyncUnixStream::from_std(b)?))
+    }
+
+    pub fn from_std(stream: UnixStream) -> Result<AsyncUnixStream, io::Error> {
+        stream.set_nonblocking(true)?;
+        Ok(AsyncUnixStream(stream))
+    }
+
+    // the socket stays in non-blocking mode
+    pub fn into_std(self) -> UnixStream {
+        self.deregister();
+        unsafe { interest::take_io(self, |this| &this.0) }
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.0.local_addr()
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.0.peer_addr()
+    }
+
+    // SO_PEERCRED, for authorizing local clients
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn peer_cred(&self) -> Result<UCred, io::Error> {
+        let cred: libc::ucred =
+            sys::getsockopt(self.0.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED)?;
+
+        Ok(UCred {
+            uid: cred.uid,
+            gid: cred.gid,
+            pid: Some(cred.pid),
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn peer_cred(&self) -> Result<UCred, io::Error> {
+        let mut uid = 0;
+        let mut gid = 0;
+
+        if unsafe { libc::getpeereid(self.0.as_raw_fd(), &mut uid, &mut gid) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(UCred {
+            uid,
+            gid,
+            pid: None,
+        })
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), io::Error> {
+        self.0.shutdown(how)
+    }
+
+    // read into the unfilled part of buf without zeroing it first, e.g.
+    // the spare capacity of a Vec. Ok(0) at EOF or when buf is full
+    pub async fn read_uninit(&mut self, buf: &mut ReadBuf<'_>) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_read_uninit(ctx, buf)).await
+    }
+
+    pub fn poll_read_uninit(
+        &mut self,
+        ctx: &mut Context,
+        buf: &mut ReadBuf,
+    ) -> Poll<Result<usize, io::Error>> {
+        crate::io::poll_read_uninit(self.0.as_raw_fd(), ctx, buf)
+    }
+
+    // read into the spare capacity of buf and advance it, Ok(0) at EOF or
+    // when buf is full
+    #[cfg(feature = "bytes")]
+    pub async fn read_buf<B: bytes::BufMut + ?Sized>(
+        &mut self,
+        buf: &mut B,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_read_buf(ctx, buf)).await
+    }
+
+    #[cfg(feature = "bytes")]
+    pub fn poll_read_buf<B: bytes::BufMut + ?Sized>(
+        &mut self,
+        ctx: &mut Context,
+        buf: &mut B,
+    ) -> Poll<Result<usize, io::Error>> {
+        crate::io::bytes_io::poll_read_buf(self.0.as_raw_fd(), ctx, buf)
+    }
+
+    // write as much of buf as the socket takes and advance it past that
+    #[cfg(feature = "bytes")]
+    pub async fn write_buf<B: bytes::Buf + ?Sized>(
+        &mut self,
+        buf: &mut B,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_write_buf(ctx, buf)).await
+    }
+
+    #[cfg(feature = "bytes")]
+    pub fn poll_write_buf<B: bytes::Buf + ?Sized>(
+        &mut self,
+        ctx: &mut Context,
+        buf: &mut B,
+    ) -> Poll<Result<usize, io::Error>> {
+        crate::io::bytes_io::poll_write_buf(self.0.as_raw_fd(), ctx, buf)
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| self.poll_ready(ctx, interest)).await
+    }
+
+    pub fn poll_ready(
+        &self,
+        ctx: &mut Context,
+        interest: Interest,
+    ) -> Poll<Result<Ready, io::Error>> {
+        interest::poll_ready(self.0.as_raw_fd(), interest, ctx)
+    }
+
+    pub async fn readable(&self) -> Result<(), io::Error> {
+        poll_fn(|ctx| self.poll_read_ready(ctx)).await
+    }
+
+    pub async fn writable(&self) -> Result<(), io::Error> {
+        poll_fn(|ctx| self.poll_write_ready(ctx)).await
+    }
+
+    pub fn poll_read_ready(&self, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        self.poll_ready(ctx, Interest::READABLE).map_ok(|_| ())
+    }
+
+    pub fn poll_write_ready(&self, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        self.poll_ready(ctx, Interest::WRITABLE).map_ok(|_| ())
+    }
+
+    // non-blocking read, returns ErrorKind::WouldBlock if there's nothing to read
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        (&self.0).read(buf)
+    }
+
+    // non-blocking write, returns ErrorKind::WouldBlock if the send buffer is full
+    pub fn try_write(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        (&self.0).write(buf)
+    }
+
+    // send buf along with duplicates of fds (SCM_RIGHTS), e.g. to hand an
+    // accepted connection over to a worker process. At least one byte of
+    // buf must be sent for the descriptors to go with it
+    pub async fn send_with_fds(&self, buf: &[u8], fds: &[RawFd]) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_with_fds(ctx, buf, fds)).await
+    }
+
+    pub fn poll_send_with_fds(
+        &self,
+        ctx: &mut Context,
+        buf: &[u8],
+        fds: &[RawFd],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_with_fds() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            sys::send_with_fds(fd, buf, fds)
+        })
+    }
+
+    // receive into buf, descriptors that came along are pushed onto fds
+    pub async fn recv_with_fds(
+        &self,
+        buf: &mut [u8],
+        fds: &mut Vec<OwnedFd>,
+    ) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_recv_with_fds(ctx, buf, fds)).await
+    }
+
+    pub fn poll_recv_with_fds(
+        &self,
+        ctx: &mut Context,
+        buf: &mut [u8],
+        fds: &mut Vec<OwnedFd>,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_recv_with_fds() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            sys::recv_with_fds(fd, buf, fds)
+        })
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncUnixStream {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncUnixStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncUnixStream {
+    fn into_raw_fd(self) -> RawFd {
+        self.into_std().into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncUnixStream {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncUnixStream {
+        AsyncUnixStream(UnixStream::from(sys::adopt_fd(fd)))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncUnixStream {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncUnixStream, io::Error> {
+        AsyncUnixStream::from_std(UnixStream::from(fd))
+    }
+}
+
+impl AsyncRead for AsyncUnixStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            (&self.0).read(buf)
+        })
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &mut [IoSliceMut],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read_vectored() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            (&self.0).read_vectored(bufs)
+        })
+    }
+}
+
+impl AsyncWrite for AsyncUnixStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            (&self.0).write(buf)
+        })
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write_vectored() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            (&self.0).write_vectored(bufs)
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // closing the writer half-closes the connection, the read side stays open
+    fn poll_close(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(self.0.shutdown(Shutdown::Write))
+    }
+}
@@ -0,0 +1,216 @@
+// a thread pool for work that would block the reactor (file io, DNS over
+// libc, CPU heavy work). A finished job stores its result and wakes the
+// task waiting on the JoinHandle, the waker gets it onto its reactor's
+// run queue from the pool thread
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::PendingWork;
+
+const MAX_THREADS: usize = 64;
+
+// idle threads exit after this long
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static POOL: OnceLock<Pool> = OnceLock::new();
+
+struct Pool {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+struct State {
+    jobs: VecDeque<Job>,
+    threads: usize,
+    idle: usize,
+}
+
+// run f on the blocking pool. The job runs to completion even if the handle
+// is dropped
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let shared = Arc::new(Mutex::new(Shared {
+        result: None,
+        waker: None,
+    }));
+
+    let slot = shared.clone();
+    let pending = PendingWork::new();
+    pool().execute(Box::new(move || {
+        let res = panic::catch_unwind(AssertUnwindSafe(f));
+
+        let mut slot = slot.lock().unwrap_or_else(|err| err.into_inner());
+        slot.result = Some(res);
+        let waker = slot.waker.take();
+        drop(slot);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        // only now, a paused clock mustn't jump before the wakeup is in
+        drop(pending);
+    }));
+
+    JoinHandle {
+        shared,
+        done: false,
+    }
+}
+
+// run a blocking io operation on the pool
+pub(crate) async fn asyncify<F, T>(f: F) -> Result<T, io::Error>
+where
+    F: FnOnce() -> Result<T, io::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_blocking(f).await?
+}
+
+fn pool() -> &'static Pool {
+    POOL.get_or_init(|| Pool {
+        state: Mutex::new(State {
+            jobs: VecDeque::new(),
+            threads: 0,
+            idle: 0,
+        }),
+        cond: Condvar::new(),
+    })
+}
+
+impl Pool {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn execute(&'static self, job: Job) {
+        let mut state = self.lock();
+        state.jobs.push_back(job);
+
+        // an idle thread only leaves idle once it has woken up, so a burst
+        // of jobs can't all count on the same one
+        if state.idle > 0 {
+            self.cond.notify_one();
+        }
+        if state.jobs.len() > state.idle && state.threads < MAX_THREADS {
+            state.threads += 1;
+            let id = state.threads;
+
+            let spawned = thread::Builder::new()
+                .name("fahrenheit-blocking".to_string())
+                .spawn(move || self.work());
+
+            match spawned {
+                Ok(_) => debug!("started blocking thread #{}", id),
+                // the job waits for a running thread to pick it up
+                Err(err) => {
+                    debug!("can't start blocking thread: {}", err);
+                    state.threads -= 1;
+                }
+            }
+        }
+    }
+
+    fn work(&self) {
+        let mut state = self.lock();
+
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                job();
+                state = self.lock();
+                continue;
+            }
+
+            state.idle += 1;
+            let (guard, timeout) = self
+                .cond
+                .wait_timeout(state, KEEP_ALIVE)
+                .unwrap_or_else(|err| err.into_inner());
+            state = guard;
+            state.idle -= 1;
+
+            if timeout.timed_out() && state.jobs.is_empty() {
+                state.threads -= 1;
+                debug!("blocking thread exiting, {} left", state.threads);
+                return;
+            }
+        }
+    }
+}
+
+// resolves to the job's return value, or an error if it panicked
+pub struct JoinHandle<R> {
+    shared: Arc<Mutex<Shared<R>>>,
+    // the result has been handed out
+    done: bool,
+}
+
+// between the handle and the pool thread running the job
+struct Shared<R> {
+    result: Option<thread::Result<R>>,
+    // the task waiting for the result
+    waker: Option<Waker>,
+}
+
+impl<R> Future for JoinHandle<R> {
+    type Output = Result<R, io::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        if self.done {
+            return Poll::Ready(Err(io::Error::other("polled after completion")));
+        }
+
+        let mut shared = self.shared.lock().unwrap_or_else(|err| err.into_inner());
+        let res = match shared.result.take() {
+            Some(res) => res,
+            None => {
+                shared.waker = Some(ctx.waker().clone());
+                return Poll::Pending;
+            }
+        };
+        drop(shared);
+        self.done = true;
+
+        match res {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(payload) => Poll::Ready(Err(io::Error::other(format!(
+                "blocking task panicked: {}",
+                panic_message(&payload)
+            )))),
+        }
+    }
+}
+
+impl<R> fmt::Debug for JoinHandle<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle")
+            .field("done", &self.done)
+            .finish()
+    }
+}
+
+fn panic_message(payload: &Box<dyn Any + Send>) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
+}
@@ -0,0 +1,44 @@
+use std::io;
+
+use crate::codec::{Decoder, Encoder};
+
+// passes bytes through as they come, every read is a frame
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BytesCodec;
+
+impl BytesCodec {
+    pub fn new() -> BytesCodec {
+        BytesCodec
+    }
+}
+
+impl Decoder for BytesCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, io::Error> {
+        if src.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(std::mem::take(src)))
+        }
+    }
+}
+
+impl Encoder<Vec<u8>> for BytesCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Vec<u8>, dst: &mut Vec<u8>) -> Result<(), io::Error> {
+        dst.extend_from_slice(&item);
+        Ok(())
+    }
+}
+
+impl<'a> Encoder<&'a [u8]> for BytesCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: &'a [u8], dst: &mut Vec<u8>) -> Result<(), io::Error> {
+        dst.extend_from_slice(item);
+        Ok(())
+    }
+}
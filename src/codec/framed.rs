@@ -0,0 +1,254 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use futures_sink::Sink;
+
+use crate::codec::{Decoder, Encoder};
+
+// bytes asked for from the transport per read
+const READ_SIZE: usize = 8 * 1024;
+
+// default cap on rd, a peer that never completes a frame can't make it
+// grow any further
+const MAX_READ_BUFFER: usize = 8 * 1024 * 1024;
+
+// default high-water mark of the write buffer
+const BACKPRESSURE_BOUNDARY: usize = 8 * 1024;
+
+// Framed applies a codec to a byte stream: it's a Stream of the frames
+// decoded from what's read and a Sink of frames to encode and write.
+// Frames can span reads, leftover bytes stay buffered for the next one
+pub struct Framed<T, C> {
+    io: T,
+    codec: C,
+    rd: Vec<u8>,
+    // reads land here first. It's zeroed once, rd only grows by the bytes
+    // actually read
+    scratch: Box<[u8]>,
+    max_read_buffer: usize,
+    wr: Vec<u8>,
+    // poll_ready starts writing once wr holds this many bytes
+    backpressure_boundary: usize,
+    // rd may hold another frame, try decoding before reading more
+    is_readable: bool,
+    // the transport hit EOF
+    eof: bool,
+    // EOF or an error ended the stream
+    done: bool,
+}
+
+impl<T, C> Framed<T, C> {
+    pub fn new(io: T, codec: C) -> Framed<T, C> {
+        Framed {
+            io,
+            codec,
+            rd: Vec::new(),
+            scratch: vec![0; READ_SIZE].into_boxed_slice(),
+            max_read_buffer: MAX_READ_BUFFER,
+            wr: Vec::new(),
+            backpressure_boundary: BACKPRESSURE_BOUNDARY,
+            is_readable: false,
+            eof: false,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+
+    // io done through this reference bypasses the buffers
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.io
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    // bytes read but not decoded yet
+    pub fn read_buffer(&self) -> &[u8] {
+        &self.rd
+    }
+
+    // frames encoded but not written yet
+    pub fn write_buffer(&self) -> &[u8] {
+        &self.wr
+    }
+
+    pub fn backpressure_boundary(&self) -> usize {
+        self.backpressure_boundary
+    }
+
+    // how many encoded bytes may pile up before send() waits for the
+    // transport. 0 writes every frame out before accepting the next
+    pub fn set_backpressure_boundary(&mut self, boundary: usize) {
+        self.backpressure_boundary = boundary;
+    }
+
+    pub fn max_read_buffer(&self) -> usize {
+        self.max_read_buffer
+    }
+
+    // how many undecoded bytes may pile up waiting for the rest of a frame.
+    // Past that the stream fails with InvalidData and ends
+    pub fn set_max_read_buffer(&mut self, max: usize) {
+        self.max_read_buffer = max;
+    }
+
+    // buffered bytes in either direction are lost
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+}
+
+// the codec is never pinned
+impl<T: Unpin, C> Unpin for Framed<T, C> {}
+
+impl<T, C> Stream for Framed<T, C>
+where
+    T: AsyncRead + Unpin,
+    C: Decoder,
+{
+    type Item = Result<C::Item, C::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            if this.is_readable {
+                if this.eof {
+                    let frame = this.codec.decode_eof(&mut this.rd);
+                    if let Ok(None) | Err(_) = frame {
+                        this.done = true;
+                    }
+                    return Poll::Ready(frame.transpose());
+                }
+
+                // after a decode error the stream is out of sync, it ends
+                match this.codec.decode(&mut this.rd) {
+                    Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                    Ok(None) => this.is_readable = false,
+                    Err(err) => {
+                        this.done = true;
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+            }
+
+            let room = this.max_read_buffer.saturating_sub(this.rd.len());
+            if room == 0 {
+                this.done = true;
+                let err =
+                    io::Error::new(io::ErrorKind::InvalidData, "frame exceeds max_read_buffer");
+                return Poll::Ready(Some(Err(err.into())));
+            }
+
+            let len = room.min(this.scratch.len());
+            let res = Pin::new(&mut this.io).poll_read(ctx, &mut this.scratch[..len]);
+
+            match res {
+                Poll::Ready(Ok(n)) => {
+                    this.rd.extend_from_slice(&this.scratch[..n]);
+                    if n == 0 {
+                        this.eof = true;
+                    }
+                    this.is_readable = true;
+                }
+                Poll::Ready(Err(err)) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T, C> Framed<T, C>
+where
+    T: AsyncWrite + Unpin,
+{
+    // write from wr until it holds no more than limit bytes
+    fn poll_write_buf(&mut self, ctx: &mut Context, limit: usize) -> Poll<Result<(), io::Error>> {
+        let mut written = 0;
+
+        let res = loop {
+            if self.wr.len() - written <= limit {
+                break Poll::Ready(Ok(()));
+            }
+
+            match Pin::new(&mut self.io).poll_write(ctx, &self.wr[written..]) {
+                Poll::Ready(Ok(0)) => {
+                    break Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write frame to transport",
+                    )))
+                }
+                Poll::Ready(Ok(n)) => written += n,
+                Poll::Ready(Err(err)) => break Poll::Ready(Err(err)),
+                Poll::Pending => break Poll::Pending,
+            }
+        };
+
+        self.wr.drain(..written);
+        res
+    }
+}
+
+impl<T, I, C> Sink<I> for Framed<T, C>
+where
+    T: AsyncWrite + Unpin,
+    C: Encoder<I>,
+{
+    type Error = C::Error;
+
+    // frames are buffered until the write buffer reaches the backpressure
+    // boundary, then it's written out below that before the next is accepted
+    fn poll_ready(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        if this.wr.len() < this.backpressure_boundary {
+            return Poll::Ready(Ok(()));
+        }
+        let limit = this.backpressure_boundary.saturating_sub(1);
+        this.poll_write_buf(ctx, limit).map_err(Into::into)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        this.codec.encode(item, &mut this.wr)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        match this.poll_write_buf(ctx, 0) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.io).poll_flush(ctx).map_err(Into::into),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    // flush, then close the transport's write side
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        match this.poll_write_buf(ctx, 0) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.io).poll_close(ctx).map_err(Into::into),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
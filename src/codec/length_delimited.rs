@@ -0,0 +1,112 @@
+use std::io;
+
+use crate::codec::{Decoder, Encoder};
+
+// length of the frame header, a big endian u32
+const HEADER: usize = 4;
+
+// frames prefixed with their length as a big endian u32. Frames longer
+// than the maximum (8 MiB unless set) are refused in both directions so a
+// peer can't make us buffer arbitrary amounts
+#[derive(Debug, Clone, Copy)]
+pub struct LengthDelimitedCodec {
+    max_frame_length: usize,
+}
+
+impl LengthDelimitedCodec {
+    pub fn new() -> LengthDelimitedCodec {
+        LengthDelimitedCodec {
+            max_frame_length: 8 * 1024 * 1024,
+        }
+    }
+
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    pub fn set_max_frame_length(&mut self, max: usize) {
+        self.max_frame_length = max.min(u32::MAX as usize);
+    }
+
+    // the payload written by f becomes one frame
+    pub(crate) fn encode_with<F>(&self, dst: &mut Vec<u8>, f: F) -> Result<(), io::Error>
+    where
+        F: FnOnce(&mut Vec<u8>) -> Result<(), io::Error>,
+    {
+        let start = dst.len();
+        dst.extend_from_slice(&[0; HEADER]);
+
+        if let Err(err) = f(dst) {
+            dst.truncate(start);
+            return Err(err);
+        }
+
+        let len = dst.len() - start - HEADER;
+        if len > self.max_frame_length {
+            dst.truncate(start);
+            return Err(frame_too_big(len));
+        }
+        dst[start..start + HEADER].copy_from_slice(&(len as u32).to_be_bytes());
+
+        Ok(())
+    }
+}
+
+impl Default for LengthDelimitedCodec {
+    fn default() -> LengthDelimitedCodec {
+        LengthDelimitedCodec::new()
+    }
+}
+
+fn frame_too_big(len: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("frame of {} bytes exceeds max frame length", len),
+    )
+}
+
+impl Decoder for LengthDelimitedCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, io::Error> {
+        if src.len() < HEADER {
+            return Ok(None);
+        }
+
+        let mut header = [0; HEADER];
+        header.copy_from_slice(&src[..HEADER]);
+        let len = u32::from_be_bytes(header) as usize;
+
+        if len > self.max_frame_length {
+            return Err(frame_too_big(len));
+        }
+        if src.len() < HEADER + len {
+            src.reserve(HEADER + len - src.len());
+            return Ok(None);
+        }
+
+        let frame = src[HEADER..HEADER + len].to_vec();
+        src.drain(..HEADER + len);
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder<Vec<u8>> for LengthDelimitedCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Vec<u8>, dst: &mut Vec<u8>) -> Result<(), io::Error> {
+        self.encode(&item[..], dst)
+    }
+}
+
+impl<'a> Encoder<&'a [u8]> for LengthDelimitedCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: &'a [u8], dst: &mut Vec<u8>) -> Result<(), io::Error> {
+        self.encode_with(dst, |dst| {
+            dst.extend_from_slice(item);
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,49 @@
+// codecs turn a byte oriented transport into a stream of frames. A Decoder
+// cuts frames out of a read buffer, an Encoder appends frames to a write
+// buffer, and the framed adapters take care of the actual io
+use std::io;
+
+mod bytes_codec;
+mod framed;
+mod length_delimited;
+#[cfg(any(feature = "serde-json", feature = "serde-bincode"))]
+mod serde_codec;
+mod udp_framed;
+
+pub use self::bytes_codec::BytesCodec;
+pub use self::framed::Framed;
+pub use self::length_delimited::LengthDelimitedCodec;
+#[cfg(feature = "serde-bincode")]
+pub use self::serde_codec::BincodeCodec;
+#[cfg(feature = "serde-json")]
+pub use self::serde_codec::JsonCodec;
+pub use self::udp_framed::UdpFramed;
+
+pub trait Decoder {
+    type Item;
+    // io errors from the transport are reported through the codec's error
+    type Error: From<io::Error>;
+
+    // decode one frame from the front of src, removing the bytes it used.
+    // Ok(None) means src doesn't hold a complete frame yet
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error>;
+
+    // called when the transport has no more data. Leftover bytes that
+    // don't make a whole frame are an error
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => Err(
+                io::Error::new(io::ErrorKind::UnexpectedEof, "bytes remaining on stream").into(),
+            ),
+        }
+    }
+}
+
+pub trait Encoder<Item> {
+    type Error: From<io::Error>;
+
+    // append the encoded frame to dst
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> Result<(), Self::Error>;
+}
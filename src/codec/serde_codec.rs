@@ -0,0 +1,142 @@
+// serde frames on top of LengthDelimitedCodec, every message is one length
+// prefixed frame. JSON needs feature "serde-json", bincode "serde-bincode".
+// The codecs decode into T and encode anything Serialize, so requests and
+// responses can be different types
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::codec::{Decoder, Encoder, LengthDelimitedCodec};
+
+#[cfg(feature = "serde-json")]
+pub struct JsonCodec<T> {
+    frames: LengthDelimitedCodec,
+    _item: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "serde-json")]
+impl<T> JsonCodec<T> {
+    pub fn new() -> JsonCodec<T> {
+        JsonCodec::with_frames(LengthDelimitedCodec::new())
+    }
+
+    // e.g. with a different max frame length
+    pub fn with_frames(frames: LengthDelimitedCodec) -> JsonCodec<T> {
+        JsonCodec {
+            frames,
+            _item: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde-json")]
+impl<T> Default for JsonCodec<T> {
+    fn default() -> JsonCodec<T> {
+        JsonCodec::new()
+    }
+}
+
+#[cfg(feature = "serde-json")]
+impl<T> fmt::Debug for JsonCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JsonCodec")
+            .field("frames", &self.frames)
+            .finish()
+    }
+}
+
+#[cfg(feature = "serde-json")]
+impl<T: DeserializeOwned> Decoder for JsonCodec<T> {
+    type Item = T;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<T>, io::Error> {
+        match self.frames.decode(src)? {
+            Some(frame) => serde_json::from_slice(&frame)
+                .map(Some)
+                .map_err(invalid_data),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "serde-json")]
+impl<T, I: Serialize> Encoder<I> for JsonCodec<T> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: I, dst: &mut Vec<u8>) -> Result<(), io::Error> {
+        self.frames.encode_with(dst, |dst| {
+            serde_json::to_writer(dst, &item).map_err(invalid_data)
+        })
+    }
+}
+
+#[cfg(feature = "serde-bincode")]
+pub struct BincodeCodec<T> {
+    frames: LengthDelimitedCodec,
+    _item: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "serde-bincode")]
+impl<T> BincodeCodec<T> {
+    pub fn new() -> BincodeCodec<T> {
+        BincodeCodec::with_frames(LengthDelimitedCodec::new())
+    }
+
+    pub fn with_frames(frames: LengthDelimitedCodec) -> BincodeCodec<T> {
+        BincodeCodec {
+            frames,
+            _item: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "serde-bincode")]
+impl<T> Default for BincodeCodec<T> {
+    fn default() -> BincodeCodec<T> {
+        BincodeCodec::new()
+    }
+}
+
+#[cfg(feature = "serde-bincode")]
+impl<T> fmt::Debug for BincodeCodec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BincodeCodec")
+            .field("frames", &self.frames)
+            .finish()
+    }
+}
+
+#[cfg(feature = "serde-bincode")]
+impl<T: DeserializeOwned> Decoder for BincodeCodec<T> {
+    type Item = T;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<T>, io::Error> {
+        match self.frames.decode(src)? {
+            Some(frame) => bincode::deserialize(&frame).map(Some).map_err(invalid_data),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(feature = "serde-bincode")]
+impl<T, I: Serialize> Encoder<I> for BincodeCodec<T> {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: I, dst: &mut Vec<u8>) -> Result<(), io::Error> {
+        self.frames.encode_with(dst, |dst| {
+            bincode::serialize_into(dst, &item).map_err(invalid_data)
+        })
+    }
+}
+
+fn invalid_data<E>(err: E) -> io::Error
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
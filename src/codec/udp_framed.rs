@@ -0,0 +1,155 @@
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::codec::{Decoder, Encoder};
+use crate::AsyncUdpSocket;
+
+// largest possible udp payload
+const MAX_DATAGRAM: usize = 64 * 1024;
+
+// UdpFramed applies a codec to datagrams: every frame in a datagram
+// received is decoded, every frame sent goes out as one datagram. Bytes
+// left over at the end of a datagram can't be completed by the next one
+// and are dropped. It's a Stream of (frame, sender) and a Sink of
+// (frame, destination)
+pub struct UdpFramed<C> {
+    socket: AsyncUdpSocket,
+    codec: C,
+    // datagrams are received here, zeroed once
+    scratch: Box<[u8]>,
+    // the datagram being decoded and its sender
+    rd: Vec<u8>,
+    rd_addr: Option<SocketAddr>,
+    wr: Vec<u8>,
+    // destination of the frame in wr
+    out_addr: Option<SocketAddr>,
+}
+
+impl<C> UdpFramed<C> {
+    pub fn new(socket: AsyncUdpSocket, codec: C) -> UdpFramed<C> {
+        UdpFramed {
+            socket,
+            codec,
+            scratch: vec![0; MAX_DATAGRAM].into_boxed_slice(),
+            rd: Vec::new(),
+            rd_addr: None,
+            wr: Vec::new(),
+            out_addr: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &AsyncUdpSocket {
+        &self.socket
+    }
+
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    pub fn into_inner(self) -> AsyncUdpSocket {
+        self.socket
+    }
+}
+
+// nothing in here is self-referential
+impl<C> Unpin for UdpFramed<C> {}
+
+impl<C: Decoder> Stream for UdpFramed<C> {
+    type Item = Result<(C::Item, SocketAddr), C::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(addr) = this.rd_addr {
+                match this.codec.decode(&mut this.rd) {
+                    Ok(Some(frame)) => return Poll::Ready(Some(Ok((frame, addr)))),
+                    Ok(None) => {
+                        if !this.rd.is_empty() {
+                            log::debug!("dropping {} trailing bytes from {}", this.rd.len(), addr);
+                        }
+                    }
+                    Err(err) => {
+                        this.rd.clear();
+                        this.rd_addr = None;
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+                this.rd.clear();
+                this.rd_addr = None;
+            }
+
+            let res = this.socket.poll_recv_from(ctx, &mut this.scratch);
+
+            let (len, addr) = match res {
+                Poll::Ready(Ok(res)) => res,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                Poll::Pending => return Poll::Pending,
+            };
+            this.rd.extend_from_slice(&this.scratch[..len]);
+            this.rd_addr = Some(addr);
+        }
+    }
+}
+
+impl<I, C: Encoder<I>> Sink<(I, SocketAddr)> for UdpFramed<C> {
+    type Error = C::Error;
+
+    // one datagram is buffered at a time
+    fn poll_ready(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        if self.out_addr.is_some() {
+            return self.poll_flush(ctx);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: (I, SocketAddr)) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let (frame, addr) = item;
+
+        this.codec.encode(frame, &mut this.wr)?;
+        this.out_addr = Some(addr);
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+
+        let addr = match this.out_addr {
+            Some(addr) => addr,
+            None => return Poll::Ready(Ok(())),
+        };
+
+        let sent = match this.socket.poll_send_to(ctx, &this.wr, addr) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let len = this.wr.len();
+        this.wr.clear();
+        this.out_addr = None;
+
+        match sent {
+            Ok(sent) if sent == len => Poll::Ready(Ok(())),
+            Ok(_) => Poll::Ready(Err(
+                io::Error::other("failed to write entire datagram").into()
+            )),
+            Err(err) => Poll::Ready(Err(err.into())),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(ctx)
+    }
+}
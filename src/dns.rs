@@ -0,0 +1,673 @@
+// minimal stub resolver: asks a recursive name server for A/AAAA records
+// over udp and follows the CNAME chain in the answer
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::future::{poll_fn, Future};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::Poll;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::blocking::asyncify;
+use crate::time::sleep;
+use crate::AsyncUdpSocket;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_ATTEMPTS: usize = 2;
+// answers bigger than that set the TC bit, we don't retry over tcp
+const MAX_MESSAGE: usize = 512;
+
+const CLASS_IN: u16 = 1;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+}
+
+impl RecordType {
+    fn code(self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Cname => 5,
+            RecordType::Aaaa => 28,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    // anything else, with its type code and raw rdata
+    Other(u16, Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+#[derive(Debug)]
+pub struct Client {
+    servers: Vec<SocketAddr>,
+    timeout: Duration,
+    attempts: usize,
+    // query ids are random to make spoofed answers harder
+    ids: AtomicU64,
+}
+
+impl Client {
+    pub fn new(servers: Vec<SocketAddr>) -> Client {
+        let seed = RandomState::new().build_hasher().finish();
+
+        Client {
+            servers,
+            timeout: DEFAULT_TIMEOUT,
+            attempts: DEFAULT_ATTEMPTS,
+            ids: AtomicU64::new(seed | 1),
+        }
+    }
+
+    // use the name servers from /etc/resolv.conf. The file is read on the
+    // blocking pool, not on the reactor thread
+    pub async fn from_system() -> Result<Client, io::Error> {
+        let conf = asyncify(|| fs::read_to_string("/etc/resolv.conf")).await?;
+        let servers: Vec<SocketAddr> = conf
+            .lines()
+            .filter_map(|line| {
+                let mut words = line.split_whitespace();
+                match (words.next(), words.next()) {
+                    (Some("nameserver"), Some(addr)) => addr.parse::<IpAddr>().ok(),
+                    _ => None,
+                }
+            })
+            .map(|ip| SocketAddr::new(ip, 53))
+            .collect();
+
+        if servers.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no nameserver in /etc/resolv.conf",
+            ));
+        }
+
+        Ok(Client::new(servers))
+    }
+
+    // how long to wait for each server to answer
+    pub fn timeout(mut self, timeout: Duration) -> Client {
+        self.timeout = timeout;
+        self
+    }
+
+    // how many times to go through the server list before giving up
+    pub fn attempts(mut self, attempts: usize) -> Client {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    // all records in the answer section
+    pub async fn query(&self, name: &str, qtype: RecordType) -> Result<Vec<Record>, io::Error> {
+        let mut last_err = io::Error::new(io::ErrorKind::TimedOut, "dns query timed out");
+
+        for attempt in 0..self.attempts {
+            for server in &self.servers {
+                debug!(
+                    "dns query {} {:?} to {} (attempt {})",
+                    name, qtype, server, attempt
+                );
+
+                match self.query_server(*server, name, qtype).await {
+                    Ok(records) => return Ok(records),
+                    // the name doesn't exist, other servers won't tell different
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(err),
+                    Err(err) => last_err = err,
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    // addresses of name, following CNAMEs. Both A and AAAA are asked for,
+    // IPv4 addresses come first
+    pub async fn lookup_ip(&self, name: &str) -> Result<Vec<IpAddr>, io::Error> {
+        if let Ok(ip) = name.parse::<IpAddr>() {
+            return Ok(vec![ip]);
+        }
+
+        let mut addrs = Vec::new();
+        let mut last_err = None;
+
+        for &qtype in &[RecordType::A, RecordType::Aaaa] {
+            match self.query(name, qtype).await {
+                Ok(records) => addrs.extend(resolve_chain(name, &records)),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        match last_err {
+            Some(err) if addrs.is_empty() => Err(err),
+            _ if addrs.is_empty() => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no addresses found",
+            )),
+            _ => Ok(addrs),
+        }
+    }
+
+    async fn query_server(
+        &self,
+        server: SocketAddr,
+        name: &str,
+        qtype: RecordType,
+    ) -> Result<Vec<Record>, io::Error> {
+        let id = self.next_id();
+        let query = encode_query(id, name, qtype)?;
+
+        // a fresh socket (and source port) for every query
+        let local: SocketAddr = match server {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = AsyncUdpSocket::bind(local)?;
+        socket.connect(server)?;
+        socket.send(&query).await?;
+
+        let mut timeout = sleep(self.timeout);
+        let mut buf = [0u8; MAX_MESSAGE];
+
+        loop {
+            let len = poll_fn(|ctx| {
+                if let Poll::Ready(res) = socket.poll_recv(ctx, &mut buf) {
+                    return Poll::Ready(res);
+                }
+
+                match Pin::new(&mut timeout).poll(ctx) {
+                    Poll::Ready(()) => Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "dns query timed out",
+                    ))),
+                    Poll::Pending => Poll::Pending,
+                }
+            })
+            .await?;
+
+            // stray, forged or garbled datagrams are ignored, keep waiting.
+            // An error rcode in the answer to our question ends the query
+            match decode_response(&buf[..len], id, name, qtype) {
+                Ok(Some(records)) => return Ok(records),
+                Ok(None) => debug!("dns: ignoring unrelated response from {}", server),
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    debug!("dns: ignoring malformed response from {}: {}", server, err)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn next_id(&self) -> u16 {
+        // xorshift
+        let mut x = self.ids.load(Ordering::Relaxed);
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.ids.store(x, Ordering::Relaxed);
+
+        x as u16
+    }
+}
+
+// hostname resolution for connect: ip literals, then /etc/hosts, then the
+// name servers from /etc/resolv.conf. Both files are read on the blocking pool
+pub async fn lookup_host(host: &str) -> Result<Vec<IpAddr>, io::Error> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(vec![ip]);
+    }
+
+    if let Ok(hosts) = asyncify(|| fs::read_to_string("/etc/hosts")).await {
+        let addrs = parse_hosts(&hosts, host);
+        if !addrs.is_empty() {
+            return Ok(addrs);
+        }
+    }
+
+    Client::from_system().await?.lookup_ip(host).await
+}
+
+fn parse_hosts(hosts: &str, host: &str) -> Vec<IpAddr> {
+    hosts
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            let ip = words.next()?.parse::<IpAddr>().ok()?;
+            if words.any(|name| name.eq_ignore_ascii_case(host)) {
+                Some(ip)
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// addresses reachable from name through the CNAME records in the answer
+fn resolve_chain(name: &str, records: &[Record]) -> Vec<IpAddr> {
+    let mut target = name.trim_end_matches('.').to_string();
+    let mut addrs = Vec::new();
+
+    // bounded, a looping chain must not hang us
+    for _ in 0..=records.len() {
+        let mut next = None;
+
+        for record in records
+            .iter()
+            .filter(|r| r.name.eq_ignore_ascii_case(&target))
+        {
+            match record.data {
+                RecordData::A(ip) => addrs.push(IpAddr::V4(ip)),
+                RecordData::Aaaa(ip) => addrs.push(IpAddr::V6(ip)),
+                RecordData::Cname(ref alias) => next = Some(alias.clone()),
+                RecordData::Other(..) => {}
+            }
+        }
+
+        match next {
+            Some(alias) if addrs.is_empty() => target = alias,
+            _ => break,
+        }
+    }
+
+    addrs
+}
+
+fn encode_query(id: u16, name: &str, qtype: RecordType) -> Result<Vec<u8>, io::Error> {
+    let mut msg = Vec::with_capacity(MAX_MESSAGE);
+
+    msg.extend_from_slice(&id.to_be_bytes());
+    // standard query, recursion desired
+    msg.extend_from_slice(&0x0100u16.to_be_bytes());
+    // one question, no answers, authority or additional records
+    msg.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    encode_name(name, &mut msg)?;
+    msg.extend_from_slice(&qtype.code().to_be_bytes());
+    msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(msg)
+}
+
+fn encode_name(name: &str, msg: &mut Vec<u8>) -> Result<(), io::Error> {
+    let name = name.trim_end_matches('.');
+    if name.len() > 253 {
+        return Err(invalid_input("name too long"));
+    }
+
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(invalid_input("invalid label in name"));
+        }
+        msg.push(label.len() as u8);
+        msg.extend_from_slice(label.as_bytes());
+    }
+    msg.push(0);
+
+    Ok(())
+}
+
+// Ok(None) if the message isn't the answer to our question
+fn decode_response(
+    msg: &[u8],
+    id: u16,
+    name: &str,
+    qtype: RecordType,
+) -> Result<Option<Vec<Record>>, io::Error> {
+    if msg.len() < 12 || read_u16(msg, 0)? != id {
+        return Ok(None);
+    }
+
+    let flags = read_u16(msg, 2)?;
+    let is_response = flags & 0x8000 != 0;
+    if !is_response {
+        return Ok(None);
+    }
+
+    let qdcount = read_u16(msg, 4)?;
+    let ancount = read_u16(msg, 6)?;
+    if qdcount != 1 {
+        return Ok(None);
+    }
+
+    let (qname, mut pos) = decode_name(msg, 12)?;
+    let qt = read_u16(msg, pos)?;
+    pos += 4;
+    if !qname.eq_ignore_ascii_case(name.trim_end_matches('.')) || qt != qtype.code() {
+        return Ok(None);
+    }
+
+    match (flags & 0x000f) as u8 {
+        0 => {}
+        RCODE_NXDOMAIN => {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such domain"));
+        }
+        RCODE_SERVFAIL => {
+            return Err(io::Error::other("name server failure"));
+        }
+        rcode => {
+            return Err(io::Error::other(format!(
+                "name server returned rcode {}",
+                rcode
+            )));
+        }
+    }
+
+    // a truncated answer still starts with valid records, use what fits
+    let truncated = flags & 0x0200 != 0;
+    let mut records = Vec::with_capacity(ancount as usize);
+    for _ in 0..ancount {
+        let (record, next) = match decode_record(msg, pos) {
+            Ok(res) => res,
+            Err(_) if truncated => break,
+            Err(err) => return Err(err),
+        };
+        pos = next;
+
+        if let Some(record) = record {
+            records.push(record);
+        }
+    }
+
+    Ok(Some(records))
+}
+
+// one resource record at pos, None if it isn't class IN
+fn decode_record(msg: &[u8], pos: usize) -> Result<(Option<Record>, usize), io::Error> {
+    let (owner, mut pos) = decode_name(msg, pos)?;
+
+    let rtype = read_u16(msg, pos)?;
+    let class = read_u16(msg, pos + 2)?;
+    let ttl = u32::from(read_u16(msg, pos + 4)?) << 16 | u32::from(read_u16(msg, pos + 6)?);
+    let rdlen = read_u16(msg, pos + 8)? as usize;
+    pos += 10;
+
+    let rdata = msg
+        .get(pos..pos + rdlen)
+        .ok_or_else(|| invalid_data("record data out of bounds"))?;
+
+    let data = match (rtype, rdlen) {
+        (1, 4) => RecordData::A(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3])),
+        (28, 16) => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(rdata);
+            RecordData::Aaaa(Ipv6Addr::from(octets))
+        }
+        // the alias may be compressed against the whole message
+        (5, _) => RecordData::Cname(decode_name(msg, pos)?.0),
+        _ => RecordData::Other(rtype, rdata.to_vec()),
+    };
+    pos += rdlen;
+
+    if class != CLASS_IN {
+        return Ok((None, pos));
+    }
+
+    let record = Record {
+        name: owner,
+        ttl,
+        data,
+    };
+    Ok((Some(record), pos))
+}
+
+// returns the name (without the trailing dot) and the position right after
+// it in the message
+fn decode_name(msg: &[u8], mut pos: usize) -> Result<(String, usize), io::Error> {
+    let mut name = String::new();
+    let mut end = None;
+    // compression pointers may only point backwards, but be safe
+    let mut jumps = 0;
+
+    loop {
+        let len = *msg
+            .get(pos)
+            .ok_or_else(|| invalid_data("name out of bounds"))? as usize;
+
+        match len & 0xc0 {
+            0x00 if len == 0 => {
+                pos += 1;
+                break;
+            }
+            0x00 => {
+                let label = msg
+                    .get(pos + 1..pos + 1 + len)
+                    .ok_or_else(|| invalid_data("label out of bounds"))?;
+                if !name.is_empty() {
+                    name.push('.');
+                }
+                name.push_str(&String::from_utf8_lossy(label));
+                pos += 1 + len;
+            }
+            0xc0 => {
+                let offset = (read_u16(msg, pos)? & 0x3fff) as usize;
+                if end.is_none() {
+                    end = Some(pos + 2);
+                }
+                jumps += 1;
+                if jumps > 64 {
+                    return Err(invalid_data("compression loop in name"));
+                }
+                pos = offset;
+            }
+            _ => return Err(invalid_data("unsupported label type")),
+        }
+    }
+
+    Ok((name, end.unwrap_or(pos)))
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Result<u16, io::Error> {
+    match msg.get(pos..pos + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(invalid_data("message truncated")),
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn invalid_input(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE: u16 = 0x8180;
+    const TRUNCATED: u16 = 0x8380;
+
+    // a response to our question with flags and ancount patched in, the
+    // answers are appended by the test
+    fn response(id: u16, flags: u16, ancount: u16, name: &str, qtype: RecordType) -> Vec<u8> {
+        let mut msg = encode_query(id, name, qtype).unwrap();
+        msg[2..4].copy_from_slice(&flags.to_be_bytes());
+        msg[6..8].copy_from_slice(&ancount.to_be_bytes());
+        msg
+    }
+
+    fn record(msg: &mut Vec<u8>, owner: &[u8], rtype: u16, rdata: &[u8]) {
+        msg.extend_from_slice(owner);
+        msg.extend_from_slice(&rtype.to_be_bytes());
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+        msg.extend_from_slice(&300u32.to_be_bytes());
+        msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        msg.extend_from_slice(rdata);
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        encode_name(name, &mut buf).unwrap();
+        buf
+    }
+
+    // the question name starts right after the header
+    const QNAME: &[u8] = &[0xc0, 12];
+
+    #[test]
+    fn decode_name_follows_pointers() {
+        let mut msg = vec![0; 12];
+        msg.extend_from_slice(&name("example.com"));
+        msg.extend_from_slice(&[3, b'w', b'w', b'w', 0xc0, 12]);
+
+        assert_eq!(decode_name(&msg, 12).unwrap(), ("example.com".into(), 25));
+        assert_eq!(
+            decode_name(&msg, 25).unwrap(),
+            ("www.example.com".into(), 31)
+        );
+    }
+
+    #[test]
+    fn decode_name_rejects_a_compression_loop() {
+        let mut msg = vec![0; 12];
+        msg.extend_from_slice(&[1, b'a', 0xc0, 12]);
+
+        let err = decode_name(&msg, 12).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decode_name_rejects_labels_out_of_bounds() {
+        let msg = [3, b'w', b'w'];
+        assert!(decode_name(&msg, 0).is_err());
+
+        // a pointer past the end
+        let msg = [0xc0, 40];
+        assert!(decode_name(&msg, 0).is_err());
+
+        // no terminating empty label
+        let msg = [3, b'w', b'w', b'w'];
+        assert!(decode_name(&msg, 0).is_err());
+    }
+
+    #[test]
+    fn decode_record_rejects_rdata_out_of_bounds() {
+        let mut msg = Vec::new();
+        record(&mut msg, &name("example.com"), 1, &[1, 2, 3, 4]);
+        msg.truncate(msg.len() - 2);
+
+        let err = decode_record(&msg, 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn decode_record_skips_other_classes() {
+        let mut msg = Vec::new();
+        record(&mut msg, &name("example.com"), 1, &[1, 2, 3, 4]);
+        // class CH
+        let class = name("example.com").len() + 2;
+        msg[class..class + 2].copy_from_slice(&3u16.to_be_bytes());
+
+        assert_eq!(decode_record(&msg, 0).unwrap(), (None, msg.len()));
+    }
+
+    #[test]
+    fn decode_response_uses_what_fits_in_a_truncated_answer() {
+        let mut msg = response(7, TRUNCATED, 2, "example.com", RecordType::A);
+        record(&mut msg, QNAME, 1, &[1, 2, 3, 4]);
+        record(&mut msg, QNAME, 1, &[5, 6, 7, 8]);
+        msg.truncate(msg.len() - 3);
+
+        let records = decode_response(&msg, 7, "example.com", RecordType::A)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![Record {
+                name: "example.com".into(),
+                ttl: 300,
+                data: RecordData::A(Ipv4Addr::new(1, 2, 3, 4)),
+            }]
+        );
+
+        // the same cut without TC is a broken message
+        msg[2..4].copy_from_slice(&RESPONSE.to_be_bytes());
+        assert!(decode_response(&msg, 7, "example.com", RecordType::A).is_err());
+    }
+
+    #[test]
+    fn decode_response_ignores_answers_to_other_questions() {
+        let mut msg = response(7, RESPONSE, 1, "example.com", RecordType::A);
+        record(&mut msg, QNAME, 1, &[1, 2, 3, 4]);
+
+        let decode = |id, name, qtype| decode_response(&msg, id, name, qtype).unwrap();
+        assert!(decode(8, "example.com", RecordType::A).is_none());
+        assert!(decode(7, "example.org", RecordType::A).is_none());
+        assert!(decode(7, "example.com", RecordType::Aaaa).is_none());
+        // names compare case insensitively, a trailing dot doesn't matter
+        assert!(decode(7, "EXAMPLE.com.", RecordType::A).is_some());
+
+        // a query, not a response
+        let query = encode_query(7, "example.com", RecordType::A).unwrap();
+        assert!(decode_response(&query, 7, "example.com", RecordType::A)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn decode_response_maps_nxdomain_to_not_found() {
+        let flags = RESPONSE | u16::from(RCODE_NXDOMAIN);
+        let msg = response(7, flags, 0, "nope.example", RecordType::A);
+
+        let err = decode_response(&msg, 7, "nope.example", RecordType::A).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn resolve_chain_follows_cnames() {
+        let mut msg = response(7, RESPONSE, 3, "www.example.com", RecordType::A);
+        record(&mut msg, QNAME, 5, &name("web.example.com"));
+        record(
+            &mut msg,
+            &name("web.example.com"),
+            5,
+            &name("cdn.example.net"),
+        );
+        record(&mut msg, &name("cdn.example.net"), 1, &[1, 2, 3, 4]);
+
+        let records = decode_response(&msg, 7, "www.example.com", RecordType::A)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            resolve_chain("www.example.com.", &records),
+            vec![IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))]
+        );
+    }
+
+    #[test]
+    fn resolve_chain_stops_on_a_loop() {
+        let cname = |name: &str, alias: &str| Record {
+            name: name.into(),
+            ttl: 300,
+            data: RecordData::Cname(alias.into()),
+        };
+        let records = [
+            cname("a.example", "b.example"),
+            cname("b.example", "a.example"),
+        ];
+
+        assert!(resolve_chain("a.example", &records).is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+// a snapshot of the tasks on this thread's reactor, for finding out why a
+// server stopped responding: which tasks exist, when they last ran and
+// what they're waiting for
+use std::fmt;
+use std::fmt::Write;
+use std::future::poll_fn;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::panic::Location;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use log::warn;
+
+use crate::signal::{signal, SignalKind};
+use crate::sync::watch;
+use crate::{EventLoop, TaskId, Waiter, REACTOR};
+
+// the task calling this is being polled and isn't part of the dump
+pub fn dump() -> Dump {
+    REACTOR.with(|reactor| reactor.dump())
+}
+
+// log the dump and the reactor's interest maps at warn level every time
+// the process gets SIGUSR1, like a JVM thread dump on SIGQUIT:
+//
+//   kill -USR1 <pid>
+//
+// call from inside run, only this thread's reactor is dumped. The dumps
+// come from a task that lives until the returned guard is dropped, so run
+// doesn't return while the guard is around. SIGUSR1 stops killing the
+// process once this is installed, also after the guard is gone
+pub fn dump_on_sigusr1() -> Result<DumpOnSigusr1, io::Error> {
+    let mut usr1 = signal(SignalKind::user_defined1())?;
+    let (stop, mut stopped) = watch::channel(());
+
+    crate::spawn_named("dump_on_sigusr1", async move {
+        loop {
+            // the guard never sends, changed only fails once it's dropped
+            let signalled = poll_fn(|ctx| match stopped.poll_changed(ctx) {
+                Poll::Ready(_) => Poll::Ready(false),
+                Poll::Pending => usr1.poll_recv(ctx).map(|()| true),
+            })
+            .await;
+            if !signalled {
+                return;
+            }
+
+            let interests = REACTOR.with(|reactor| reactor.interests());
+            warn!("SIGUSR1 runtime dump, {}{}", dump(), interests);
+        }
+    });
+    Ok(DumpOnSigusr1 { _stop: stop })
+}
+
+// ends the dump task when dropped
+#[must_use = "dropping the guard stops the dumps right away"]
+#[derive(Debug)]
+pub struct DumpOnSigusr1 {
+    _stop: watch::Sender<()>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Dump {
+    // pending tasks, oldest first
+    pub tasks: Vec<TaskDump>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskDump {
+    pub id: usize,
+    // set with spawn_named
+    pub name: Option<String>,
+    // where the task was spawned
+    pub location: &'static Location<'static>,
+    pub age: Duration,
+    pub since_last_poll: Duration,
+    pub polls: u64,
+    // woken and waiting in the run queue
+    pub scheduled: bool,
+    // fds and timers registered while the task was polled. Wakers held by
+    // channels and locks aren't known to the reactor
+    pub waiting_on: Vec<WaitingOn>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitingOn {
+    Read(RawFd),
+    Write(RawFd),
+    Timer(Instant),
+}
+
+impl EventLoop {
+    pub(crate) fn dump(&self) -> Dump {
+        let now = Instant::now();
+        let scheduled: Vec<TaskId> = self.run_queue.borrow().iter().map(|w| w.index).collect();
+
+        let mut tasks: Vec<TaskDump> = self
+            .wait_queue
+            .borrow()
+            .iter()
+            .map(|(&id, task)| TaskDump {
+                id,
+                name: task.name.clone(),
+                location: task.location,
+                age: now - task.spawned,
+                since_last_poll: now - task.last_poll,
+                polls: task.polls,
+                scheduled: scheduled.contains(&id),
+                waiting_on: Vec::new(),
+            })
+            .collect();
+
+        let mut waiting_on = |task: Option<TaskId>, what: WaitingOn| {
+            if let Some(t) = tasks.iter_mut().find(|t| Some(t.id) == task) {
+                t.waiting_on.push(what);
+            }
+        };
+        for (&fd, waiters) in self.read.borrow().iter() {
+            for waiter in waiters {
+                waiting_on(waiter.task, WaitingOn::Read(fd));
+            }
+        }
+        for (&fd, waiters) in self.write.borrow().iter() {
+            for waiter in waiters {
+                waiting_on(waiter.task, WaitingOn::Write(fd));
+            }
+        }
+        for (&(deadline, _), waiter) in self.timers.borrow().iter() {
+            waiting_on(waiter.task, WaitingOn::Timer(deadline));
+        }
+
+        Dump { tasks }
+    }
+
+    // every registered fd and timer with the tasks whose wakers it holds,
+    // including wakers the task dump can't attribute
+    fn interests(&self) -> String {
+        let mut out = String::new();
+        let tasks = |waiters: &[Waiter]| {
+            waiters
+                .iter()
+                .map(|w| match w.task {
+                    Some(id) => format!("task#{}", id),
+                    None => "?".to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        let read = self.read.borrow();
+        let write = self.write.borrow();
+        let timers = self.timers.borrow();
+        let _ = writeln!(out, "{} read, {} write interests", read.len(), write.len());
+        for (fd, waiters) in read.iter() {
+            let _ = writeln!(out, "  read fd#{}: {}", fd, tasks(waiters));
+        }
+        for (fd, waiters) in write.iter() {
+            let _ = writeln!(out, "  write fd#{}: {}", fd, tasks(waiters));
+        }
+
+        let _ = write!(out, "{} timers", timers.len());
+        if let Some((&(deadline, _), _)) = timers.iter().next() {
+            let _ = write!(
+                out,
+                ", next in {:?}",
+                deadline.saturating_duration_since(Instant::now())
+            );
+        }
+        out
+    }
+}
+
+// one line per task
+impl fmt::Display for Dump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} pending tasks", self.tasks.len())?;
+
+        for task in &self.tasks {
+            write!(f, "  task#{}", task.id)?;
+            if let Some(name) = &task.name {
+                write!(f, " {:?}", name)?;
+            }
+            write!(
+                f,
+                " at {}, age {:?}, {} polls, last {:?} ago",
+                task.location, task.age, task.polls, task.since_last_poll
+            )?;
+
+            if task.scheduled {
+                write!(f, ", scheduled")?;
+            }
+            if task.waiting_on.is_empty() {
+                // a channel, lock or similar holds its waker
+                if !task.scheduled {
+                    write!(f, ", no fd or timer interests")?;
+                }
+            } else {
+                write!(f, ", waiting on")?;
+                for what in &task.waiting_on {
+                    match what {
+                        WaitingOn::Read(fd) => write!(f, " read fd#{}", fd)?,
+                        WaitingOn::Write(fd) => write!(f, " write fd#{}", fd)?,
+                        WaitingOn::Timer(deadline) => write!(
+                            f,
+                            " timer in {:?}",
+                            deadline.saturating_duration_since(Instant::now())
+                        )?,
+                    }
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+// eventfd(2): a kernel counter behind a descriptor. Writes add to it from
+// any thread (or signal handler), reads wait for it to become non-zero.
+// Wakers only work on the reactor's own thread, so this is how other
+// threads get the attention of a task
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::task::{Context, Poll};
+
+use libc::c_void;
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::sys;
+use crate::REACTOR;
+
+#[derive(Debug)]
+pub struct AsyncEventFd(OwnedFd);
+
+impl AsyncEventFd {
+    // read() returns the whole counter and resets it to zero
+    pub fn new() -> Result<AsyncEventFd, io::Error> {
+        AsyncEventFd::with_flags(0, 0)
+    }
+
+    // EFD_SEMAPHORE: read() takes one off the counter and returns 1
+    pub fn semaphore(initial: u32) -> Result<AsyncEventFd, io::Error> {
+        AsyncEventFd::with_flags(initial, libc::EFD_SEMAPHORE)
+    }
+
+    fn with_flags(initial: u32, flags: libc::c_int) -> Result<AsyncEventFd, io::Error> {
+        let flags = flags | libc::EFD_NONBLOCK | libc::EFD_CLOEXEC;
+
+        let fd = unsafe { libc::eventfd(initial, flags) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(AsyncEventFd(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+
+    // a second handle on the same counter, e.g. to move to another thread
+    pub fn try_clone(&self) -> Result<AsyncEventFd, io::Error> {
+        Ok(AsyncEventFd(self.0.try_clone()?))
+    }
+
+    // add one to the counter, doesn't need the reactor
+    pub fn notify(&self) -> Result<(), io::Error> {
+        self.try_write(1)
+    }
+
+    // add n to the counter. Fails with WouldBlock if the counter would
+    // overflow, i.e. nobody has been reading for a very long time
+    pub fn try_write(&self, n: u64) -> Result<(), io::Error> {
+        let rv = unsafe {
+            libc::write(
+                self.0.as_raw_fd(),
+                &n as *const u64 as *const c_void,
+                mem::size_of::<u64>(),
+            )
+        };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub async fn write(&self, n: u64) -> Result<(), io::Error> {
+        poll_fn(|ctx| self.poll_write(ctx, n)).await
+    }
+
+    pub fn poll_write(&self, ctx: &mut Context, n: u64) -> Poll<Result<(), io::Error>> {
+        debug!("poll_write() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            self.try_write(n)
+        })
+    }
+
+    // wait until the counter is non-zero and consume it
+    pub async fn read(&self) -> Result<u64, io::Error> {
+        poll_fn(|ctx| self.poll_read(ctx)).await
+    }
+
+    pub fn poll_read(&self, ctx: &mut Context) -> Poll<Result<u64, io::Error>> {
+        debug!("poll_read() called");
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            self.try_read()
+        })
+    }
+
+    // WouldBlock if the counter is zero
+    pub fn try_read(&self) -> Result<u64, io::Error> {
+        let mut n: u64 = 0;
+
+        let rv = unsafe {
+            libc::read(
+                self.0.as_raw_fd(),
+                &mut n as *mut u64 as *mut c_void,
+                mem::size_of::<u64>(),
+            )
+        };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(n)
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| interest::poll_ready(self.0.as_raw_fd(), interest, ctx)).await
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncEventFd {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncEventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncEventFd {
+    fn into_raw_fd(self) -> RawFd {
+        self.deregister();
+
+        unsafe { interest::take_io(self, |this| &this.0) }.into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncEventFd {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncEventFd {
+        AsyncEventFd(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncEventFd {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncEventFd, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(AsyncEventFd(fd))
+    }
+}
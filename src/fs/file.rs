@@ -0,0 +1,479 @@
+use std::fmt;
+use std::fs;
+use std::future::{poll_fn, Future};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncSeek, AsyncWrite};
+use log::debug;
+
+use crate::blocking::{asyncify, spawn_blocking, JoinHandle};
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+use crate::uring;
+
+// most bytes moved by one job
+const MAX_BUF: usize = 64 * 1024;
+
+// a file whose reads, writes and seeks run on the blocking pool (reads and
+// writes go through io_uring instead with the io-uring feature). Reads fetch
+// up to MAX_BUF bytes ahead, writes return as soon as the data is copied
+// and finish in the background; their errors come out of the next write or
+// flush. Call flush before dropping a written file to see them
+#[derive(Debug)]
+pub struct File {
+    std: Arc<fs::File>,
+    state: State,
+    last_write_err: Option<io::Error>,
+}
+
+#[derive(Debug)]
+enum State {
+    Idle(Option<Buf>),
+    Busy(Job),
+}
+
+// a read, write or seek in flight
+enum Job {
+    Pool(JoinHandle<(Operation, Buf)>),
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    Uring(Pin<Box<dyn Future<Output = (Operation, Buf)> + Send>>),
+}
+
+impl Future for Job {
+    type Output = Result<(Operation, Buf), io::Error>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Job::Pool(handle) => Pin::new(handle).poll(ctx),
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            Job::Uring(op) => op.as_mut().poll(ctx).map(Ok),
+        }
+    }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Job::Pool(handle) => f.debug_tuple("Pool").field(handle).finish(),
+            #[cfg(all(target_os = "linux", feature = "io-uring"))]
+            Job::Uring(_) => f.write_str("Uring"),
+        }
+    }
+}
+
+fn read_job(std: Arc<fs::File>, mut buf: Buf, len: usize) -> Job {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    if let Some(ring) = uring::ring() {
+        return Job::Uring(Box::pin(async move {
+            let (res, data) = ring.read(std.as_raw_fd(), mem::take(&mut buf.data), len).await;
+            buf.data = data;
+            buf.data.truncate(*res.as_ref().unwrap_or(&0));
+            buf.pos = 0;
+            (Operation::Read(res), buf)
+        }));
+    }
+
+    Job::Pool(spawn_blocking(move || {
+        let res = buf.read_from(&std, len);
+        (Operation::Read(res), buf)
+    }))
+}
+
+// seek back by ahead (the discarded read-ahead), then write all of buf
+fn write_job(std: Arc<fs::File>, mut buf: Buf, ahead: i64) -> Job {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    if let Some(ring) = uring::ring() {
+        return Job::Uring(Box::pin(async move {
+            // lseek only moves the position, it doesn't wait for the disk
+            if ahead != 0 {
+                if let Err(err) = (&*std).seek(SeekFrom::Current(ahead)) {
+                    buf.data.clear();
+                    return (Operation::Write(Err(err)), buf);
+                }
+            }
+
+            let mut data = mem::take(&mut buf.data);
+            let mut written = 0;
+            let res = loop {
+                if written == data.len() {
+                    break Ok(());
+                }
+
+                let (res, returned) = ring.write(std.as_raw_fd(), data, written).await;
+                data = returned;
+                match res {
+                    Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                    Ok(n) => written += n,
+                    Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                    Err(err) => break Err(err),
+                }
+            };
+
+            data.clear();
+            buf.data = data;
+            buf.pos = 0;
+            (Operation::Write(res), buf)
+        }));
+    }
+
+    Job::Pool(spawn_blocking(move || {
+        let res = if ahead != 0 {
+            (&*std).seek(SeekFrom::Current(ahead)).map(|_| ())
+        } else {
+            Ok(())
+        };
+        let res = res.and_then(|_| buf.write_to(&std));
+        (Operation::Write(res), buf)
+    }))
+}
+
+#[derive(Debug)]
+enum Operation {
+    Read(Result<usize, io::Error>),
+    Write(Result<(), io::Error>),
+    Seek(Result<u64, io::Error>),
+}
+
+// data travelling between the task and the pool. After a read, data[pos..]
+// is what hasn't been handed out yet; the os file position is that far
+// ahead of the one the user sees
+#[derive(Debug, Default)]
+struct Buf {
+    data: Vec<u8>,
+    pos: usize,
+}
+
+impl Buf {
+    fn is_empty(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    fn copy_to(&mut self, dst: &mut [u8]) -> usize {
+        let n = dst.len().min(self.data.len() - self.pos);
+        dst[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+        self.pos += n;
+        n
+    }
+
+    fn copy_from(&mut self, src: &[u8]) -> usize {
+        let n = src.len().min(MAX_BUF);
+        self.data.clear();
+        self.data.extend_from_slice(&src[..n]);
+        self.pos = 0;
+        n
+    }
+
+    fn read_from(&mut self, file: &fs::File, len: usize) -> Result<usize, io::Error> {
+        self.data.resize(len, 0);
+        self.pos = 0;
+
+        let res = loop {
+            match (&*file).read(&mut self.data) {
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                res => break res,
+            }
+        };
+        self.data.truncate(*res.as_ref().unwrap_or(&0));
+        res
+    }
+
+    fn write_to(&mut self, file: &fs::File) -> Result<(), io::Error> {
+        let res = (&*file).write_all(&self.data);
+        self.data.clear();
+        self.pos = 0;
+        res
+    }
+
+    // drop the read-ahead, returns how far to seek back to undo it
+    fn discard_read(&mut self) -> i64 {
+        let ahead = self.data.len() - self.pos;
+        self.data.clear();
+        self.pos = 0;
+        -(ahead as i64)
+    }
+}
+
+impl File {
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<File, io::Error> {
+        let path = path.as_ref().to_owned();
+        let std = asyncify(move || fs::File::open(path)).await?;
+        Ok(File::from_std(std))
+    }
+
+    // create or truncate path for writing
+    pub async fn create<P: AsRef<Path>>(path: P) -> Result<File, io::Error> {
+        let path = path.as_ref().to_owned();
+        let std = asyncify(move || fs::File::create(path)).await?;
+        Ok(File::from_std(std))
+    }
+
+    // for other modes use std::fs::OpenOptions inside spawn_blocking
+    pub fn from_std(std: fs::File) -> File {
+        File {
+            std: Arc::new(std),
+            state: State::Idle(Some(Buf::default())),
+            last_write_err: None,
+        }
+    }
+
+    pub async fn metadata(&mut self) -> Result<fs::Metadata, io::Error> {
+        self.flush().await?;
+        let std = self.std.clone();
+        asyncify(move || std.metadata()).await
+    }
+
+    // truncate or extend, the position is left alone
+    pub async fn set_len(&mut self, size: u64) -> Result<(), io::Error> {
+        self.flush().await?;
+
+        let mut buf = self.take_buf();
+        let ahead = buf.discard_read();
+        let std = self.std.clone();
+
+        let handle = spawn_blocking(move || {
+            let res = if ahead != 0 {
+                (&*std).seek(SeekFrom::Current(ahead)).map(|_| ())
+            } else {
+                Ok(())
+            };
+            (Operation::Write(res.and_then(|_| std.set_len(size))), buf)
+        });
+        self.state = State::Busy(Job::Pool(handle));
+
+        self.flush().await
+    }
+
+    pub async fn sync_all(&mut self) -> Result<(), io::Error> {
+        self.flush().await?;
+        let std = self.std.clone();
+        asyncify(move || std.sync_all()).await
+    }
+
+    pub async fn sync_data(&mut self) -> Result<(), io::Error> {
+        self.flush().await?;
+        let std = self.std.clone();
+        asyncify(move || std.sync_data()).await
+    }
+
+    // flock(2) locks belong to the open file, not the process. They're
+    // released by unlock() or when the last handle on the file is closed.
+    // Dropping the future doesn't cancel the wait, the lock is taken (and
+    // kept) once the other holder lets go
+    pub async fn lock_exclusive(&mut self) -> Result<(), io::Error> {
+        let std = self.std.clone();
+        asyncify(move || flock(&std, libc::LOCK_EX)).await
+    }
+
+    pub async fn lock_shared(&mut self) -> Result<(), io::Error> {
+        let std = self.std.clone();
+        asyncify(move || flock(&std, libc::LOCK_SH)).await
+    }
+
+    // take an exclusive lock without waiting, Ok(false) if somebody else
+    // holds one
+    pub fn try_lock(&self) -> Result<bool, io::Error> {
+        match flock(&self.std, libc::LOCK_EX | libc::LOCK_NB) {
+            Ok(()) => Ok(true),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn unlock(&self) -> Result<(), io::Error> {
+        flock(&self.std, libc::LOCK_UN)
+    }
+
+    // wait for the background write, if any
+    pub async fn flush(&mut self) -> Result<(), io::Error> {
+        poll_fn(|ctx| Pin::new(&mut *self).poll_flush(ctx)).await
+    }
+
+    // only call when idle
+    fn take_buf(&mut self) -> Buf {
+        match self.state {
+            State::Idle(ref mut buf) => buf.take().unwrap_or_default(),
+            State::Busy(_) => unreachable!("file is busy"),
+        }
+    }
+
+    // wait for the job in flight. A join error leaves the file idle with
+    // an empty buffer
+    fn poll_complete(&mut self, ctx: &mut Context) -> Poll<Result<Operation, io::Error>> {
+        let job = match self.state {
+            State::Busy(ref mut job) => job,
+            State::Idle(_) => unreachable!("file is idle"),
+        };
+
+        let res = match Pin::new(job).poll(ctx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        match res {
+            Ok((op, buf)) => {
+                self.state = State::Idle(Some(buf));
+                Poll::Ready(Ok(op))
+            }
+            Err(err) => {
+                self.state = State::Idle(Some(Buf::default()));
+                Poll::Ready(Err(err))
+            }
+        }
+    }
+}
+
+fn flock(file: &fs::File, operation: libc::c_int) -> Result<(), io::Error> {
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(());
+        }
+
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+impl AsyncRead for File {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        dst: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read() called");
+        let this = self.get_mut();
+
+        loop {
+            if let State::Idle(_) = this.state {
+                let mut buf = this.take_buf();
+                if !buf.is_empty() {
+                    let n = buf.copy_to(dst);
+                    this.state = State::Idle(Some(buf));
+                    return Poll::Ready(Ok(n));
+                }
+
+                let len = dst.len().min(MAX_BUF);
+                this.state = State::Busy(read_job(this.std.clone(), buf, len));
+            }
+
+            let op = match this.poll_complete(ctx) {
+                Poll::Ready(res) => res?,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match op {
+                Operation::Read(Ok(_)) => {
+                    let mut buf = this.take_buf();
+                    let n = buf.copy_to(dst);
+                    this.state = State::Idle(Some(buf));
+                    return Poll::Ready(Ok(n));
+                }
+                Operation::Read(Err(err)) => return Poll::Ready(Err(err)),
+                Operation::Write(Err(err)) => this.last_write_err = Some(err),
+                Operation::Write(Ok(())) | Operation::Seek(_) => {}
+            }
+        }
+    }
+}
+
+impl AsyncWrite for File {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        src: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write() called");
+        let this = self.get_mut();
+
+        loop {
+            if let Some(err) = this.last_write_err.take() {
+                return Poll::Ready(Err(err));
+            }
+
+            if let State::Idle(_) = this.state {
+                let mut buf = this.take_buf();
+                // writing goes where the user thinks the position is
+                let ahead = buf.discard_read();
+                let n = buf.copy_from(src);
+
+                this.state = State::Busy(write_job(this.std.clone(), buf, ahead));
+                return Poll::Ready(Ok(n));
+            }
+
+            match this.poll_complete(ctx) {
+                Poll::Ready(Ok(Operation::Write(Err(err)))) => return Poll::Ready(Err(err)),
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+
+        if let Some(err) = this.last_write_err.take() {
+            return Poll::Ready(Err(err));
+        }
+
+        if let State::Idle(_) = this.state {
+            return Poll::Ready(Ok(()));
+        }
+
+        match this.poll_complete(ctx) {
+            Poll::Ready(Ok(Operation::Write(res))) => Poll::Ready(res),
+            Poll::Ready(Ok(_)) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        self.poll_flush(ctx)
+    }
+}
+
+impl AsyncSeek for File {
+    fn poll_seek(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        pos: SeekFrom,
+    ) -> Poll<Result<u64, io::Error>> {
+        debug!("poll_seek() called");
+        let this = self.get_mut();
+
+        loop {
+            if let State::Idle(_) = this.state {
+                let mut buf = this.take_buf();
+                let ahead = buf.discard_read();
+                let pos = match pos {
+                    SeekFrom::Current(n) => SeekFrom::Current(n + ahead),
+                    pos => pos,
+                };
+
+                let std = this.std.clone();
+                this.state = State::Busy(Job::Pool(spawn_blocking(move || {
+                    (Operation::Seek((&*std).seek(pos)), buf)
+                })));
+            }
+
+            let op = match this.poll_complete(ctx) {
+                Poll::Ready(res) => res?,
+                Poll::Pending => return Poll::Pending,
+            };
+
+            match op {
+                Operation::Seek(res) => return Poll::Ready(res),
+                Operation::Write(Err(err)) => this.last_write_err = Some(err),
+                Operation::Write(Ok(())) | Operation::Read(_) => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+// filesystem access. Regular files are always "ready" as far as select is
+// concerned, so the actual calls run on the blocking pool (see
+// spawn_blocking) and the reactor thread only waits for the results
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::blocking::asyncify;
+
+mod file;
+mod read_dir;
+
+pub use self::file::File;
+pub use self::read_dir::{read_dir, walk_dir, DirEntry, ReadDir, WalkDir};
+
+pub async fn read<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, io::Error> {
+    let path = path.as_ref().to_owned();
+    asyncify(move || fs::read(path)).await
+}
+
+pub async fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String, io::Error> {
+    let path = path.as_ref().to_owned();
+    asyncify(move || fs::read_to_string(path)).await
+}
+
+// create or truncate path and write contents to it
+pub async fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<(), io::Error> {
+    let path = path.as_ref().to_owned();
+    let contents = contents.as_ref().to_owned();
+    asyncify(move || fs::write(path, contents)).await
+}
+
+// copy the contents and permissions of from to to, returns the bytes copied
+pub async fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<u64, io::Error> {
+    let from = from.as_ref().to_owned();
+    let to = to.as_ref().to_owned();
+    asyncify(move || fs::copy(from, to)).await
+}
+
+pub async fn remove_file<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    let path = path.as_ref().to_owned();
+    asyncify(move || fs::remove_file(path)).await
+}
+
+pub async fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<(), io::Error> {
+    let path = path.as_ref().to_owned();
+    asyncify(move || fs::create_dir_all(path)).await
+}
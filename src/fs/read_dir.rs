@@ -0,0 +1,228 @@
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::blocking::{asyncify, spawn_blocking, JoinHandle};
+
+// entries fetched per trip to the blocking pool
+const BATCH: usize = 32;
+
+// list the entries of a directory, without "." and ".."
+pub async fn read_dir<P: AsRef<Path>>(path: P) -> Result<ReadDir, io::Error> {
+    let path = path.as_ref().to_owned();
+    let std = asyncify(move || fs::read_dir(path)).await?;
+
+    Ok(ReadDir::new(Batch {
+        dir: Dir::Open(std),
+        entries: VecDeque::new(),
+    }))
+}
+
+// every entry below path, depth first, parents before their children.
+// Symlinks are reported but not followed. An unreadable subdirectory
+// yields its error and the walk goes on
+pub fn walk_dir<P: AsRef<Path>>(path: P) -> WalkDir {
+    WalkDir {
+        stack: vec![ReadDir::new(Batch {
+            dir: Dir::Unopened(path.as_ref().to_owned()),
+            entries: VecDeque::new(),
+        })],
+    }
+}
+
+#[derive(Debug)]
+pub struct DirEntry {
+    std: Arc<fs::DirEntry>,
+    // usually comes for free with the entry, None if it needed a stat
+    // that failed
+    file_type: Option<fs::FileType>,
+}
+
+impl DirEntry {
+    pub fn path(&self) -> PathBuf {
+        self.std.path()
+    }
+
+    pub fn file_name(&self) -> OsString {
+        self.std.file_name()
+    }
+
+    // type of the entry itself, a symlink isn't followed
+    pub async fn file_type(&self) -> Result<fs::FileType, io::Error> {
+        if let Some(file_type) = self.file_type {
+            return Ok(file_type);
+        }
+
+        let std = self.std.clone();
+        asyncify(move || std.file_type()).await
+    }
+
+    // like fs::symlink_metadata, a symlink isn't followed
+    pub async fn metadata(&self) -> Result<fs::Metadata, io::Error> {
+        let std = self.std.clone();
+        asyncify(move || std.metadata()).await
+    }
+}
+
+#[derive(Debug)]
+enum Dir {
+    Unopened(PathBuf),
+    Open(fs::ReadDir),
+    Done,
+}
+
+#[derive(Debug)]
+struct Batch {
+    dir: Dir,
+    entries: VecDeque<Result<DirEntry, io::Error>>,
+}
+
+impl Batch {
+    // runs on the blocking pool
+    fn fill(&mut self) {
+        if let Dir::Unopened(ref path) = self.dir {
+            match fs::read_dir(path) {
+                Ok(std) => self.dir = Dir::Open(std),
+                Err(err) => {
+                    self.dir = Dir::Done;
+                    self.entries.push_back(Err(err));
+                    return;
+                }
+            }
+        }
+
+        let std = match self.dir {
+            Dir::Open(ref mut std) => std,
+            _ => return,
+        };
+
+        for _ in 0..BATCH {
+            match std.next() {
+                Some(Ok(entry)) => self.entries.push_back(Ok(DirEntry {
+                    file_type: entry.file_type().ok(),
+                    std: Arc::new(entry),
+                })),
+                Some(Err(err)) => self.entries.push_back(Err(err)),
+                None => {
+                    self.dir = Dir::Done;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Idle(Option<Batch>),
+    Busy(JoinHandle<Batch>),
+}
+
+// a Stream of entries, fetched from the blocking pool in batches
+#[derive(Debug)]
+pub struct ReadDir {
+    state: State,
+}
+
+impl ReadDir {
+    fn new(batch: Batch) -> ReadDir {
+        ReadDir {
+            state: State::Idle(Some(batch)),
+        }
+    }
+
+    // Ok(None) once all entries have been returned
+    pub async fn next_entry(&mut self) -> Result<Option<DirEntry>, io::Error> {
+        poll_fn(|ctx| self.poll_next_entry(ctx).map(|entry| entry.transpose())).await
+    }
+
+    fn poll_next_entry(&mut self, ctx: &mut Context) -> Poll<Option<Result<DirEntry, io::Error>>> {
+        loop {
+            match self.state {
+                State::Idle(ref mut batch) => {
+                    let mut batch = match batch.take() {
+                        Some(batch) => batch,
+                        None => return Poll::Ready(None),
+                    };
+
+                    if let Some(entry) = batch.entries.pop_front() {
+                        self.state = State::Idle(Some(batch));
+                        return Poll::Ready(Some(entry));
+                    }
+
+                    if let Dir::Done = batch.dir {
+                        return Poll::Ready(None);
+                    }
+
+                    self.state = State::Busy(spawn_blocking(move || {
+                        batch.fill();
+                        batch
+                    }));
+                }
+                State::Busy(ref mut handle) => match Pin::new(handle).poll(ctx) {
+                    Poll::Ready(Ok(batch)) => self.state = State::Idle(Some(batch)),
+                    // the job panicked, the directory is gone with it
+                    Poll::Ready(Err(err)) => {
+                        self.state = State::Idle(None);
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+            }
+        }
+    }
+}
+
+impl Stream for ReadDir {
+    type Item = Result<DirEntry, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_entry(ctx)
+    }
+}
+
+// see walk_dir(). Keeps one ReadDir per directory level
+#[derive(Debug)]
+pub struct WalkDir {
+    stack: Vec<ReadDir>,
+}
+
+impl Stream for WalkDir {
+    type Item = Result<DirEntry, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            let dir = match this.stack.last_mut() {
+                Some(dir) => dir,
+                None => return Poll::Ready(None),
+            };
+
+            match dir.poll_next_entry(ctx) {
+                Poll::Ready(Some(Ok(entry))) => {
+                    if entry.file_type.is_some_and(|ty| ty.is_dir()) {
+                        this.stack.push(ReadDir::new(Batch {
+                            dir: Dir::Unopened(entry.path()),
+                            entries: VecDeque::new(),
+                        }));
+                    }
+                    return Poll::Ready(Some(Ok(entry)));
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Some(Err(err))),
+                Poll::Ready(None) => {
+                    this.stack.pop();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+// hyper 1.x on top of the reactor: pass FahrenheitExecutor and
+// FahrenheitTimer to hyper's client/server builders and wrap streams in
+// HyperIo. Spawned connection tasks run on the calling thread's reactor,
+// so builders must be used inside fahrenheit::run
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use hyper::rt::{Executor, Read, ReadBufCursor, Sleep as HyperSleep, Timer, Write};
+
+use crate::io::ReadBuf;
+use crate::time::{self, sleep, sleep_until, Sleep};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FahrenheitExecutor;
+
+impl<F> Executor<F> for FahrenheitExecutor
+where
+    F: Future + Send + 'static,
+{
+    fn execute(&self, fut: F) {
+        crate::spawn(async move {
+            fut.await;
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FahrenheitTimer;
+
+impl Timer for FahrenheitTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn HyperSleep>> {
+        Box::pin(sleep(duration))
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn HyperSleep>> {
+        Box::pin(sleep_until(deadline))
+    }
+
+    fn now(&self) -> Instant {
+        time::now()
+    }
+
+    // move our own timers instead of allocating new ones
+    fn reset(&self, sleep: &mut Pin<Box<dyn HyperSleep>>, new_deadline: Instant) {
+        match sleep.as_mut().downcast_mut_pin::<Sleep>() {
+            Some(sleep) => sleep.get_mut().reset(new_deadline),
+            None => *sleep = self.sleep_until(new_deadline),
+        }
+    }
+}
+
+impl HyperSleep for Sleep {}
+
+// hyper's Read/Write for anything with the futures io traits, e.g.
+// AsyncTcpStream or AsyncUnixStream
+#[derive(Debug)]
+pub struct HyperIo<T> {
+    inner: T,
+}
+
+impl<T> HyperIo<T> {
+    pub fn new(inner: T) -> HyperIo<T> {
+        HyperIo { inner }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsyncRead + Unpin> Read for HyperIo<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        mut buf: ReadBufCursor,
+    ) -> Poll<Result<(), io::Error>> {
+        // AsyncRead wants initialized memory
+        let mut rb = ReadBuf::uninit(unsafe { buf.as_mut() });
+        let n = match Pin::new(&mut self.get_mut().inner).poll_read(ctx, rb.initialize_unfilled()) {
+            Poll::Ready(Ok(n)) => n,
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+
+        unsafe { buf.advance(n) };
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> Write for HyperIo<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(ctx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &[io::IoSlice],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_write_vectored(ctx, bufs)
+    }
+
+    // AsyncWrite has no way to tell, assume the inner stream does
+    // something useful with vectored writes
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(ctx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(ctx)
+    }
+}
@@ -0,0 +1,124 @@
+// ICMP echo (ping) on top of AsyncRawSocket, for health checks
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use crate::time::sleep;
+use crate::AsyncRawSocket;
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+
+const PAYLOAD: &[u8] = b"fahrenheit ping";
+
+// sequence numbers tell our concurrent pings apart
+static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+// send one echo request to addr and wait for the reply, returns the round
+// trip time. Needs CAP_NET_RAW, fails with TimedOut if nothing comes back
+pub async fn ping(addr: IpAddr, timeout: Duration) -> Result<Duration, io::Error> {
+    let (socket, request_type, reply_type) = match addr {
+        IpAddr::V4(_) => (
+            AsyncRawSocket::new(libc::AF_INET, libc::IPPROTO_ICMP)?,
+            ECHO_REQUEST_V4,
+            ECHO_REPLY_V4,
+        ),
+        IpAddr::V6(_) => (
+            AsyncRawSocket::new(libc::AF_INET6, libc::IPPROTO_ICMPV6)?,
+            ECHO_REQUEST_V6,
+            ECHO_REPLY_V6,
+        ),
+    };
+
+    let id = std::process::id() as u16;
+    let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let request = echo_request(request_type, id, seq);
+
+    let start = Instant::now();
+    socket.send_to(&request, SocketAddr::new(addr, 0)).await?;
+
+    let mut deadline = sleep(timeout);
+    let mut buf = [0u8; 1500];
+
+    loop {
+        let (len, from) = poll_fn(|ctx| {
+            if let Poll::Ready(res) = socket.poll_recv_from(ctx, &mut buf) {
+                return Poll::Ready(res);
+            }
+
+            match Pin::new(&mut deadline).poll(ctx) {
+                Poll::Ready(()) => Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no echo reply",
+                ))),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await?;
+
+        // a raw socket sees every icmp packet for the host, skip the ones
+        // that aren't our reply
+        if from.ip() != addr {
+            continue;
+        }
+
+        let icmp = match addr {
+            // IPv4 raw sockets deliver the ip header too
+            IpAddr::V4(_) => {
+                let header_len = (buf[0] & 0x0f) as usize * 4;
+                &buf[header_len.min(len)..len]
+            }
+            IpAddr::V6(_) => &buf[..len],
+        };
+
+        if is_reply(icmp, reply_type, id, seq) {
+            return Ok(start.elapsed());
+        }
+    }
+}
+
+fn echo_request(kind: u8, id: u16, seq: u16) -> Vec<u8> {
+    let mut packet = vec![kind, 0, 0, 0];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+
+    // the kernel fills in the checksum for ICMPv6, but not for ICMPv4
+    if kind == ECHO_REQUEST_V4 {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+
+    packet
+}
+
+fn is_reply(packet: &[u8], reply_type: u8, id: u16, seq: u16) -> bool {
+    packet.len() >= 8
+        && packet[0] == reply_type
+        && packet[4..6] == id.to_be_bytes()
+        && packet[6..8] == seq.to_be_bytes()
+}
+
+// internet checksum (RFC 1071)
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|chunk| match *chunk {
+            [hi, lo] => u32::from(u16::from_be_bytes([hi, lo])),
+            [hi] => u32::from(hi) << 8,
+            _ => 0,
+        })
+        .sum();
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
@@ -0,0 +1,180 @@
+// inotify(7) file watching. The inotify fd becomes readable when events
+// are queued, so it plugs into the reactor like a socket. Event masks are
+// the libc::IN_* constants
+use std::ffi::{CString, OsStr, OsString};
+use std::future::poll_fn;
+use std::io;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use libc::c_void;
+use log::debug;
+
+use crate::REACTOR;
+
+// room for a few dozen events with long names per read
+const BUF_SIZE: usize = 4096;
+
+const HEADER_SIZE: usize = mem::size_of::<libc::inotify_event>();
+
+// identifies one watched path, events carry it back
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WatchDescriptor(i32);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InotifyEvent {
+    pub wd: WatchDescriptor,
+    pub mask: u32,
+    // pairs up IN_MOVED_FROM and IN_MOVED_TO of one rename
+    pub cookie: u32,
+    // file name inside a watched directory, None for the watched path itself
+    pub name: Option<OsString>,
+}
+
+impl InotifyEvent {
+    // the kernel dropped events, rescan whatever is being watched
+    pub fn is_overflow(&self) -> bool {
+        self.mask & libc::IN_Q_OVERFLOW != 0
+    }
+}
+
+// yields events as a Stream. A read may return several events, the ones
+// not handed out yet wait in buf
+#[derive(Debug)]
+pub struct AsyncInotify {
+    fd: OwnedFd,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl AsyncInotify {
+    pub fn new() -> Result<AsyncInotify, io::Error> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(AsyncInotify {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            buf: Vec::new(),
+            pos: 0,
+        })
+    }
+
+    // start watching path for the events in mask, e.g.
+    // IN_MODIFY | IN_CLOSE_WRITE. Watching the same path again replaces
+    // its mask and returns the same descriptor
+    pub fn add_watch<P: AsRef<Path>>(
+        &self,
+        path: P,
+        mask: u32,
+    ) -> Result<WatchDescriptor, io::Error> {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a nul byte"))?;
+
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+        if wd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(WatchDescriptor(wd))
+    }
+
+    // stop watching, an IN_IGNORED event for wd follows
+    pub fn rm_watch(&self, wd: WatchDescriptor) -> Result<(), io::Error> {
+        let rv = unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd.0) };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub async fn next_event(&mut self) -> Result<InotifyEvent, io::Error> {
+        poll_fn(|ctx| self.poll_next_event(ctx)).await
+    }
+
+    pub fn poll_next_event(&mut self, ctx: &mut Context) -> Poll<Result<InotifyEvent, io::Error>> {
+        debug!("poll_next_event() called");
+
+        if self.pos >= self.buf.len() {
+            self.buf.resize(BUF_SIZE, 0);
+            self.pos = 0;
+
+            let fd = self.fd.as_raw_fd();
+            let rv = unsafe { libc::read(fd, self.buf.as_mut_ptr() as *mut c_void, BUF_SIZE) };
+            if rv == -1 {
+                self.buf.clear();
+
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+                    return Poll::Pending;
+                }
+                return Poll::Ready(Err(err));
+            }
+
+            self.buf.truncate(rv as usize);
+        }
+
+        Poll::Ready(Ok(self.parse_event()))
+    }
+
+    // take the event at pos out of buf. The kernel only returns whole
+    // events, so a header is always followed by its name
+    fn parse_event(&mut self) -> InotifyEvent {
+        let raw: libc::inotify_event =
+            unsafe { std::ptr::read_unaligned(self.buf[self.pos..].as_ptr() as *const _) };
+        let start = self.pos + HEADER_SIZE;
+        let end = start + raw.len as usize;
+        self.pos = end;
+
+        // the name is padded with nul bytes
+        let name = &self.buf[start..end];
+        let name = match name.iter().position(|&b| b == 0) {
+            Some(n) => &name[..n],
+            None => name,
+        };
+
+        InotifyEvent {
+            wd: WatchDescriptor(raw.wd),
+            mask: raw.mask,
+            cookie: raw.cookie,
+            name: if name.is_empty() {
+                None
+            } else {
+                Some(OsStr::from_bytes(name).to_os_string())
+            },
+        }
+    }
+
+    fn deregister(&self) {
+        let fd = self.fd.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| reactor.remove_read_interest(fd));
+    }
+}
+
+impl Stream for AsyncInotify {
+    type Item = Result<InotifyEvent, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next_event(ctx).map(Some)
+    }
+}
+
+impl Drop for AsyncInotify {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncInotify {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
@@ -0,0 +1,225 @@
+// readiness interests and events
+use std::fmt;
+use std::io;
+use std::mem::ManuallyDrop;
+use std::ops;
+use std::os::unix::io::RawFd;
+use std::ptr;
+use std::task::{Context, Poll};
+
+use crate::sys;
+use crate::REACTOR;
+
+// what a task wants to wait for on a descriptor
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Interest(u8);
+
+const READABLE: u8 = 0b0001;
+const WRITABLE: u8 = 0b0010;
+const READ_CLOSED: u8 = 0b0100;
+const WRITE_CLOSED: u8 = 0b1000;
+const ERROR: u8 = 0b1_0000;
+
+impl Interest {
+    pub const READABLE: Interest = Interest(READABLE);
+    pub const WRITABLE: Interest = Interest(WRITABLE);
+
+    pub const fn add(self, other: Interest) -> Interest {
+        Interest(self.0 | other.0)
+    }
+
+    pub const fn is_readable(self) -> bool {
+        self.0 & READABLE != 0
+    }
+
+    pub const fn is_writable(self) -> bool {
+        self.0 & WRITABLE != 0
+    }
+}
+
+impl ops::BitOr for Interest {
+    type Output = Interest;
+
+    fn bitor(self, other: Interest) -> Interest {
+        self.add(other)
+    }
+}
+
+impl ops::BitOrAssign for Interest {
+    fn bitor_assign(&mut self, other: Interest) {
+        *self = self.add(other);
+    }
+}
+
+impl fmt::Debug for Interest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.is_readable(), self.is_writable()) {
+            (true, true) => write!(f, "READABLE | WRITABLE"),
+            (true, false) => write!(f, "READABLE"),
+            (false, true) => write!(f, "WRITABLE"),
+            (false, false) => write!(f, "(empty)"),
+        }
+    }
+}
+
+// which conditions fired on a descriptor
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Ready(u8);
+
+impl Ready {
+    pub const EMPTY: Ready = Ready(0);
+    pub const READABLE: Ready = Ready(READABLE);
+    pub const WRITABLE: Ready = Ready(WRITABLE);
+    // the peer shut down its write side (or the connection is gone)
+    pub const READ_CLOSED: Ready = Ready(READ_CLOSED);
+    // we can't write anymore
+    pub const WRITE_CLOSED: Ready = Ready(WRITE_CLOSED);
+    // there's a pending socket error (or error queue entry)
+    pub const ERROR: Ready = Ready(ERROR);
+
+    // translate poll(2) revents
+    pub(crate) fn from_revents(revents: libc::c_short) -> Ready {
+        let mut ready = Ready::EMPTY;
+
+        if revents & libc::POLLIN != 0 {
+            ready = ready | Ready::READABLE;
+        }
+        if revents & libc::POLLOUT != 0 {
+            ready = ready | Ready::WRITABLE;
+        }
+        if revents & libc::POLLHUP != 0 {
+            ready = ready | Ready::READ_CLOSED | Ready::WRITE_CLOSED;
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            if revents & libc::POLLRDHUP != 0 {
+                ready = ready | Ready::READ_CLOSED;
+            }
+        }
+        if revents & libc::POLLERR != 0 {
+            ready = ready | Ready::ERROR;
+        }
+
+        ready
+    }
+
+    // the events that answer interest: closed and error conditions
+    // count for both directions, since the next read/write reports them
+    pub(crate) fn intersection(self, interest: Interest) -> Ready {
+        let mut mask = ERROR;
+        if interest.is_readable() {
+            mask |= READABLE | READ_CLOSED;
+        }
+        if interest.is_writable() {
+            mask |= WRITABLE | WRITE_CLOSED;
+        }
+
+        Ready(self.0 & mask)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_readable(self) -> bool {
+        self.0 & (READABLE | READ_CLOSED) != 0
+    }
+
+    pub fn is_writable(self) -> bool {
+        self.0 & (WRITABLE | WRITE_CLOSED) != 0
+    }
+
+    pub fn is_read_closed(self) -> bool {
+        self.0 & READ_CLOSED != 0
+    }
+
+    pub fn is_write_closed(self) -> bool {
+        self.0 & WRITE_CLOSED != 0
+    }
+
+    pub fn is_error(self) -> bool {
+        self.0 & ERROR != 0
+    }
+}
+
+impl ops::BitOr for Ready {
+    type Output = Ready;
+
+    fn bitor(self, other: Ready) -> Ready {
+        Ready(self.0 | other.0)
+    }
+}
+
+impl fmt::Debug for Ready {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Ready")
+            .field("readable", &(self.0 & READABLE != 0))
+            .field("writable", &(self.0 & WRITABLE != 0))
+            .field("read_closed", &self.is_read_closed())
+            .field("write_closed", &self.is_write_closed())
+            .field("error", &self.is_error())
+            .finish()
+    }
+}
+
+// check fd right away and register with the reactor if nothing we're
+// interested in is ready yet
+pub(crate) fn poll_ready(
+    fd: RawFd,
+    interest: Interest,
+    ctx: &mut Context,
+) -> Poll<Result<Ready, io::Error>> {
+    let ready = match sys::poll_now(fd, interest) {
+        Ok(ready) => ready.intersection(interest),
+        Err(err) => return Poll::Ready(Err(err)),
+    };
+
+    if !ready.is_empty() {
+        return Poll::Ready(Ok(ready));
+    }
+
+    REACTOR.with(|reactor| {
+        if interest.is_readable() {
+            reactor.add_read_interest(fd, ctx.waker().clone());
+        }
+        if interest.is_writable() {
+            reactor.add_write_interest(fd, ctx.waker().clone());
+        }
+    });
+
+    Poll::Pending
+}
+
+// run op, if it would block register interest and try again when woken
+pub(crate) fn poll_io<R>(
+    fd: RawFd,
+    ctx: &mut Context,
+    interest: Interest,
+    mut op: impl FnMut() -> Result<R, io::Error>,
+) -> Poll<Result<R, io::Error>> {
+    match op() {
+        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+            REACTOR.with(|reactor| {
+                if interest.is_readable() {
+                    reactor.add_read_interest(fd, ctx.waker().clone());
+                }
+                if interest.is_writable() {
+                    reactor.add_write_interest(fd, ctx.waker().clone());
+                }
+            });
+
+            Poll::Pending
+        }
+        res => Poll::Ready(res),
+    }
+}
+
+// move the io object out of an io type whose Drop only deregisters it, for
+// into_std and into_raw_fd. The caller deregisters first, the rest of this
+// is forgotten.
+//
+// Safety: io has to return a field of this
+pub(crate) unsafe fn take_io<T, U>(this: T, io: fn(&T) -> &U) -> U {
+    let this = ManuallyDrop::new(this);
+    ptr::read(io(&this))
+}
@@ -0,0 +1,166 @@
+use std::fmt;
+use std::io::{self, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncBufRead, AsyncRead, AsyncSeek, AsyncWrite};
+
+pub(crate) const DEFAULT_BUF_SIZE: usize = 8 * 1024;
+
+// reads from the inner reader in big chunks and hands them out from
+// memory. Writes go straight through
+pub struct BufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+}
+
+impl<R> BufReader<R> {
+    pub fn new(inner: R) -> BufReader<R> {
+        BufReader::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> BufReader<R> {
+        BufReader {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    // reading from the inner reader directly skips what's buffered
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    // bytes read ahead but not handed out yet
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.cap]
+    }
+
+    // anything still buffered is lost
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn discard_buffer(&mut self) {
+        self.pos = 0;
+        self.cap = 0;
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BufReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        // nothing buffered and a big read, no point copying it twice
+        if self.pos == self.cap && buf.len() >= self.buf.len() {
+            let res = Pin::new(&mut self.inner).poll_read(ctx, buf);
+            self.discard_buffer();
+            return res;
+        }
+
+        let n = match self.as_mut().poll_fill_buf(ctx) {
+            Poll::Ready(Ok(rem)) => {
+                let n = rem.len().min(buf.len());
+                buf[..n].copy_from_slice(&rem[..n]);
+                n
+            }
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        };
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for BufReader<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<&[u8], io::Error>> {
+        let this = self.get_mut();
+
+        if this.pos >= this.cap {
+            match Pin::new(&mut this.inner).poll_read(ctx, &mut this.buf) {
+                Poll::Ready(Ok(n)) => {
+                    this.pos = 0;
+                    this.cap = n;
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(Ok(&this.buf[this.pos..this.cap]))
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.pos = (self.pos + amt).min(self.cap);
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncSeek for BufReader<R> {
+    // the buffer is thrown away, SeekFrom::Current is relative to what the
+    // caller has read so far, not to the inner reader's position
+    fn poll_seek(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        pos: SeekFrom,
+    ) -> Poll<Result<u64, io::Error>> {
+        let pos = match pos {
+            SeekFrom::Current(offset) => {
+                let remainder = (self.cap - self.pos) as i64;
+                match offset.checked_sub(remainder) {
+                    Some(offset) => SeekFrom::Current(offset),
+                    None => {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            "seek offset overflowed",
+                        )))
+                    }
+                }
+            }
+            pos => pos,
+        };
+
+        let res = Pin::new(&mut self.inner).poll_seek(ctx, pos);
+        if res.is_ready() {
+            self.discard_buffer();
+        }
+        res
+    }
+}
+
+impl<R: AsyncWrite + Unpin> AsyncWrite for BufReader<R> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.inner).poll_write(ctx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(ctx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_close(ctx)
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for BufReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufReader")
+            .field("inner", &self.inner)
+            .field("buffered", &(self.cap - self.pos))
+            .field("capacity", &self.buf.len())
+            .finish()
+    }
+}
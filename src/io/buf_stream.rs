@@ -0,0 +1,76 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+use super::{BufReader, BufWriter};
+
+// buffers both directions of a stream, a BufReader around a BufWriter.
+// Flush before waiting for a reply, buffered writes aren't sent on their own
+#[derive(Debug)]
+pub struct BufStream<S>(BufReader<BufWriter<S>>);
+
+impl<S> BufStream<S> {
+    pub fn new(stream: S) -> BufStream<S> {
+        BufStream(BufReader::new(BufWriter::new(stream)))
+    }
+
+    pub fn with_capacity(read_capacity: usize, write_capacity: usize, stream: S) -> BufStream<S> {
+        BufStream(BufReader::with_capacity(
+            read_capacity,
+            BufWriter::with_capacity(write_capacity, stream),
+        ))
+    }
+
+    pub fn get_ref(&self) -> &S {
+        self.0.get_ref().get_ref()
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        self.0.get_mut().get_mut()
+    }
+
+    // buffered data in either direction is lost
+    pub fn into_inner(self) -> S {
+        self.0.into_inner().into_inner()
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for BufStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.0).poll_read(ctx, buf)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncBufRead for BufStream<S> {
+    fn poll_fill_buf(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<&[u8], io::Error>> {
+        Pin::new(&mut self.get_mut().0).poll_fill_buf(ctx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.0).consume(amt)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for BufStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.0).poll_write(ctx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.0).poll_flush(ctx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.0).poll_close(ctx)
+    }
+}
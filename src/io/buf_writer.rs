@@ -0,0 +1,149 @@
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+use super::buf_reader::DEFAULT_BUF_SIZE;
+
+// collects small writes in memory and passes them on in big chunks. The
+// buffer only reaches the inner writer on flush, close or when it fills
+// up; whatever is left when a BufWriter is dropped is lost. Reads go
+// straight through
+pub struct BufWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+    // bytes at the front of buf the inner writer already took
+    written: usize,
+}
+
+impl<W> BufWriter<W> {
+    pub fn new(inner: W) -> BufWriter<W> {
+        BufWriter::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: W) -> BufWriter<W> {
+        BufWriter {
+            inner,
+            buf: Vec::with_capacity(capacity),
+            written: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    // writing to the inner writer directly jumps ahead of what's buffered
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    // bytes waiting to be written
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.written..]
+    }
+
+    // anything still buffered is lost, flush first
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BufWriter<W> {
+    // hand the whole buffer to the inner writer
+    fn poll_flush_buf(&mut self, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        while self.written < self.buf.len() {
+            match Pin::new(&mut self.inner).poll_write(ctx, &self.buf[self.written..]) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write the buffered data",
+                    )))
+                }
+                Poll::Ready(Ok(n)) => self.written += n,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        self.buf.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BufWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if this.buf.len() + buf.len() > this.buf.capacity() {
+            match this.poll_flush_buf(ctx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        // too big to be worth buffering
+        if buf.len() >= this.buf.capacity() {
+            return Pin::new(&mut this.inner).poll_write(ctx, buf);
+        }
+
+        this.buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+
+        match this.poll_flush_buf(ctx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_flush(ctx),
+            other => other,
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+
+        match this.poll_flush_buf(ctx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_close(ctx),
+            other => other,
+        }
+    }
+}
+
+impl<W: AsyncRead + Unpin> AsyncRead for BufWriter<W> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        Pin::new(&mut self.inner).poll_read(ctx, buf)
+    }
+}
+
+impl<W: AsyncBufRead + Unpin> AsyncBufRead for BufWriter<W> {
+    fn poll_fill_buf(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<&[u8], io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(ctx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
+
+impl<W: fmt::Debug> fmt::Debug for BufWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufWriter")
+            .field("inner", &self.inner)
+            .field("buffered", &(self.buf.len() - self.written))
+            .field("capacity", &self.buf.capacity())
+            .finish()
+    }
+}
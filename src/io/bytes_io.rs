@@ -0,0 +1,70 @@
+// reads into bytes::BufMut and writes from bytes::Buf (feature "bytes").
+// Reads land in the buffer's spare capacity without zeroing or copying it
+// first, writes send every chunk of the Buf with one writev(2)
+use std::io::{self, IoSlice};
+use std::os::unix::io::RawFd;
+use std::task::{Context, Poll};
+
+use bytes::{Buf, BufMut};
+use libc::c_void;
+
+use crate::REACTOR;
+
+// IoSlices handed to one writev(2)
+const MAX_IOVS: usize = 64;
+
+pub(crate) fn poll_read_buf<B: BufMut + ?Sized>(
+    fd: RawFd,
+    ctx: &mut Context,
+    buf: &mut B,
+) -> Poll<Result<usize, io::Error>> {
+    if !buf.has_remaining_mut() {
+        return Poll::Ready(Ok(0));
+    }
+
+    let chunk = buf.chunk_mut();
+    let n = unsafe { libc::read(fd, chunk.as_mut_ptr() as *mut c_void, chunk.len()) };
+    if n < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+            return Poll::Pending;
+        }
+        return Poll::Ready(Err(err));
+    }
+
+    // the kernel initialized the first n bytes
+    unsafe { buf.advance_mut(n as usize) };
+    Poll::Ready(Ok(n as usize))
+}
+
+pub(crate) fn poll_write_buf<B: Buf + ?Sized>(
+    fd: RawFd,
+    ctx: &mut Context,
+    buf: &mut B,
+) -> Poll<Result<usize, io::Error>> {
+    if !buf.has_remaining() {
+        return Poll::Ready(Ok(0));
+    }
+
+    let mut iovs = [IoSlice::new(&[]); MAX_IOVS];
+    let count = buf.chunks_vectored(&mut iovs);
+    let n = unsafe {
+        libc::writev(
+            fd,
+            iovs.as_ptr() as *const libc::iovec,
+            count as libc::c_int,
+        )
+    };
+    if n < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            REACTOR.with(|reactor| reactor.add_write_interest(fd, ctx.waker().clone()));
+            return Poll::Pending;
+        }
+        return Poll::Ready(Err(err));
+    }
+
+    buf.advance(n as usize);
+    Poll::Ready(Ok(n as usize))
+}
@@ -0,0 +1,226 @@
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+const BUF_SIZE: usize = 16 * 1024;
+
+// chunks moved in one poll before the task yields. A connection that's
+// always ready would otherwise keep the loop from ever reaching select
+const BUDGET: usize = 32;
+
+// copy everything from reader to writer until EOF, then flush the writer.
+// Returns the number of bytes copied
+pub async fn copy<R, W>(reader: &mut R, writer: &mut W) -> Result<u64, io::Error>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut buf = CopyBuffer::new();
+    poll_fn(|ctx| buf.poll_copy(ctx, Pin::new(&mut *reader), Pin::new(&mut *writer))).await
+}
+
+// like copy() but writes straight out of the reader's own buffer
+pub async fn copy_buf<R, W>(reader: &mut R, writer: &mut W) -> Result<u64, io::Error>
+where
+    R: AsyncBufRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut amt = 0;
+
+    poll_fn(|ctx| {
+        let mut reader = Pin::new(&mut *reader);
+        let mut writer = Pin::new(&mut *writer);
+
+        for _ in 0..BUDGET {
+            let buf = match reader.as_mut().poll_fill_buf(ctx) {
+                Poll::Ready(Ok(buf)) => buf,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => {
+                    // don't leave a buffering writer sitting on data while we wait
+                    if let Poll::Ready(Err(err)) = writer.as_mut().poll_flush(ctx) {
+                        return Poll::Ready(Err(err));
+                    }
+                    return Poll::Pending;
+                }
+            };
+
+            if buf.is_empty() {
+                return writer.as_mut().poll_flush(ctx).map_ok(|()| amt);
+            }
+
+            let n = match writer.as_mut().poll_write(ctx, buf) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(write_zero())),
+                Poll::Ready(Ok(n)) => n,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            };
+            reader.as_mut().consume(n);
+            amt += n as u64;
+        }
+
+        ctx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+// copy in both directions until both readers hit EOF. When one side is
+// done sending, the other side's write half is closed so the half-close
+// reaches the peer. Returns the bytes copied a -> b and b -> a
+pub async fn copy_bidirectional<A, B>(a: &mut A, b: &mut B) -> Result<(u64, u64), io::Error>
+where
+    A: AsyncRead + AsyncWrite + Unpin + ?Sized,
+    B: AsyncRead + AsyncWrite + Unpin + ?Sized,
+{
+    let mut a_to_b = Transfer::Running(CopyBuffer::new());
+    let mut b_to_a = Transfer::Running(CopyBuffer::new());
+
+    poll_fn(|ctx| {
+        let a_to_b = a_to_b.poll(ctx, &mut *a, &mut *b)?;
+        let b_to_a = b_to_a.poll(ctx, &mut *b, &mut *a)?;
+
+        match (a_to_b, b_to_a) {
+            (Poll::Ready(a_to_b), Poll::Ready(b_to_a)) => Poll::Ready(Ok((a_to_b, b_to_a))),
+            _ => Poll::Pending,
+        }
+    })
+    .await
+}
+
+// one direction of copy_bidirectional()
+enum Transfer {
+    Running(CopyBuffer),
+    ShuttingDown(u64),
+    Done(u64),
+}
+
+impl Transfer {
+    fn poll<R, W>(
+        &mut self,
+        ctx: &mut Context,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Poll<Result<u64, io::Error>>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        loop {
+            match self {
+                Transfer::Running(buf) => {
+                    match buf.poll_copy(ctx, Pin::new(&mut *reader), Pin::new(&mut *writer)) {
+                        Poll::Ready(Ok(amt)) => *self = Transfer::ShuttingDown(amt),
+                        Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                Transfer::ShuttingDown(amt) => match Pin::new(&mut *writer).poll_close(ctx) {
+                    Poll::Ready(Ok(())) => *self = Transfer::Done(*amt),
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => return Poll::Pending,
+                },
+                Transfer::Done(amt) => return Poll::Ready(Ok(*amt)),
+            }
+        }
+    }
+}
+
+// state of one direction of a copy
+struct CopyBuffer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    amt: u64,
+    read_done: bool,
+    need_flush: bool,
+}
+
+impl CopyBuffer {
+    fn new() -> CopyBuffer {
+        CopyBuffer {
+            buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            amt: 0,
+            read_done: false,
+            need_flush: false,
+        }
+    }
+
+    // resolves once the reader hit EOF and everything was written and flushed
+    fn poll_copy<R, W>(
+        &mut self,
+        ctx: &mut Context,
+        mut reader: Pin<&mut R>,
+        mut writer: Pin<&mut W>,
+    ) -> Poll<Result<u64, io::Error>>
+    where
+        R: AsyncRead + ?Sized,
+        W: AsyncWrite + ?Sized,
+    {
+        let mut budget = BUDGET;
+
+        loop {
+            if self.pos == self.cap && !self.read_done {
+                if budget == 0 {
+                    ctx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                budget -= 1;
+
+                match reader.as_mut().poll_read(ctx, &mut self.buf) {
+                    Poll::Ready(Ok(0)) => self.read_done = true,
+                    Poll::Ready(Ok(n)) => {
+                        self.pos = 0;
+                        self.cap = n;
+                    }
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => {
+                        if self.need_flush {
+                            match writer.as_mut().poll_flush(ctx) {
+                                Poll::Ready(Ok(())) => self.need_flush = false,
+                                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                                Poll::Pending => {}
+                            }
+                        }
+                        return Poll::Pending;
+                    }
+                }
+            }
+
+            while self.pos < self.cap {
+                match writer
+                    .as_mut()
+                    .poll_write(ctx, &self.buf[self.pos..self.cap])
+                {
+                    Poll::Ready(Ok(0)) => return Poll::Ready(Err(write_zero())),
+                    Poll::Ready(Ok(n)) => {
+                        self.pos += n;
+                        self.amt += n as u64;
+                        self.need_flush = true;
+                    }
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            if self.read_done {
+                return match writer.as_mut().poll_flush(ctx) {
+                    Poll::Ready(Ok(())) => {
+                        self.need_flush = false;
+                        Poll::Ready(Ok(self.amt))
+                    }
+                    Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+        }
+    }
+}
+
+fn write_zero() -> io::Error {
+    io::Error::new(io::ErrorKind::WriteZero, "write zero bytes into writer")
+}
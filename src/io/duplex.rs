@@ -0,0 +1,162 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+// two connected in-memory streams, what's written to one can be read from
+// the other. Each direction buffers up to capacity bytes, after that writes
+// wait for the other side to read. Wakers only work on the reactor thread
+// they came from, so both ends have to stay on one reactor
+pub fn duplex(capacity: usize) -> (DuplexStream, DuplexStream) {
+    assert!(capacity > 0, "duplex capacity must be non-zero");
+
+    let one = Arc::new(Mutex::new(Pipe::new(capacity)));
+    let two = Arc::new(Mutex::new(Pipe::new(capacity)));
+
+    (
+        DuplexStream {
+            read: one.clone(),
+            write: two.clone(),
+        },
+        DuplexStream {
+            read: two,
+            write: one,
+        },
+    )
+}
+
+#[derive(Debug)]
+pub struct DuplexStream {
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+// one direction
+#[derive(Debug)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    // the writer closed or was dropped, reads see EOF once buf is drained
+    write_closed: bool,
+    // the reader was dropped, writes fail
+    read_closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Pipe {
+        Pipe {
+            buf: VecDeque::new(),
+            capacity,
+            write_closed: false,
+            read_closed: false,
+            read_waker: None,
+            write_waker: None,
+        }
+    }
+
+    fn poll_read(&mut self, ctx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, io::Error>> {
+        if self.buf.is_empty() {
+            if self.write_closed || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            self.read_waker = Some(ctx.waker().clone());
+            return Poll::Pending;
+        }
+
+        let n = cmp::min(buf.len(), self.buf.len());
+        for (dst, src) in buf.iter_mut().zip(self.buf.drain(..n)) {
+            *dst = src;
+        }
+
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_write(&mut self, ctx: &mut Context, buf: &[u8]) -> Poll<Result<usize, io::Error>> {
+        if self.read_closed {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        if self.write_closed {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "write after close",
+            )));
+        }
+
+        let n = cmp::min(buf.len(), self.capacity - self.buf.len());
+        if n == 0 && !buf.is_empty() {
+            self.write_waker = Some(ctx.waker().clone());
+            return Poll::Pending;
+        }
+
+        self.buf.extend(&buf[..n]);
+
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn close_write(&mut self) {
+        self.write_closed = true;
+        if let Some(waker) = self.read_waker.take() {
+            waker.wake();
+        }
+    }
+
+    fn close_read(&mut self) {
+        self.read_closed = true;
+        if let Some(waker) = self.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+fn lock(pipe: &Mutex<Pipe>) -> MutexGuard<'_, Pipe> {
+    pipe.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+impl AsyncRead for DuplexStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        lock(&self.read).poll_read(ctx, buf)
+    }
+}
+
+impl AsyncWrite for DuplexStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        lock(&self.write).poll_write(ctx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // like shutdown(Write) on a socket, the other end reads EOF
+    fn poll_close(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        lock(&self.write).close_write();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for DuplexStream {
+    fn drop(&mut self) {
+        lock(&self.write).close_write();
+        lock(&self.read).close_read();
+    }
+}
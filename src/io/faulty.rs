@@ -0,0 +1,166 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::rng::Rng;
+use crate::time::{self, sleep_until, Sleep};
+
+// injects faults into reads and writes of the inner stream, for testing how
+// protocol code copes with partial and failing io. Every read and write may
+// be delayed, fail or move fewer bytes than asked for, each with its own
+// chance. The faults come from a generator seeded by the caller, the same
+// seed and the same sequence of calls give the same faults. Flush and close
+// are passed through untouched
+pub struct Faulty<T> {
+    inner: T,
+    rng: Rng,
+    error_rate: f64,
+    error_kind: io::ErrorKind,
+    short_rate: f64,
+    delay_rate: f64,
+    max_delay: Duration,
+    // a delayed call waits on this, the call after it goes through
+    delay: Option<Sleep>,
+}
+
+impl<T> Faulty<T> {
+    // no faults until some are turned on
+    pub fn new(inner: T, seed: u64) -> Faulty<T> {
+        Faulty {
+            inner,
+            rng: Rng::new(seed),
+            error_rate: 0.0,
+            error_kind: io::ErrorKind::Other,
+            short_rate: 0.0,
+            delay_rate: 0.0,
+            max_delay: Duration::ZERO,
+            delay: None,
+        }
+    }
+
+    // chance in 0..=1 that a call fails without touching the inner stream.
+    // The stream stays usable, the next call may succeed
+    pub fn error_rate(mut self, rate: f64) -> Faulty<T> {
+        self.error_rate = rate;
+        self
+    }
+
+    // what failing calls return, Other by default
+    pub fn error_kind(mut self, kind: io::ErrorKind) -> Faulty<T> {
+        self.error_kind = kind;
+        self
+    }
+
+    // chance that a call passes on only part of the buffer, at least a byte
+    pub fn short_rate(mut self, rate: f64) -> Faulty<T> {
+        self.short_rate = rate;
+        self
+    }
+
+    // chance that a call first waits for up to max
+    pub fn delay(mut self, rate: f64, max: Duration) -> Faulty<T> {
+        self.delay_rate = rate;
+        self.max_delay = max;
+        self
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    // how much of len the call gets to move, or the fault to fail it with
+    fn poll_fault(&mut self, ctx: &mut Context, len: usize) -> Poll<Result<usize, io::Error>> {
+        if self.delay.is_none() && self.rng.chance(self.delay_rate) {
+            let nanos = self.rng.below(self.max_delay.as_nanos() as u64 + 1);
+            let deadline = time::now() + Duration::from_nanos(nanos);
+            self.delay = Some(sleep_until(deadline));
+        }
+        if let Some(delay) = &mut self.delay {
+            if Pin::new(delay).poll(ctx).is_pending() {
+                return Poll::Pending;
+            }
+            self.delay = None;
+        }
+
+        if self.rng.chance(self.error_rate) {
+            return Poll::Ready(Err(io::Error::new(self.error_kind, "injected fault")));
+        }
+
+        if len > 1 && self.rng.chance(self.short_rate) {
+            return Poll::Ready(Ok(1 + self.rng.below(len as u64 - 1) as usize));
+        }
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Faulty<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(ctx, buf);
+        }
+
+        match this.poll_fault(ctx, buf.len()) {
+            Poll::Ready(Ok(n)) => Pin::new(&mut this.inner).poll_read(ctx, &mut buf[..n]),
+            other => other,
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Faulty<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(ctx, buf);
+        }
+
+        match this.poll_fault(ctx, buf.len()) {
+            Poll::Ready(Ok(n)) => Pin::new(&mut this.inner).poll_write(ctx, &buf[..n]),
+            other => other,
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(ctx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_close(ctx)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Faulty<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Faulty")
+            .field("inner", &self.inner)
+            .field("error_rate", &self.error_rate)
+            .field("error_kind", &self.error_kind)
+            .field("short_rate", &self.short_rate)
+            .field("delay_rate", &self.delay_rate)
+            .field("max_delay", &self.max_delay)
+            .finish()
+    }
+}
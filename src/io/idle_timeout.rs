@@ -0,0 +1,122 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::time::{self, sleep_until, Sleep};
+
+// fails reads and writes with ErrorKind::TimedOut once no bytes have moved
+// in either direction for the timeout. Every byte read or written starts
+// the clock again
+#[derive(Debug)]
+pub struct IdleTimeout<T> {
+    inner: T,
+    timeout: Duration,
+    last_activity: Instant,
+    sleep: Sleep,
+}
+
+impl<T> IdleTimeout<T> {
+    pub fn new(inner: T, timeout: Duration) -> IdleTimeout<T> {
+        let now = time::now();
+
+        IdleTimeout {
+            inner,
+            timeout,
+            last_activity: now,
+            sleep: sleep_until(now + timeout),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // counted from the last activity, not from now
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    // the inner stream is waiting, fail if it's been idle for too long
+    fn poll_idle(&mut self, ctx: &mut Context) -> Poll<io::Error> {
+        let deadline = self.last_activity + self.timeout;
+        if self.sleep.deadline() != deadline {
+            self.sleep.reset(deadline);
+        }
+
+        match Pin::new(&mut self.sleep).poll(ctx) {
+            Poll::Ready(()) => Poll::Ready(io::Error::new(io::ErrorKind::TimedOut, "idle timeout")),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for IdleTimeout<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        match Pin::new(&mut this.inner).poll_read(ctx, buf) {
+            Poll::Ready(Ok(n)) => {
+                if n > 0 {
+                    this.last_activity = time::now();
+                }
+                Poll::Ready(Ok(n))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => this.poll_idle(ctx).map(Err),
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        match Pin::new(&mut this.inner).poll_write(ctx, buf) {
+            Poll::Ready(Ok(n)) => {
+                if n > 0 {
+                    this.last_activity = time::now();
+                }
+                Poll::Ready(Ok(n))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => this.poll_idle(ctx).map(Err),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+
+        match Pin::new(&mut this.inner).poll_flush(ctx) {
+            Poll::Pending => this.poll_idle(ctx).map(Err),
+            res => res,
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_close(ctx)
+    }
+}
@@ -0,0 +1,107 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use tracing::{debug, trace, Span};
+
+// emits tracing events for every read, write, wait and error of the inner
+// stream (feature "tracing"). The inner stream is polled inside the span,
+// so its own events land there too
+#[derive(Debug)]
+pub struct Instrumented<T> {
+    inner: T,
+    span: Span,
+}
+
+impl<T> Instrumented<T> {
+    pub fn new(inner: T, span: Span) -> Instrumented<T> {
+        Instrumented { inner, span }
+    }
+
+    // use whatever span is current, e.g. the connection handler's
+    pub fn in_current_span(inner: T) -> Instrumented<T> {
+        Instrumented::new(inner, Span::current())
+    }
+
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Instrumented<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        let _enter = this.span.enter();
+
+        let res = Pin::new(&mut this.inner).poll_read(ctx, buf);
+        match res {
+            Poll::Ready(Ok(0)) if !buf.is_empty() => trace!("read: eof"),
+            Poll::Ready(Ok(n)) => trace!(bytes = n, "read"),
+            Poll::Ready(Err(ref err)) => debug!(error = %err, "read failed"),
+            Poll::Pending => trace!(capacity = buf.len(), "read: waiting for readiness"),
+        }
+        res
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Instrumented<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+        let _enter = this.span.enter();
+
+        let res = Pin::new(&mut this.inner).poll_write(ctx, buf);
+        match res {
+            Poll::Ready(Ok(n)) => trace!(bytes = n, requested = buf.len(), "write"),
+            Poll::Ready(Err(ref err)) => debug!(error = %err, "write failed"),
+            Poll::Pending => trace!(bytes = buf.len(), "write: waiting for readiness"),
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+        let _enter = this.span.enter();
+
+        let res = Pin::new(&mut this.inner).poll_flush(ctx);
+        match res {
+            Poll::Ready(Ok(())) => trace!("flush"),
+            Poll::Ready(Err(ref err)) => debug!(error = %err, "flush failed"),
+            Poll::Pending => trace!("flush: waiting for readiness"),
+        }
+        res
+    }
+
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+        let _enter = this.span.enter();
+
+        let res = Pin::new(&mut this.inner).poll_close(ctx);
+        match res {
+            Poll::Ready(Ok(())) => trace!("close"),
+            Poll::Ready(Err(ref err)) => debug!(error = %err, "close failed"),
+            Poll::Pending => trace!("close: waiting for readiness"),
+        }
+        res
+    }
+}
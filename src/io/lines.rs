@@ -0,0 +1,122 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use futures_io::AsyncBufRead;
+
+// a Stream of the lines of an AsyncBufRead, without the "\n" or "\r\n".
+// A line longer than the limit is reported as an InvalidData error and
+// skipped, the stream carries on with the next one. Invalid UTF-8 is an
+// InvalidData error too
+#[derive(Debug)]
+pub struct LinesStream<R> {
+    reader: R,
+    line: Vec<u8>,
+    max_length: usize,
+    // dropping the rest of a line that was too long
+    discarding: bool,
+}
+
+impl<R> LinesStream<R> {
+    // no limit on the line length
+    pub fn new(reader: R) -> LinesStream<R> {
+        LinesStream::with_max_length(reader, usize::MAX)
+    }
+
+    // lines longer than max_length bytes (line ending not counted) fail
+    pub fn with_max_length(reader: R, max_length: usize) -> LinesStream<R> {
+        LinesStream {
+            reader,
+            line: Vec::new(),
+            max_length,
+            discarding: false,
+        }
+    }
+
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    // a partly read line is lost
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn take_line(&mut self) -> Result<String, io::Error> {
+        let mut line = std::mem::take(&mut self.line);
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+
+        String::from_utf8(line)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.utf8_error()))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for LinesStream<R> {
+    type Item = Result<String, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            let buf = match Pin::new(&mut this.reader).poll_fill_buf(ctx) {
+                Poll::Ready(Ok(buf)) => buf,
+                Poll::Ready(Err(err)) => return Poll::Ready(Some(Err(err))),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            // EOF, the last line doesn't need a line ending
+            if buf.is_empty() {
+                if this.discarding || this.line.is_empty() {
+                    this.discarding = false;
+                    return Poll::Ready(None);
+                }
+                return Poll::Ready(Some(this.take_line()));
+            }
+
+            let (used, found) = match buf.iter().position(|b| *b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (buf.len(), false),
+            };
+
+            if !this.discarding {
+                let end = if found { used - 1 } else { used };
+                this.line.extend_from_slice(&buf[..end]);
+            }
+            Pin::new(&mut this.reader).consume(used);
+
+            if this.discarding {
+                this.discarding = !found;
+                continue;
+            }
+
+            // a trailing "\r" belongs to the line ending, not the line
+            let len = match this.line.last() {
+                Some(b'\r') => this.line.len() - 1,
+                _ => this.line.len(),
+            };
+            if len > this.max_length {
+                this.line.clear();
+                this.discarding = !found;
+                return Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "line too long",
+                ))));
+            }
+
+            if found {
+                return Poll::Ready(Some(this.take_line()));
+            }
+        }
+    }
+}
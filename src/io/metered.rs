@@ -0,0 +1,132 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncBufRead, AsyncRead, AsyncWrite};
+
+// byte and call counts of one or more Metered streams. Share one between
+// all connections of a listener to get its totals
+#[derive(Debug, Default)]
+pub struct IoCounters {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    reads: AtomicU64,
+    writes: AtomicU64,
+}
+
+impl IoCounters {
+    pub fn new() -> IoCounters {
+        IoCounters::default()
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    // completed read calls, EOF included. Calls that had to wait count once
+    pub fn reads(&self) -> u64 {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.writes.load(Ordering::Relaxed)
+    }
+}
+
+// counts what goes through the inner stream
+#[derive(Debug)]
+pub struct Metered<T> {
+    inner: T,
+    counters: Arc<IoCounters>,
+}
+
+impl<T> Metered<T> {
+    pub fn new(inner: T) -> Metered<T> {
+        Metered::with_counters(inner, Arc::new(IoCounters::new()))
+    }
+
+    // add to existing counters
+    pub fn with_counters(inner: T, counters: Arc<IoCounters>) -> Metered<T> {
+        Metered { inner, counters }
+    }
+
+    pub fn counters(&self) -> &Arc<IoCounters> {
+        &self.counters
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    // io done through this reference isn't counted
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Metered<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let res = Pin::new(&mut self.inner).poll_read(ctx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            self.counters.reads.fetch_add(1, Ordering::Relaxed);
+            self.counters
+                .bytes_read
+                .fetch_add(n as u64, Ordering::Relaxed);
+        }
+        res
+    }
+}
+
+impl<T: AsyncBufRead + Unpin> AsyncBufRead for Metered<T> {
+    // bytes are counted as they're consumed
+    fn poll_fill_buf(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<&[u8], io::Error>> {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(ctx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        self.counters.reads.fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .bytes_read
+            .fetch_add(amt as u64, Ordering::Relaxed);
+        Pin::new(&mut self.inner).consume(amt)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Metered<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let res = Pin::new(&mut self.inner).poll_write(ctx, buf);
+        if let Poll::Ready(Ok(n)) = res {
+            self.counters.writes.fetch_add(1, Ordering::Relaxed);
+            self.counters
+                .bytes_written
+                .fetch_add(n as u64, Ordering::Relaxed);
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(ctx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_close(ctx)
+    }
+}
@@ -0,0 +1,261 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::time::{self, sleep_until, Sleep};
+
+// a stream that plays a script, for testing protocol code without a
+// listener:
+//
+//   let stream = MockStream::builder()
+//       .write(b"PING\r\n")
+//       .read(b"+PONG\r\n")
+//       .build();
+//
+// steps run in order. Reads get the scripted bytes and wait while a write
+// is due, writes are checked against the script and panic on anything
+// else. Once the script is done reads see EOF. Dropping the stream with
+// steps left panics, so a test notices the client stopped early
+pub struct MockStream {
+    steps: VecDeque<Step>,
+    // the wait in front, once it's been reached
+    sleep: Option<Sleep>,
+    // a read waiting for a write to be done
+    reader: Option<Waker>,
+}
+
+#[derive(Debug, Default)]
+pub struct MockStreamBuilder {
+    steps: VecDeque<Step>,
+}
+
+enum Step {
+    Read(Vec<u8>),
+    Write(Vec<u8>),
+    ReadError(io::Error),
+    WriteError(io::Error),
+    Wait(Duration),
+}
+
+impl MockStream {
+    pub fn builder() -> MockStreamBuilder {
+        MockStreamBuilder::default()
+    }
+
+    // steps not played yet
+    pub fn remaining(&self) -> usize {
+        self.steps.len()
+    }
+
+    // Ready once the step in front isn't a wait
+    fn poll_wait(&mut self, ctx: &mut Context) -> Poll<()> {
+        while let Some(Step::Wait(duration)) = self.steps.front() {
+            let duration = *duration;
+            let sleep = self
+                .sleep
+                .get_or_insert_with(|| sleep_until(time::now() + duration));
+
+            if Pin::new(sleep).poll(ctx).is_pending() {
+                return Poll::Pending;
+            }
+            self.sleep = None;
+            self.steps.pop_front();
+        }
+        Poll::Ready(())
+    }
+
+    fn wake_reader(&mut self) {
+        if let Some(waker) = self.reader.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl MockStreamBuilder {
+    // bytes the stream hands out, maybe over several reads
+    pub fn read(mut self, data: &[u8]) -> MockStreamBuilder {
+        self.steps.push_back(Step::Read(data.to_vec()));
+        self
+    }
+
+    // bytes the code under test has to write next, maybe over several writes
+    pub fn write(mut self, data: &[u8]) -> MockStreamBuilder {
+        self.steps.push_back(Step::Write(data.to_vec()));
+        self
+    }
+
+    pub fn read_error(mut self, err: io::Error) -> MockStreamBuilder {
+        self.steps.push_back(Step::ReadError(err));
+        self
+    }
+
+    pub fn write_error(mut self, err: io::Error) -> MockStreamBuilder {
+        self.steps.push_back(Step::WriteError(err));
+        self
+    }
+
+    // neither reads nor writes go through for duration, counted from the
+    // first read or write that gets here
+    pub fn wait(mut self, duration: Duration) -> MockStreamBuilder {
+        self.steps.push_back(Step::Wait(duration));
+        self
+    }
+
+    pub fn build(self) -> MockStream {
+        MockStream {
+            steps: self.steps,
+            sleep: None,
+            reader: None,
+        }
+    }
+}
+
+impl AsyncRead for MockStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if this.poll_wait(ctx).is_pending() {
+            return Poll::Pending;
+        }
+
+        match this.steps.front_mut() {
+            None => Poll::Ready(Ok(0)),
+            Some(Step::Read(data)) => {
+                let n = cmp::min(buf.len(), data.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                data.drain(..n);
+
+                if data.is_empty() {
+                    this.steps.pop_front();
+                }
+                Poll::Ready(Ok(n))
+            }
+            Some(Step::ReadError(_)) => match this.steps.pop_front() {
+                Some(Step::ReadError(err)) => Poll::Ready(Err(err)),
+                _ => unreachable!(),
+            },
+            // the code under test has to write first
+            Some(_) => {
+                this.reader = Some(ctx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MockStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if this.poll_wait(ctx).is_pending() {
+            return Poll::Pending;
+        }
+
+        let res = match this.steps.front_mut() {
+            Some(Step::Write(expected)) => {
+                let n = cmp::min(buf.len(), expected.len());
+                assert_eq!(
+                    Bytes(&buf[..n]),
+                    Bytes(&expected[..n]),
+                    "mock stream got an unexpected write"
+                );
+                expected.drain(..n);
+
+                if expected.is_empty() {
+                    this.steps.pop_front();
+                }
+                Ok(n)
+            }
+            Some(Step::WriteError(_)) => match this.steps.pop_front() {
+                Some(Step::WriteError(err)) => Err(err),
+                _ => unreachable!(),
+            },
+            Some(step) => panic!(
+                "mock stream got a write of {:?}, expected {:?}",
+                Bytes(buf),
+                step
+            ),
+            None => panic!(
+                "mock stream got a write of {:?} after the script ended",
+                Bytes(buf)
+            ),
+        };
+
+        this.wake_reader();
+        Poll::Ready(res)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MockStream {
+    fn drop(&mut self) {
+        // don't turn a failing test into an abort
+        if std::thread::panicking() {
+            return;
+        }
+
+        // a trailing wait doesn't need anybody
+        while let Some(Step::Wait(_)) = self.steps.back() {
+            self.steps.pop_back();
+        }
+        if let Some(step) = self.steps.front() {
+            panic!(
+                "mock stream dropped with {} steps left, next {:?}",
+                self.steps.len(),
+                step
+            );
+        }
+    }
+}
+
+impl fmt::Debug for MockStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockStream")
+            .field("steps", &self.steps)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Read(data) => write!(f, "read {:?}", Bytes(data)),
+            Step::Write(data) => write!(f, "write {:?}", Bytes(data)),
+            Step::ReadError(err) => write!(f, "read error {}", err),
+            Step::WriteError(err) => write!(f, "write error {}", err),
+            Step::Wait(duration) => write!(f, "wait {:?}", duration),
+        }
+    }
+}
+
+// bytes in panic messages, as text where possible
+#[derive(PartialEq)]
+struct Bytes<'a>(&'a [u8]);
+
+impl fmt::Debug for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "b\"{}\"", self.0.escape_ascii())
+    }
+}
@@ -0,0 +1,39 @@
+// io helpers that aren't tied to one socket type
+mod buf_reader;
+mod buf_stream;
+mod buf_writer;
+#[cfg(feature = "tokio")]
+mod compat;
+#[cfg(feature = "bytes")]
+pub(crate) mod bytes_io;
+mod copy;
+mod duplex;
+mod faulty;
+mod idle_timeout;
+#[cfg(feature = "tracing")]
+mod instrumented;
+mod lines;
+mod metered;
+mod mock;
+mod rate_limited;
+mod read_buf;
+mod stdio;
+
+pub use self::buf_reader::BufReader;
+pub use self::buf_stream::BufStream;
+pub use self::buf_writer::BufWriter;
+#[cfg(feature = "tokio")]
+pub use self::compat::Compat;
+pub use self::copy::{copy, copy_bidirectional, copy_buf};
+pub use self::duplex::{duplex, DuplexStream};
+pub use self::faulty::Faulty;
+pub use self::idle_timeout::IdleTimeout;
+#[cfg(feature = "tracing")]
+pub use self::instrumented::Instrumented;
+pub use self::lines::LinesStream;
+pub use self::metered::{IoCounters, Metered};
+pub use self::mock::{MockStream, MockStreamBuilder};
+pub use self::rate_limited::RateLimited;
+pub(crate) use self::read_buf::poll_read_uninit;
+pub use self::read_buf::ReadBuf;
+pub use self::stdio::{stderr, stdin, stdout, Stderr, Stdin, Stdout};
@@ -0,0 +1,190 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::time::{self, sleep_until, Sleep};
+
+// caps the bytes per second read from and written to the inner stream,
+// each direction with its own token bucket. The buckets hold a second's
+// worth of bytes, so an idle connection may burst that much at once
+#[derive(Debug)]
+pub struct RateLimited<T> {
+    inner: T,
+    read: Bucket,
+    write: Bucket,
+}
+
+impl<T> RateLimited<T> {
+    // the same limit for both directions
+    pub fn new(inner: T, bytes_per_sec: u64) -> RateLimited<T> {
+        RateLimited::with_rates(inner, bytes_per_sec, bytes_per_sec)
+    }
+
+    pub fn with_rates(inner: T, read_per_sec: u64, write_per_sec: u64) -> RateLimited<T> {
+        RateLimited {
+            inner,
+            read: Bucket::new(read_per_sec),
+            write: Bucket::new(write_per_sec),
+        }
+    }
+
+    pub fn read_rate(&self) -> u64 {
+        self.read.rate
+    }
+
+    pub fn write_rate(&self) -> u64 {
+        self.write.rate
+    }
+
+    // takes effect on the next read, the bucket keeps its tokens
+    pub fn set_read_rate(&mut self, bytes_per_sec: u64) {
+        self.read.set_rate(bytes_per_sec);
+    }
+
+    pub fn set_write_rate(&mut self, bytes_per_sec: u64) {
+        self.write.set_rate(bytes_per_sec);
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    tokens: f64,
+    refilled: Instant,
+    // waits for the bucket to fill up again
+    sleep: Option<Sleep>,
+}
+
+impl Bucket {
+    fn new(rate: u64) -> Bucket {
+        assert!(rate > 0, "rate must be non-zero");
+
+        Bucket {
+            rate,
+            tokens: rate as f64,
+            refilled: time::now(),
+            sleep: None,
+        }
+    }
+
+    fn set_rate(&mut self, rate: u64) {
+        assert!(rate > 0, "rate must be non-zero");
+
+        self.refill();
+        self.rate = rate;
+        self.tokens = self.tokens.min(rate as f64);
+        self.sleep = None;
+    }
+
+    fn refill(&mut self) {
+        let now = time::now();
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        self.refilled = now;
+    }
+
+    // how many bytes the next io call may move, at most want
+    fn poll_take(&mut self, ctx: &mut Context, want: usize) -> Poll<usize> {
+        // waiting for single bytes would mean a syscall per byte, wait for
+        // a 20th of a second's worth instead
+        let chunk = (self.rate / 20).max(1).min(want as u64) as f64;
+
+        loop {
+            self.refill();
+            if self.tokens >= chunk {
+                self.sleep = None;
+                return Poll::Ready((self.tokens as usize).min(want));
+            }
+
+            let wait = Duration::from_secs_f64((chunk - self.tokens) / self.rate as f64);
+            let deadline = time::now() + wait;
+            let sleep = self.sleep.get_or_insert_with(|| sleep_until(deadline));
+            if sleep.deadline() != deadline {
+                sleep.reset(deadline);
+            }
+
+            match Pin::new(sleep).poll(ctx) {
+                Poll::Ready(()) => continue,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn spend(&mut self, n: usize) {
+        self.tokens -= n as f64;
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for RateLimited<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_read(ctx, buf);
+        }
+
+        let n = match this.read.poll_take(ctx, buf.len()) {
+            Poll::Ready(n) => n,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let res = Pin::new(&mut this.inner).poll_read(ctx, &mut buf[..n]);
+        if let Poll::Ready(Ok(n)) = res {
+            this.read.spend(n);
+        }
+        res
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for RateLimited<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(ctx, buf);
+        }
+
+        let n = match this.write.poll_take(ctx, buf.len()) {
+            Poll::Ready(n) => n,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        let res = Pin::new(&mut this.inner).poll_write(ctx, &buf[..n]);
+        if let Poll::Ready(Ok(n)) = res {
+            this.write.spend(n);
+        }
+        res
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_flush(ctx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Pin::new(&mut self.inner).poll_close(ctx)
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::io::RawFd;
+use std::task::{Context, Poll};
+
+use libc::c_void;
+
+use crate::REACTOR;
+
+// a read target that may start out uninitialized, so big read buffers
+// don't have to be zeroed before every call. Tracks three regions:
+// filled (read so far) <= initialized <= capacity
+pub struct ReadBuf<'a> {
+    buf: &'a mut [MaybeUninit<u8>],
+    filled: usize,
+    initialized: usize,
+}
+
+impl<'a> ReadBuf<'a> {
+    // over memory that's already initialized
+    pub fn new(buf: &'a mut [u8]) -> ReadBuf<'a> {
+        let initialized = buf.len();
+        let buf = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+
+        ReadBuf {
+            buf,
+            filled: 0,
+            initialized,
+        }
+    }
+
+    // over memory that may not be initialized, e.g. Vec::spare_capacity_mut()
+    pub fn uninit(buf: &'a mut [MaybeUninit<u8>]) -> ReadBuf<'a> {
+        ReadBuf {
+            buf,
+            filled: 0,
+            initialized: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.capacity() - self.filled
+    }
+
+    pub fn filled(&self) -> &[u8] {
+        unsafe { &*(&self.buf[..self.filled] as *const [MaybeUninit<u8>] as *const [u8]) }
+    }
+
+    pub fn filled_mut(&mut self) -> &mut [u8] {
+        unsafe { &mut *(&mut self.buf[..self.filled] as *mut [MaybeUninit<u8>] as *mut [u8]) }
+    }
+
+    pub fn initialized(&self) -> &[u8] {
+        unsafe { &*(&self.buf[..self.initialized] as *const [MaybeUninit<u8>] as *const [u8]) }
+    }
+
+    // zero whatever of the unfilled part isn't initialized yet and return
+    // all of it
+    pub fn initialize_unfilled(&mut self) -> &mut [u8] {
+        self.initialize_unfilled_to(self.remaining())
+    }
+
+    // the first n unfilled bytes, initialized
+    pub fn initialize_unfilled_to(&mut self, n: usize) -> &mut [u8] {
+        assert!(self.remaining() >= n, "n overflows remaining");
+
+        let end = self.filled + n;
+        if self.initialized < end {
+            for byte in &mut self.buf[self.initialized..end] {
+                *byte = MaybeUninit::new(0);
+            }
+            self.initialized = end;
+        }
+
+        unsafe { &mut *(&mut self.buf[self.filled..end] as *mut [MaybeUninit<u8>] as *mut [u8]) }
+    }
+
+    /// The unfilled part as it is.
+    ///
+    /// # Safety
+    /// The caller must not write uninitialized bytes into it.
+    pub unsafe fn unfilled_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        &mut self.buf[self.filled..]
+    }
+
+    /// Mark the first n unfilled bytes as initialized.
+    ///
+    /// # Safety
+    /// The caller must have written those bytes.
+    pub unsafe fn assume_init(&mut self, n: usize) {
+        let end = self.filled + n;
+        if end > self.initialized {
+            self.initialized = end;
+        }
+    }
+
+    // n more bytes were filled, they have to be initialized already
+    pub fn advance(&mut self, n: usize) {
+        let filled = self.filled.checked_add(n).expect("filled overflow");
+        self.set_filled(filled);
+    }
+
+    pub fn set_filled(&mut self, n: usize) {
+        assert!(
+            n <= self.initialized,
+            "filled must not become larger than initialized"
+        );
+        self.filled = n;
+    }
+
+    // forget the filled bytes, they stay initialized
+    pub fn clear(&mut self) {
+        self.filled = 0;
+    }
+
+    pub fn put_slice(&mut self, src: &[u8]) {
+        assert!(self.remaining() >= src.len(), "src overflows remaining");
+
+        let end = self.filled + src.len();
+        for (dst, src) in self.buf[self.filled..end].iter_mut().zip(src) {
+            *dst = MaybeUninit::new(*src);
+        }
+        if end > self.initialized {
+            self.initialized = end;
+        }
+        self.filled = end;
+    }
+}
+
+impl fmt::Debug for ReadBuf<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ReadBuf")
+            .field("filled", &self.filled)
+            .field("initialized", &self.initialized)
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+// read(2) into the unfilled part of buf, for the socket types
+pub(crate) fn poll_read_uninit(
+    fd: RawFd,
+    ctx: &mut Context,
+    buf: &mut ReadBuf,
+) -> Poll<Result<usize, io::Error>> {
+    let unfilled = unsafe { buf.unfilled_mut() };
+    if unfilled.is_empty() {
+        return Poll::Ready(Ok(0));
+    }
+
+    let n = unsafe { libc::read(fd, unfilled.as_mut_ptr() as *mut c_void, unfilled.len()) };
+    if n < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::WouldBlock {
+            REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+            return Poll::Pending;
+        }
+        return Poll::Ready(Err(err));
+    }
+
+    let n = n as usize;
+    unsafe { buf.assume_init(n) };
+    buf.advance(n);
+    Poll::Ready(Ok(n))
+}
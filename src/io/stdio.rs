@@ -0,0 +1,347 @@
+// async stdin/stdout/stderr. The descriptors are shared with the parent
+// process, so their flags are left alone. For pipes and sockets we wait for
+// readiness and then do a plain read or write that won't block. Everything
+// else (regular files, /dev/null, ttys) either always polls ready or may
+// block anyway, so those reads and writes run on the blocking pool
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use libc::c_void;
+use log::debug;
+
+use crate::blocking::{spawn_blocking, JoinHandle};
+use crate::interest::{self, Interest};
+
+// a pipe that polls writable has room for at least this much, bigger
+// writes could block
+const PIPE_BUF: usize = 4096;
+
+// most bytes moved by one job on the blocking pool
+const MAX_BUF: usize = 64 * 1024;
+
+pub fn stdin() -> Stdin {
+    Stdin {
+        pool: Pool::unless_pollable(libc::STDIN_FILENO),
+    }
+}
+
+pub fn stdout() -> Stdout {
+    Stdout {
+        pool: Pool::unless_pollable(libc::STDOUT_FILENO),
+    }
+}
+
+pub fn stderr() -> Stderr {
+    Stderr {
+        pool: Pool::unless_pollable(libc::STDERR_FILENO),
+    }
+}
+
+// unbuffered, wrap it in a reader that buffers to read lines. On the pool a
+// read may be in flight, dropping the Stdin then loses what it reads
+#[derive(Debug)]
+pub struct Stdin {
+    pool: Option<Pool>,
+}
+
+// unbuffered, so output doesn't get stuck. On the pool writes finish in the
+// background, their errors come out of the next write or flush. Don't mix
+// it with println!, std's own buffer may reorder the two
+#[derive(Debug)]
+pub struct Stdout {
+    pool: Option<Pool>,
+}
+
+#[derive(Debug)]
+pub struct Stderr {
+    pool: Option<Pool>,
+}
+
+// how many bytes a job read or wrote, and its buffer back
+type Done = (Result<usize, io::Error>, Vec<u8>);
+
+// reads and writes of a descriptor select can't wait for, one job at a time
+#[derive(Default)]
+struct Pool {
+    job: Option<JoinHandle<Done>>,
+    // after a read, buf[pos..] hasn't been handed out yet
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Pool {
+    // None for pipes and sockets
+    fn unless_pollable(fd: RawFd) -> Option<Pool> {
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } == 0 {
+            let kind = stat.st_mode & libc::S_IFMT;
+            if kind == libc::S_IFIFO || kind == libc::S_IFSOCK {
+                return None;
+            }
+        }
+        Some(Pool::default())
+    }
+
+    // wait for the job in flight, if any
+    fn poll_job(&mut self, ctx: &mut Context) -> Poll<Result<usize, io::Error>> {
+        let job = match self.job {
+            Some(ref mut job) => job,
+            None => return Poll::Ready(Ok(0)),
+        };
+
+        let res = match Pin::new(job).poll(ctx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+        self.job = None;
+
+        let (res, buf) = res?;
+        self.buf = buf;
+        Poll::Ready(res)
+    }
+
+    fn poll_read(
+        &mut self,
+        fd: RawFd,
+        ctx: &mut Context,
+        dst: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        loop {
+            if self.pos < self.buf.len() {
+                let n = dst.len().min(self.buf.len() - self.pos);
+                dst[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+                self.pos += n;
+                return Poll::Ready(Ok(n));
+            }
+
+            if self.job.is_some() {
+                self.pos = 0;
+                match self.poll_job(ctx) {
+                    Poll::Ready(Ok(0)) => return Poll::Ready(Ok(0)),
+                    Poll::Ready(Ok(_)) => continue,
+                    other => return other,
+                }
+            }
+
+            let len = dst.len().min(MAX_BUF);
+            if len == 0 {
+                return Poll::Ready(Ok(0));
+            }
+
+            let mut buf = mem::take(&mut self.buf);
+            self.job = Some(spawn_blocking(move || {
+                buf.resize(len, 0);
+                let res = read(fd, &mut buf);
+                buf.truncate(*res.as_ref().unwrap_or(&0));
+                (res, buf)
+            }));
+        }
+    }
+
+    // the write before this one has to be done first, its error is ours
+    fn poll_write(
+        &mut self,
+        fd: RawFd,
+        ctx: &mut Context,
+        src: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        match self.poll_job(ctx) {
+            Poll::Ready(Ok(_)) => {}
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let n = src.len().min(MAX_BUF);
+        let mut buf = mem::take(&mut self.buf);
+        buf.clear();
+        buf.extend_from_slice(&src[..n]);
+
+        self.job = Some(spawn_blocking(move || {
+            let res = write_all(fd, &buf).map(|()| buf.len());
+            (res, buf)
+        }));
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(&mut self, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        self.poll_job(ctx).map_ok(|_| ())
+    }
+}
+
+impl fmt::Debug for Pool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("busy", &self.job.is_some())
+            .field("buffered", &(self.buf.len() - self.pos))
+            .finish()
+    }
+}
+
+// blocking read, only called on the pool
+fn read(fd: RawFd, buf: &mut [u8]) -> Result<usize, io::Error> {
+    loop {
+        let rv = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+        if rv == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        return Ok(rv as usize);
+    }
+}
+
+// blocking write, only called on the pool
+fn write_all(fd: RawFd, mut buf: &[u8]) -> Result<(), io::Error> {
+    while !buf.is_empty() {
+        let rv = unsafe { libc::write(fd, buf.as_ptr() as *const c_void, buf.len()) };
+        match rv {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => buf = &buf[n as usize..],
+        }
+    }
+    Ok(())
+}
+
+fn poll_read(fd: RawFd, ctx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize, io::Error>> {
+    loop {
+        match interest::poll_ready(fd, Interest::READABLE, ctx) {
+            Poll::Ready(Ok(_)) => {}
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let rv = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
+        if rv == -1 {
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                // somebody else got the data first, or the fd is non-blocking
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => continue,
+                _ => return Poll::Ready(Err(err)),
+            }
+        }
+
+        return Poll::Ready(Ok(rv as usize));
+    }
+}
+
+fn poll_write(fd: RawFd, ctx: &mut Context, buf: &[u8]) -> Poll<Result<usize, io::Error>> {
+    let len = buf.len().min(PIPE_BUF);
+
+    loop {
+        match interest::poll_ready(fd, Interest::WRITABLE, ctx) {
+            Poll::Ready(Ok(_)) => {}
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        let rv = unsafe { libc::write(fd, buf.as_ptr() as *const c_void, len) };
+        if rv == -1 {
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => continue,
+                _ => return Poll::Ready(Err(err)),
+            }
+        }
+
+        return Poll::Ready(Ok(rv as usize));
+    }
+}
+
+impl AsyncRead for Stdin {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read() called");
+        match self.get_mut().pool {
+            Some(ref mut pool) => pool.poll_read(libc::STDIN_FILENO, ctx, buf),
+            None => poll_read(libc::STDIN_FILENO, ctx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Stdout {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write() called");
+        match self.get_mut().pool {
+            Some(ref mut pool) => pool.poll_write(libc::STDOUT_FILENO, ctx, buf),
+            None => poll_write(libc::STDOUT_FILENO, ctx, buf),
+        }
+    }
+
+    // waits for the write running on the pool
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        match self.get_mut().pool {
+            Some(ref mut pool) => pool.poll_flush(ctx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    // stdout belongs to the process, it is never closed
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        self.poll_flush(ctx)
+    }
+}
+
+impl AsyncWrite for Stderr {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write() called");
+        match self.get_mut().pool {
+            Some(ref mut pool) => pool.poll_write(libc::STDERR_FILENO, ctx, buf),
+            None => poll_write(libc::STDERR_FILENO, ctx, buf),
+        }
+    }
+
+    // waits for the write running on the pool
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        match self.get_mut().pool {
+            Some(ref mut pool) => pool.poll_flush(ctx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        self.poll_flush(ctx)
+    }
+}
+
+impl AsRawFd for Stdin {
+    fn as_raw_fd(&self) -> RawFd {
+        libc::STDIN_FILENO
+    }
+}
+
+impl AsRawFd for Stdout {
+    fn as_raw_fd(&self) -> RawFd {
+        libc::STDOUT_FILENO
+    }
+}
+
+impl AsRawFd for Stderr {
+    fn as_raw_fd(&self) -> RawFd {
+        libc::STDERR_FILENO
+    }
+}
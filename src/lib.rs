@@ -1,23 +1,122 @@
-use log::debug;
+use log::{debug, warn};
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll, Waker};
 
 use futures_task::{ArcWake, FutureObj};
-use libc::{fd_set, select, timeval, FD_ISSET, FD_SET, FD_ZERO};
+use libc::{fd_set, select, timeval, FD_ISSET, FD_SET, FD_SETSIZE, FD_ZERO};
 
-use std::os::unix::io::RawFd;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::panic::Location;
 
 use std::cell::{Cell, RefCell};
 use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 use std::rc::Rc;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
 
+use crate::rng::Rng;
+use crate::task_trace::{TaskSpan, WakeId};
+
+mod async_fd;
 mod async_tcp_listener;
 mod async_tcp_stream;
-
-pub use crate::async_tcp_listener::AsyncTcpListener;
+mod async_udp_socket;
+mod async_unix_datagram;
+mod async_unix_listener;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod async_unix_seqpacket;
+mod async_unix_stream;
+mod blocking;
+pub mod codec;
+pub mod dns;
+mod dump;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod eventfd;
+pub mod fs;
+#[cfg(feature = "hyper")]
+mod hyper_rt;
+pub mod icmp;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod inotify;
+pub mod io;
+mod interest;
+mod loop_metrics;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod netlink;
+#[cfg(feature = "quinn")]
+mod quinn_runtime;
+pub mod process;
+mod raw_socket;
+mod registration;
+mod rng;
+mod runtime_metrics;
+mod seeded;
+pub mod signal;
+#[cfg(feature = "sim")]
+pub mod sim;
+mod splice;
+mod steps;
+pub mod sync;
+mod sys;
+mod task_trace;
+mod tcp_socket;
+mod test_runner;
+pub mod time;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "tower")]
+mod tower_serve;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod vsock;
+#[cfg(target_os = "linux")]
+mod zerocopy;
+
+pub use crate::async_fd::{AsyncFd, AsyncFdReadyGuard, TryIoError};
+pub use crate::async_tcp_listener::{
+    AsyncTcpListener, ConnectionPermit, Incoming, LimitedIncoming, RateLimitedIncoming,
+};
 pub use crate::async_tcp_stream::AsyncTcpStream;
+#[cfg(target_os = "linux")]
+pub use crate::async_tcp_stream::TcpInfo;
+pub use crate::async_udp_socket::AsyncUdpSocket;
+pub use crate::async_unix_datagram::AsyncUnixDatagram;
+pub use crate::async_unix_listener::{AsyncUnixListener, UnixIncoming};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::async_unix_seqpacket::{AsyncUnixSeqpacket, AsyncUnixSeqpacketListener};
+pub use crate::async_unix_stream::{AsyncUnixStream, UCred};
+pub use crate::blocking::{spawn_blocking, JoinHandle};
+pub use crate::dump::{dump, dump_on_sigusr1, Dump, DumpOnSigusr1, TaskDump, WaitingOn};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::eventfd::AsyncEventFd;
+#[cfg(feature = "hyper")]
+pub use crate::hyper_rt::{FahrenheitExecutor, FahrenheitTimer, HyperIo};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::inotify::{AsyncInotify, InotifyEvent, WatchDescriptor};
+pub use crate::interest::{Interest, Ready};
+pub use crate::loop_metrics::{metrics, Histogram, RuntimeMetrics};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::netlink::{AsyncNetlinkSocket, NetlinkAddr};
+#[cfg(feature = "quinn")]
+pub use crate::quinn_runtime::FahrenheitRuntime;
+pub use crate::raw_socket::AsyncRawSocket;
+pub use crate::registration::Registration;
+pub use crate::seeded::{explore, run_seeded};
+pub use crate::splice::splice_bidirectional;
+pub use crate::steps::{run_steps, Incomplete};
+pub use crate::tcp_socket::TcpSocket;
+#[doc(hidden)]
+pub use crate::test_runner::run_test as __run_test;
+pub use fahrenheit_macros::test;
+#[cfg(feature = "tower")]
+pub use crate::tower_serve::{serve, Server};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use crate::vsock::{AsyncVsockListener, AsyncVsockStream, VsockAddr};
 
 // reactor lives in a thread local variable. Here's where all magic happens!
 thread_local! {
@@ -26,51 +125,216 @@ thread_local! {
 
 type TaskId = usize;
 
+// timers are ordered by deadline, the id tells apart timers with equal deadlines
+type TimerKey = (Instant, usize);
+
+// panics if the event loop fails, see try_run
+#[track_caller]
 pub fn run<F: Future<Output = ()> + Send + 'static>(f: F) {
-    REACTOR.with(|reactor| reactor.run(f))
+    let loc = Location::caller();
+    REACTOR.with(|reactor| {
+        if let Err(err) = reactor.run(f, loc, None) {
+            panic!("event loop failed: {}", err);
+        }
+    })
 }
 
+// like run, but returns select's errors and fds select can't watch instead
+// of panicking. The tasks left are dropped, so the reactor can run again
+#[track_caller]
+pub fn try_run<F: Future<Output = ()> + Send + 'static>(f: F) -> Result<(), std::io::Error> {
+    let loc = Location::caller();
+    REACTOR.with(|reactor| match reactor.run(f, loc, None) {
+        Ok(_) => Ok(()),
+        Err(err) => {
+            debug!("event loop failed: {}", err);
+            reactor.abandon();
+            Err(err)
+        }
+    })
+}
+
+#[track_caller]
 pub fn spawn<F: Future<Output = ()> + Send + 'static>(f: F) {
-    REACTOR.with(|reactor| reactor.do_spawn(f))
+    let loc = Location::caller();
+    REACTOR.with(|reactor| reactor.do_spawn(f, None, loc))
+}
+
+// like spawn, the name shows up in task dumps and traces
+#[track_caller]
+pub fn spawn_named<F: Future<Output = ()> + Send + 'static>(name: &str, f: F) {
+    let loc = Location::caller();
+    REACTOR.with(|reactor| reactor.do_spawn(f, Some(name.to_owned()), loc))
+}
+
+// warn about task polls that take longer than threshold, None turns it
+// off. Every other task on this thread waits while one is polled, so a
+// slow poll is usually a blocking call in async code. Defaults to 100ms
+pub fn set_slow_poll_threshold(threshold: Option<Duration>) {
+    REACTOR.with(|reactor| reactor.slow_poll.set(threshold))
 }
 
 // Our waker Token. It stores the index of the future in the wait queue
-// (see below)
-#[derive(Debug)]
-struct Token(usize);
+// (see below), how to reach its reactor from other threads and what
+// tracing knows the task as
+struct Token(usize, Arc<Remote>, WakeId);
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Token({})", self.0)
+    }
+}
 
 impl ArcWake for Token {
     fn wake_by_ref(arc_self: &Arc<Self>) {
         debug!("waking {:?}", arc_self);
 
-        let Token(idx) = **arc_self;
+        let Token(idx, ref remote, wake_id) = **arc_self;
+        wake_id.woken();
+
+        // the reactor belongs to another thread
+        if thread::current().id() != remote.thread {
+            remote.wake(idx);
+            return;
+        }
 
         // get access to the reactor by way of TLS and call wake
         REACTOR.with(|reactor| {
             let wakeup = Wakeup {
                 index: idx,
                 waker: futures_task::waker(arc_self.clone()),
+                woken: Instant::now(),
             };
             reactor.wake(wakeup);
         });
     }
 }
 
-// Wakeup notification struct stores the index of the future in the wait queue
-// and waker
+// wakeups from other threads. They can't touch the reactor's run queue, so
+// the task index is queued here and a byte written to the pipe makes select
+// return and pick it up. The pipe is opened by the first run, so building
+// the reactor can't fail and try_run gets to report it
+#[derive(Debug)]
+struct Remote {
+    thread: ThreadId,
+    queue: Mutex<Vec<(TaskId, Instant)>>,
+    // read and write ends
+    pipe: OnceLock<(OwnedFd, OwnedFd)>,
+    // see PendingWork
+    pending: AtomicUsize,
+}
+
+impl Remote {
+    fn new() -> Remote {
+        Remote {
+            thread: thread::current().id(),
+            queue: Mutex::new(Vec::new()),
+            pipe: OnceLock::new(),
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    // only the reactor's thread opens the pipe, returns its read end
+    fn open(&self) -> Result<RawFd, std::io::Error> {
+        if let Some((read, _)) = self.pipe.get() {
+            return Ok(read.as_raw_fd());
+        }
+
+        let (read, write) = sys::pipe()?;
+        let fd = read.as_raw_fd();
+        let _ = self.pipe.set((read, write));
+
+        // wakes queued before there was a pipe to write to
+        if !self.queue.lock().unwrap_or_else(|err| err.into_inner()).is_empty() {
+            self.notify();
+        }
+        Ok(fd)
+    }
+
+    fn wake(&self, idx: TaskId) {
+        let mut queue = self.queue.lock().unwrap_or_else(|err| err.into_inner());
+        let notify = queue.is_empty();
+        queue.push((idx, Instant::now()));
+        drop(queue);
+
+        // one byte per batch is enough, a full pipe will wake select anyway
+        if notify {
+            self.notify();
+        }
+    }
+
+    fn notify(&self) {
+        if let Some((_, write)) = self.pipe.get() {
+            let byte = 1u8;
+            unsafe { libc::write(write.as_raw_fd(), &byte as *const u8 as *const _, 1) };
+        }
+    }
+
+    fn drain(&self) -> Vec<(TaskId, Instant)> {
+        if let Some((read, _)) = self.pipe.get() {
+            let mut buf = [0u8; 64];
+            while unsafe { libc::read(read.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) } > 0 {}
+        }
+
+        std::mem::take(&mut *self.queue.lock().unwrap_or_else(|err| err.into_inner()))
+    }
+}
+
+// work started from a reactor thread that finishes elsewhere: blocking
+// pool jobs and io_uring ops. Held until the result is in, while any is
+// held a paused clock doesn't jump to the next timer, the timers may be
+// racing the result
+pub(crate) struct PendingWork(Option<Arc<Remote>>);
+
+impl PendingWork {
+    pub(crate) fn new() -> PendingWork {
+        let remote = REACTOR.try_with(|reactor| reactor.remote.clone()).ok();
+        if let Some(remote) = &remote {
+            remote.pending.fetch_add(1, Ordering::SeqCst);
+        }
+        PendingWork(remote)
+    }
+}
+
+impl Drop for PendingWork {
+    fn drop(&mut self) {
+        if let Some(remote) = &self.0 {
+            // the reactor may be waiting in select for this
+            let last = remote.pending.fetch_sub(1, Ordering::SeqCst) == 1;
+            if last && thread::current().id() != remote.thread {
+                remote.notify();
+            }
+        }
+    }
+}
+
+// Wakeup notification struct stores the index of the future in the wait queue,
+// waker and when it was woken
 struct Wakeup {
     index: usize,
     waker: Waker,
+    woken: Instant,
 }
 
 // Task is a boxed future with Output = ()
 struct Task {
     future: FutureObj<'static, ()>,
+    span: TaskSpan,
+    // what a dump tells about it
+    name: Option<String>,
+    location: &'static Location<'static>,
+    spawned: Instant,
+    last_poll: Instant,
+    polls: u64,
 }
 
 impl Task {
     // returning Ready will lead to task being removed from wait queues and dropped
     fn poll(&mut self, waker: Waker) -> Poll<()> {
+        let _enter = self.span.enter();
+        runtime_metrics::task_polled();
+        self.last_poll = Instant::now();
+        self.polls += 1;
         let future = Pin::new(&mut self.future);
         let mut ctx = Context::from_waker(&waker);
 
@@ -87,13 +351,42 @@ impl Task {
     }
 }
 
+// a registered waker and the task that was being polled when it was
+// registered, so a dump can tell what each task is waiting on
+struct Waiter {
+    task: Option<TaskId>,
+    waker: Waker,
+}
+
+// register a waker for one fd and direction. A waker for the same task
+// replaces the one it registered before, wakers of other tasks are kept
+fn add_waker(wakers: &mut Vec<Waiter>, waiter: Waiter) {
+    match wakers.iter_mut().find(|w| w.waker.will_wake(&waiter.waker)) {
+        Some(old) => *old = waiter,
+        None => wakers.push(waiter),
+    }
+}
+
 // The "real" event loop.
+//
+// read and write map a descriptor to the wakers of all tasks waiting on it.
+// interests are one-shot: once select reports the fd ready, its wakers are
+// removed and woken, and a task that still can't make progress registers again
 struct EventLoop {
-    read: RefCell<BTreeMap<RawFd, Waker>>,
-    write: RefCell<BTreeMap<RawFd, Waker>>,
+    read: RefCell<BTreeMap<RawFd, Vec<Waiter>>>,
+    write: RefCell<BTreeMap<RawFd, Vec<Waiter>>>,
+    timers: RefCell<BTreeMap<TimerKey, Waiter>>,
+    timer_counter: Cell<usize>,
     counter: Cell<usize>,
+    // the task being polled right now
+    polling: Cell<Option<TaskId>>,
     wait_queue: RefCell<BTreeMap<TaskId, Task>>,
     run_queue: RefCell<VecDeque<Wakeup>>,
+    remote: Arc<Remote>,
+    metrics: RefCell<RuntimeMetrics>,
+    slow_poll: Cell<Option<Duration>>,
+    // set by run_seeded, shuffles each iteration's polls
+    shuffle: RefCell<Option<Rng>>,
 }
 
 impl EventLoop {
@@ -101,9 +394,16 @@ impl EventLoop {
         EventLoop {
             read: RefCell::new(BTreeMap::new()),
             write: RefCell::new(BTreeMap::new()),
+            timers: RefCell::new(BTreeMap::new()),
+            timer_counter: Cell::new(0),
             counter: Cell::new(0),
+            polling: Cell::new(None),
             wait_queue: RefCell::new(BTreeMap::new()),
             run_queue: RefCell::new(VecDeque::new()),
+            remote: Arc::new(Remote::new()),
+            metrics: RefCell::new(RuntimeMetrics::default()),
+            slow_poll: Cell::new(Some(Duration::from_millis(100))),
+            shuffle: RefCell::new(None),
         }
     }
 
@@ -112,9 +412,16 @@ impl EventLoop {
     fn add_read_interest(&self, fd: RawFd, waker: Waker) {
         debug!("adding read interest for {}", fd);
 
-        if !self.read.borrow().contains_key(&fd) {
-            self.read.borrow_mut().insert(fd, waker);
-        }
+        let waiter = self.waiter(waker);
+        add_waker(self.read.borrow_mut().entry(fd).or_default(), waiter);
+    }
+
+    // an interest no task answers for, finishing tasks leave it alone
+    fn add_detached_read_interest(&self, fd: RawFd, waker: Waker) {
+        debug!("adding detached read interest for {}", fd);
+
+        let waiter = Waiter { task: None, waker };
+        add_waker(self.read.borrow_mut().entry(fd).or_default(), waiter);
     }
 
     fn remove_read_interest(&self, fd: RawFd) {
@@ -133,43 +440,222 @@ impl EventLoop {
     fn add_write_interest(&self, fd: RawFd, waker: Waker) {
         debug!("adding write interest for {}", fd);
 
-        if !self.write.borrow().contains_key(&fd) { //fd应该是可比较的，所以直接添加就行，btreemap不会重复添加元素，这里的contains检查多此一举
-            self.write.borrow_mut().insert(fd, waker);
+        let waiter = self.waiter(waker);
+        add_waker(self.write.borrow_mut().entry(fd).or_default(), waiter);
+    }
+
+    // wake the task when deadline passes. The returned key removes the timer
+    fn add_timer(&self, deadline: Instant, waker: Waker) -> TimerKey {
+        let id = self.timer_counter.get();
+        self.timer_counter.set(id + 1);
+
+        debug!("adding timer #{}", id);
+
+        let key = (deadline, id);
+        let waiter = self.waiter(waker);
+        self.timers.borrow_mut().insert(key, waiter);
+        key
+    }
+
+    fn waiter(&self, waker: Waker) -> Waiter {
+        Waiter {
+            task: self.polling.get(),
+            waker,
+        }
+    }
+
+    fn remove_timer(&self, key: TimerKey) {
+        debug!("removing timer #{}", key.1);
+
+        self.timers.borrow_mut().remove(&key);
+    }
+
+    // how long select may block: until the nearest timer, but no longer
+    // than a second so the loop keeps iterating. Tasks that yielded are
+    // already waiting in the run queue, then select only checks the fds.
+    // With the clock paused timers don't need waiting for, see skip_time
+    fn next_timeout(&self) -> Duration {
+        let max = Duration::from_secs(1);
+
+        if !self.run_queue.borrow().is_empty() {
+            return Duration::ZERO;
+        }
+
+        match self.timers.borrow().keys().next() {
+            Some(_) if time::is_paused() && self.pending_work() => max,
+            Some(_) if time::is_paused() => Duration::ZERO,
+            Some((deadline, _)) => {
+                std::cmp::min(max, deadline.saturating_duration_since(time::now()))
+            }
+            None => max,
+        }
+    }
+
+    // a paused clock jumps to the nearest timer once select found nothing
+    // ready, no task is waiting to run and no work is out on the blocking
+    // pool or io_uring
+    fn skip_time(&self) {
+        if !self.run_queue.borrow().is_empty() || self.pending_work() {
+            return;
+        }
+        if let Some(&(deadline, _)) = self.timers.borrow().keys().next() {
+            time::skip_to(deadline);
+        }
+    }
+
+    fn pending_work(&self) -> bool {
+        self.remote.pending.load(Ordering::SeqCst) > 0
+    }
+
+    // wake the tasks of all expired timers
+    fn fire_timers(&self) {
+        let now = time::now();
+
+        loop {
+            let expired = match self.timers.borrow().keys().next() {
+                Some(&key) if key.0 <= now => key,
+                _ => break,
+            };
+
+            debug!("timer #{} fired", expired.1);
+            let waiter = self.timers.borrow_mut().remove(&expired);
+            if let Some(waiter) = waiter {
+                waiter.waker.wake();
+            }
         }
     }
 
     // waker calls this to put the future on the run queue
     fn wake(&self, wakeup: Wakeup) {
+        runtime_metrics::task_woken();
         self.run_queue.borrow_mut().push_back(wakeup);
     }
 
-    fn next_task(&self) -> (TaskId, Waker) {
+    fn next_task(&self) -> TaskId {
         let counter = self.counter.get();
-        let w = Arc::new(Token(counter));
         self.counter.set(counter + 1);
-        (counter, futures_task::waker(w))
+        counter
+    }
+
+    fn waker(&self, id: TaskId, wake_id: WakeId) -> Waker {
+        futures_task::waker(Arc::new(Token(id, self.remote.clone(), wake_id)))
     }
 
     // create a task, poll it once and push it on wait queue
-    fn do_spawn<F: Future<Output = ()> + Send + 'static>(&self, f: F) {
-        let (id, waker) = self.next_task();
+    fn do_spawn<F: Future<Output = ()> + Send + 'static>(
+        &self,
+        f: F,
+        name: Option<String>,
+        loc: &'static Location<'static>,
+    ) {
+        let id = self.next_task();
+        runtime_metrics::task_spawned();
+        let span = TaskSpan::new(id, name.as_deref(), loc);
+        let waker = self.waker(id, span.wake_id());
         let f = Box::new(f);
+        let now = Instant::now();
         let mut task = Task {
             future: FutureObj::new(f),
+            span,
+            name,
+            location: loc,
+            spawned: now,
+            last_poll: now,
+            polls: 0,
         };
 
         // if the task is ready immediately, don't add it to wait_queue
-        if let Poll::Ready(_) = task.poll(waker) {
+        if self.poll_task(id, &mut task, waker).is_ready() {
+            self.finish(id, task);
             return;
         }
 
         self.wait_queue.borrow_mut().insert(id, task);
     }
 
+    // interests registered during the poll are put down to the task. A
+    // task spawned from another one is polled inside the parent's poll
+    fn poll_task(&self, id: TaskId, task: &mut Task, waker: Waker) -> Poll<()> {
+        let parent = self.polling.replace(Some(id));
+        let res = task.poll(waker);
+        self.polling.set(parent);
+
+        // a child polled inside its parent's poll counts for both
+        let took = task.last_poll.elapsed();
+        if self.slow_poll.get().is_some_and(|max| took > max) {
+            self.metrics.borrow_mut().slow_polls += 1;
+            warn!(
+                "task#{} {:?} spawned at {} was polled for {:?}, blocking the event loop",
+                id,
+                task.name.as_deref().unwrap_or(""),
+                task.location,
+                took
+            );
+        }
+        res
+    }
+
+    // drop a finished task, then remove the interests it registered that
+    // never fired. Their fd was usually moved out of the task or leaked with
+    // mem::forget, and the wakers would wake a task that's gone every time
+    // the fd gets ready
+    fn finish(&self, id: TaskId, task: Task) {
+        let Task {
+            future,
+            name,
+            location,
+            ..
+        } = task;
+        // whatever the future still owns deregisters itself here
+        drop(future);
+
+        let owned = |w: &Waiter| w.task == Some(id);
+        let name = name.unwrap_or_default();
+
+        for (map, direction) in [(&self.read, "read"), (&self.write, "write")] {
+            map.borrow_mut().retain(|fd, waiters| {
+                if waiters.iter().any(owned) {
+                    warn!(
+                        "task#{} {:?} spawned at {} finished with {} interest in fd#{}, removing it",
+                        id, name, location, direction, fd
+                    );
+                    waiters.retain(|w| !owned(w));
+                }
+                !waiters.is_empty()
+            });
+        }
+
+        self.timers.borrow_mut().retain(|&(_, timer), waiter| {
+            if owned(waiter) {
+                warn!(
+                    "task#{} {:?} spawned at {} finished with timer #{} pending, removing it",
+                    id, name, location, timer
+                );
+            }
+            !owned(waiter)
+        });
+    }
+
     // the meat of the event loop
-    // we're using select(2) because it's simple and it's portable
-    pub fn run<F: Future<Output = ()> + Send + 'static>(&self, f: F) {
-        self.do_spawn(f);
+    // we're using select(2) because it's simple and it's portable.
+    // Returns false if tasks are left after max_iterations, fails if select
+    // does or an fd can't be watched with it
+    pub fn run<F: Future<Output = ()> + Send + 'static>(
+        &self,
+        f: F,
+        loc: &'static Location<'static>,
+        max_iterations: Option<u64>,
+    ) -> Result<bool, std::io::Error> {
+        // wakeups from other threads
+        let remote_fd = self.remote.open()?;
+
+        self.do_spawn(f, None, loc);
+        let mut iterations = 0;
+
+        // f may have finished on its first poll, select would block for nothing
+        if self.wait_queue.borrow().is_empty() {
+            return Ok(true);
+        }
 
         loop {
             //检测哪些fd就绪 - 开始
@@ -177,9 +663,10 @@ impl EventLoop {
 
             // event loop iteration timeout. if no descriptor
             // is ready we continue iterating
+            let timeout = self.next_timeout();
             let mut tv: timeval = timeval {
-                tv_sec: 1,
-                tv_usec: 0,
+                tv_sec: timeout.as_secs() as libc::time_t,
+                tv_usec: timeout.subsec_micros() as libc::suseconds_t,
             };
 
             // initialize fd_sets (file descriptor sets)
@@ -189,7 +676,22 @@ impl EventLoop {
             unsafe { FD_ZERO(&mut read_fds) };
             unsafe { FD_ZERO(&mut write_fds) };
 
-            let mut nfds = 0;
+            // fd_sets only have room for descriptors below FD_SETSIZE
+            let highest = [
+                Some(remote_fd),
+                self.read.borrow().keys().next_back().copied(),
+                self.write.borrow().keys().next_back().copied(),
+            ];
+            let too_high = highest.iter().flatten().find(|&&fd| fd >= FD_SETSIZE as RawFd);
+            if let Some(fd) = too_high {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("fd {} is past select's limit of {}", fd, FD_SETSIZE),
+                ));
+            }
+
+            unsafe { FD_SET(remote_fd, &mut read_fds as *mut fd_set) };
+            let mut nfds = remote_fd + 1;
 
             // add read interests to read fd_sets
             for fd in self.read.borrow().keys() {
@@ -205,8 +707,11 @@ impl EventLoop {
                 nfds = std::cmp::max(nfds, fd + 1);
             }
 
+            runtime_metrics::fds_registered(self.read.borrow().len(), self.write.borrow().len());
+
             // select will block until some event happens
             // on the fds or timeout triggers
+            let started = Instant::now();
             let rv = unsafe {
                 select(
                     nfds,
@@ -216,42 +721,92 @@ impl EventLoop {
                     &mut tv,
                 )  //可将select换成mio
             };
+            let select_time = started.elapsed();
+            runtime_metrics::select_done(select_time);
+            {
+                let mut metrics = self.metrics.borrow_mut();
+                metrics.iterations += 1;
+                metrics.select.record(select_time);
+            }
 
-            // don't care for errors
+            // a signal handler interrupting select isn't an error, but the
+            // fd sets can't be trusted, so start over
             if rv == -1 {
-                panic!("select()");
+                let err = std::io::Error::last_os_error();
+                if err.kind() == std::io::ErrorKind::Interrupted {
+                    debug!("select interrupted");
+                    continue;
+                }
+                return Err(err);
             } else if rv == 0 {
                 debug!("timeout");
+                self.skip_time();
             } else {
                 debug!("data available on {} fds", rv);
             }
 
             //检测哪些fd就绪 - 结束
 
+            if unsafe { FD_ISSET(remote_fd, &mut read_fds as *mut fd_set) } {
+                for (idx, woken) in self.remote.drain() {
+                    debug!("remote wakeup for task#{}", idx);
+                    let wake_id = match self.wait_queue.borrow().get(&idx) {
+                        Some(task) => task.span.wake_id(),
+                        None => WakeId::none(),
+                    };
+                    self.wake(Wakeup {
+                        index: idx,
+                        waker: self.waker(idx, wake_id),
+                        woken,
+                    });
+                }
+            }
+
             //唤醒就绪的fd的context - 开始
             // check which fd it was and put appropriate future on run queue
-            for (fd, waker) in self.read.borrow().iter() {
-                let is_set = unsafe { FD_ISSET(*fd, &mut read_fds as *mut fd_set) };
+            let ready: Vec<RawFd> = self
+                .read
+                .borrow()
+                .keys()
+                .filter(|fd| unsafe { FD_ISSET(**fd, &mut read_fds as *mut fd_set) })
+                .cloned()
+                .collect();
+            for fd in ready {
                 debug!("fd#{} set (read)", fd);
-                if is_set {
-                    waker.wake_by_ref();
+                let waiters = self.read.borrow_mut().remove(&fd).unwrap_or_default();
+                for waiter in waiters {
+                    waiter.waker.wake();
                 }
             }
 
             // same for write
-            for (fd, waker) in self.write.borrow().iter() {
-                let is_set = unsafe { FD_ISSET(*fd, &mut write_fds as *mut fd_set) };
+            let ready: Vec<RawFd> = self
+                .write
+                .borrow()
+                .keys()
+                .filter(|fd| unsafe { FD_ISSET(**fd, &mut write_fds as *mut fd_set) })
+                .cloned()
+                .collect();
+            for fd in ready {
                 debug!("fd#{} set (write)", fd);
-                if is_set {
-                    waker.wake_by_ref();
+                let waiters = self.write.borrow_mut().remove(&fd).unwrap_or_default();
+                for waiter in waiters {
+                    waiter.waker.wake();
                 }
             }
 
             //唤醒就绪的fd的context - 结束
 
+            self.fire_timers();
+
             //移除就绪的fd对应的task
-            // now pop wakeup notifications from the run queue and poll associated futures
-            loop {
+            // now pop wakeup notifications from the run queue and poll associated futures.
+            // wakeups that happen while polling wait for the next iteration, so a task
+            // that keeps waking itself can't keep select from running
+            let started = Instant::now();
+            let queued = self.run_queue.borrow().len();
+            self.shuffle_queued(queued);
+            for _ in 0..queued {
                 let w = self.run_queue.borrow_mut().pop_front();
                 match w {
                     Some(w) => {
@@ -260,21 +815,38 @@ impl EventLoop {
                         //先移除task，然后检测是否就绪，如果未就绪就重新添加回去，如果就绪就保持移除状态(在上面已经将就绪的context唤醒了，这里不用管了，那些就绪的future会从之前await的地方继续执行，然后结束)。
                         let task = self.wait_queue.borrow_mut().remove(&w.index);
                         if let Some(mut task) = task {
+                            let delay = w.woken.elapsed();
+                            runtime_metrics::task_scheduled(delay);
+                            self.metrics.borrow_mut().schedule_delay.record(delay);
+
                             // if a task is not ready put it back
-                            if let Poll::Pending = task.poll(w.waker) {
+                            if self.poll_task(w.index, &mut task, w.waker).is_pending() {
                                 self.wait_queue.borrow_mut().insert(w.index, task);
+                            } else {
+                                self.finish(w.index, task);
                             }
-                            // otherwise just drop it
                         }
                     }
                     None => break,
                 }
             }
 
+            if queued > 0 {
+                let poll_time = started.elapsed();
+                runtime_metrics::tasks_polled(poll_time);
+                self.metrics.borrow_mut().poll.record(poll_time);
+            }
+            runtime_metrics::tasks_alive(self.wait_queue.borrow().len());
+
             //没任务的时候返回
             // stop the loop if no more tasks
             if self.wait_queue.borrow().is_empty() {
-                return;
+                return Ok(true);
+            }
+
+            iterations += 1;
+            if max_iterations.is_some_and(|max| iterations >= max) {
+                return Ok(false);
             }
         }
     }
@@ -0,0 +1,110 @@
+// where this thread's event loop spends its time, kept by the reactor
+// itself so it's there without a metrics exporter:
+//
+//   select          time blocked in select per iteration
+//   poll            time spent polling woken tasks per iteration
+//   schedule_delay  time from a task's wakeup until it's polled
+//
+// a loop iteration that polls for long delays every other task, a growing
+// poll or schedule_delay tail usually means some task blocks
+use std::fmt;
+use std::time::Duration;
+
+use crate::REACTOR;
+
+// buckets are powers of two in microseconds, the last one takes everything
+// from about 4 seconds up
+const BUCKETS: usize = 24;
+
+// a snapshot of the loop's metrics since it started
+pub fn metrics() -> RuntimeMetrics {
+    REACTOR.with(|reactor| reactor.metrics.borrow().clone())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeMetrics {
+    // times the loop went through select
+    pub iterations: u64,
+    pub select: Histogram,
+    pub poll: Histogram,
+    pub schedule_delay: Histogram,
+    // task polls over the slow poll threshold
+    pub slow_polls: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Histogram {
+    pub(crate) fn record(&mut self, d: Duration) {
+        let micros = d.as_micros();
+        // bucket i holds durations below 2^i µs
+        let bucket = (128 - micros.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.sum += d;
+        self.max = self.max.max(d);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            n => Duration::from_nanos((self.sum.as_nanos() / n as u128) as u64),
+        }
+    }
+
+    // upper bound of the bucket holding the q-th quantile, q in 0..=1.
+    // Never more than max
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+
+        for (upper, n) in self.buckets() {
+            seen += n;
+            if seen >= rank.max(1) {
+                return upper.min(self.max);
+            }
+        }
+        self.max
+    }
+
+    // (upper bound, count) for every bucket, the last bound is Duration::MAX
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.buckets.iter().enumerate().map(|(i, &n)| {
+            let upper = if i == BUCKETS - 1 {
+                Duration::MAX
+            } else {
+                Duration::from_micros(1 << i)
+            };
+            (upper, n)
+        })
+    }
+}
+
+impl fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Histogram")
+            .field("count", &self.count)
+            .field("mean", &self.mean())
+            .field("p99", &self.quantile(0.99))
+            .field("max", &self.max)
+            .finish()
+    }
+}
@@ -0,0 +1,237 @@
+// netlink sockets: talk to the kernel (routing tables, interfaces, uevents)
+// with datagrams. Message encoding is left to the caller, libc has the
+// constants and header structs
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::task::{Context, Poll};
+
+use libc::{c_int, c_void};
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::sys;
+use crate::REACTOR;
+
+// port id and multicast groups of a netlink endpoint, the kernel is pid 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct NetlinkAddr {
+    pub pid: u32,
+    pub groups: u32,
+}
+
+impl NetlinkAddr {
+    pub const KERNEL: NetlinkAddr = NetlinkAddr { pid: 0, groups: 0 };
+
+    pub fn new(pid: u32, groups: u32) -> NetlinkAddr {
+        NetlinkAddr { pid, groups }
+    }
+
+    fn to_raw(self) -> libc::sockaddr_nl {
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_pid = self.pid;
+        addr.nl_groups = self.groups;
+        addr
+    }
+}
+
+#[derive(Debug)]
+pub struct AsyncNetlinkSocket(OwnedFd);
+
+impl AsyncNetlinkSocket {
+    // protocol is the netlink family, e.g. libc::NETLINK_ROUTE or
+    // libc::NETLINK_KOBJECT_UEVENT
+    pub fn new(protocol: c_int) -> Result<AsyncNetlinkSocket, io::Error> {
+        let ty = libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, ty, protocol) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(AsyncNetlinkSocket(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+
+    // bind to addr, pid 0 lets the kernel pick a port id. groups is the
+    // bitmask of multicast groups to listen to, e.g. RTMGRP_LINK
+    pub fn bind(&self, addr: NetlinkAddr) -> Result<(), io::Error> {
+        let raw = addr.to_raw();
+
+        let rv = unsafe {
+            libc::bind(
+                self.0.as_raw_fd(),
+                &raw as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn local_addr(&self) -> Result<NetlinkAddr, io::Error> {
+        let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+
+        let rv = unsafe {
+            libc::getsockname(
+                self.0.as_raw_fd(),
+                &mut addr as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            )
+        };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(NetlinkAddr::new(addr.nl_pid, addr.nl_groups))
+    }
+
+    // join a multicast group by number (RTNLGRP_*), for groups past 32
+    // that don't fit into the bind mask
+    pub fn add_membership(&self, group: u32) -> Result<(), io::Error> {
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::SOL_NETLINK,
+            libc::NETLINK_ADD_MEMBERSHIP,
+            group,
+        )
+    }
+
+    pub fn drop_membership(&self, group: u32) -> Result<(), io::Error> {
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::SOL_NETLINK,
+            libc::NETLINK_DROP_MEMBERSHIP,
+            group,
+        )
+    }
+
+    // send a request to the kernel
+    pub async fn send(&self, buf: &[u8]) -> Result<usize, io::Error> {
+        self.send_to(buf, NetlinkAddr::KERNEL).await
+    }
+
+    pub async fn send_to(&self, buf: &[u8], addr: NetlinkAddr) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_to(ctx, buf, addr)).await
+    }
+
+    pub fn poll_send_to(
+        &self,
+        ctx: &mut Context,
+        buf: &[u8],
+        addr: NetlinkAddr,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_to() called");
+        let fd = self.0.as_raw_fd();
+        let raw = addr.to_raw();
+
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            let rv = unsafe {
+                libc::sendto(
+                    fd,
+                    buf.as_ptr() as *const c_void,
+                    buf.len(),
+                    0,
+                    &raw as *const _ as *const libc::sockaddr,
+                    mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            if rv == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(rv as usize)
+        })
+    }
+
+    // receive one datagram, it may hold several netlink messages
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        self.recv_from(buf).await.map(|(len, _)| len)
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, NetlinkAddr), io::Error> {
+        poll_fn(|ctx| self.poll_recv_from(ctx, buf)).await
+    }
+
+    pub fn poll_recv_from(
+        &self,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<(usize, NetlinkAddr), io::Error>> {
+        debug!("poll_recv_from() called");
+        let fd = self.0.as_raw_fd();
+
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            let mut addr: libc::sockaddr_nl = unsafe { mem::zeroed() };
+            let mut len = mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+
+            let rv = unsafe {
+                libc::recvfrom(
+                    fd,
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len(),
+                    0,
+                    &mut addr as *mut _ as *mut libc::sockaddr,
+                    &mut len,
+                )
+            };
+            if rv == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((rv as usize, NetlinkAddr::new(addr.nl_pid, addr.nl_groups)))
+        })
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| interest::poll_ready(self.0.as_raw_fd(), interest, ctx)).await
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncNetlinkSocket {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncNetlinkSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncNetlinkSocket {
+    fn into_raw_fd(self) -> RawFd {
+        self.deregister();
+
+        unsafe { interest::take_io(self, |this| &this.0) }.into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncNetlinkSocket {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncNetlinkSocket {
+        AsyncNetlinkSocket(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncNetlinkSocket {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncNetlinkSocket, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(AsyncNetlinkSocket(fd))
+    }
+}
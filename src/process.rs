@@ -0,0 +1,498 @@
+// subprocesses without blocking the reactor. Command wraps
+// std::process::Command for setting things up, waiting for the child is
+// driven by SIGCHLD (see signal): every SIGCHLD makes the waiting tasks
+// check their child with a non-blocking waitpid.
+//
+// on Linux 5.3+ each child gets a pidfd instead, it becomes readable when
+// the child exits. No SIGCHLD handler is installed then, so we don't get in
+// the way of an application that handles SIGCHLD itself
+use std::ffi::OsStr;
+use std::future::poll_fn;
+use std::io::{self, IoSlice, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::pin::Pin;
+use std::process::{self, ExitStatus, Output, Stdio};
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use log::debug;
+
+use crate::blocking::spawn_blocking;
+use crate::interest::{self, Interest};
+use crate::signal::{self, SignalKind};
+use crate::sys;
+use crate::REACTOR;
+
+#[derive(Debug)]
+pub struct Command {
+    inner: process::Command,
+    kill_on_drop: bool,
+}
+
+impl Command {
+    pub fn new<S: AsRef<OsStr>>(program: S) -> Command {
+        Command {
+            inner: process::Command::new(program),
+            kill_on_drop: false,
+        }
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) -> &mut Command {
+        self.inner.arg(arg);
+        self
+    }
+
+    pub fn args<I, S>(&mut self, args: I) -> &mut Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.inner.args(args);
+        self
+    }
+
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, val: V) -> &mut Command {
+        self.inner.env(key, val);
+        self
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I) -> &mut Command
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.inner.envs(vars);
+        self
+    }
+
+    pub fn env_remove<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Command {
+        self.inner.env_remove(key);
+        self
+    }
+
+    pub fn env_clear(&mut self) -> &mut Command {
+        self.inner.env_clear();
+        self
+    }
+
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Command {
+        self.inner.current_dir(dir);
+        self
+    }
+
+    pub fn stdin<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdin(cfg);
+        self
+    }
+
+    pub fn stdout<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stdout(cfg);
+        self
+    }
+
+    pub fn stderr<T: Into<Stdio>>(&mut self, cfg: T) -> &mut Command {
+        self.inner.stderr(cfg);
+        self
+    }
+
+    // SIGKILL the child when its Child is dropped before it exited, e.g.
+    // because the task waiting for it was cancelled. The process is reaped
+    // on the blocking pool, a thread waits for it to die
+    pub fn kill_on_drop(&mut self, kill_on_drop: bool) -> &mut Command {
+        self.kill_on_drop = kill_on_drop;
+        self
+    }
+
+    // put the child into process group pgid, 0 makes it the leader of a new
+    // group (see Child::signal_group)
+    pub fn process_group(&mut self, pgid: i32) -> &mut Command {
+        self.inner.process_group(pgid);
+        self
+    }
+
+    // run the child in a new session, detached from our controlling
+    // terminal. It also leads a new process group
+    pub fn new_session(&mut self) -> &mut Command {
+        unsafe {
+            self.inner.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            })
+        };
+        self
+    }
+
+    // for the settings not mirrored here (uid, pre_exec, ...)
+    pub fn as_std_mut(&mut self) -> &mut process::Command {
+        &mut self.inner
+    }
+
+    pub fn spawn(&mut self) -> Result<Child, io::Error> {
+        let mut child = self.inner.spawn()?;
+        debug!("spawned child {}", child.id());
+
+        Ok(Child {
+            #[cfg(target_os = "linux")]
+            pidfd: pidfd_open(child.id()),
+            kill_on_drop: self.kill_on_drop,
+            stdin: child.stdin.take().map(ChildStdin::new).transpose()?,
+            stdout: child.stdout.take().map(ChildStdout::new).transpose()?,
+            stderr: child.stderr.take().map(ChildStderr::new).transpose()?,
+            inner: child,
+        })
+    }
+
+    // run to completion, stdio is inherited unless configured otherwise
+    pub async fn status(&mut self) -> Result<ExitStatus, io::Error> {
+        self.spawn()?.wait().await
+    }
+
+    // run to completion, collecting stdout and stderr
+    pub async fn output(&mut self) -> Result<Output, io::Error> {
+        self.inner.stdout(Stdio::piped());
+        self.inner.stderr(Stdio::piped());
+        self.spawn()?.wait_with_output().await
+    }
+}
+
+impl From<process::Command> for Command {
+    fn from(inner: process::Command) -> Command {
+        Command {
+            inner,
+            kill_on_drop: false,
+        }
+    }
+}
+
+// a running child. Like with std, dropping it doesn't kill or reap the
+// process unless kill_on_drop was set. The stdio fields are set for the streams configured as
+// Stdio::piped()
+#[derive(Debug)]
+pub struct Child {
+    inner: process::Child,
+    // None if the kernel doesn't have pidfd_open
+    #[cfg(target_os = "linux")]
+    pidfd: Option<OwnedFd>,
+    kill_on_drop: bool,
+    pub stdin: Option<ChildStdin>,
+    pub stdout: Option<ChildStdout>,
+    pub stderr: Option<ChildStderr>,
+}
+
+impl Child {
+    pub fn id(&self) -> u32 {
+        self.inner.id()
+    }
+
+    pub fn try_wait(&mut self) -> Result<Option<ExitStatus>, io::Error> {
+        self.inner.try_wait()
+    }
+
+    pub async fn wait(&mut self) -> Result<ExitStatus, io::Error> {
+        #[cfg(target_os = "linux")]
+        if let Some(pidfd) = &self.pidfd {
+            let fd = pidfd.as_raw_fd();
+
+            loop {
+                if let Some(status) = self.inner.try_wait()? {
+                    debug!("child {} exited: {}", self.inner.id(), status);
+                    return Ok(status);
+                }
+
+                poll_fn(|ctx| interest::poll_ready(fd, Interest::READABLE, ctx)).await?;
+            }
+        }
+
+        // subscribe before checking, so a SIGCHLD in between isn't lost
+        let mut sigchld = signal::signal(SignalKind::child())?;
+
+        loop {
+            if let Some(status) = self.inner.try_wait()? {
+                debug!("child {} exited: {}", self.inner.id(), status);
+                return Ok(status);
+            }
+
+            sigchld.recv().await;
+        }
+    }
+
+    // SIGKILL the child
+    pub fn kill(&mut self) -> Result<(), io::Error> {
+        self.inner.kill()
+    }
+
+    // send any signal to the child, fails once it has been waited for
+    pub fn signal(&mut self, kind: SignalKind) -> Result<(), io::Error> {
+        self.send_signal(self.inner.id() as libc::pid_t, kind)
+    }
+
+    // send a signal to the child's whole process group, the child has to
+    // lead one (process_group(0) or new_session())
+    pub fn signal_group(&mut self, kind: SignalKind) -> Result<(), io::Error> {
+        self.send_signal(-(self.inner.id() as libc::pid_t), kind)
+    }
+
+    fn send_signal(&mut self, pid: libc::pid_t, kind: SignalKind) -> Result<(), io::Error> {
+        // a reaped pid may already belong to some other process
+        if self.inner.try_wait()?.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "process has already exited",
+            ));
+        }
+
+        if unsafe { libc::kill(pid, kind.as_raw()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // close stdin, read stdout and stderr (whichever are piped) to the end
+    // and wait for the exit
+    pub async fn wait_with_output(mut self) -> Result<Output, io::Error> {
+        drop(self.stdin.take());
+
+        let mut stdout = self.stdout.take();
+        let mut stderr = self.stderr.take();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        // both at once, the child blocks if either pipe fills up
+        poll_fn(|ctx| {
+            let out_done = poll_read_to_end(&mut stdout, ctx, &mut out)?.is_ready();
+            let err_done = poll_read_to_end(&mut stderr, ctx, &mut err)?.is_ready();
+
+            if out_done && err_done {
+                Poll::Ready(Ok::<_, io::Error>(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await?;
+
+        let status = self.wait().await?;
+
+        Ok(Output {
+            status,
+            stdout: out,
+            stderr: err,
+        })
+    }
+}
+
+impl Drop for Child {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(pidfd) = &self.pidfd {
+            deregister(pidfd.as_raw_fd());
+        }
+
+        if !self.kill_on_drop {
+            return;
+        }
+
+        if let Ok(None) = self.inner.try_wait() {
+            debug!("killing child {} on drop", self.inner.id());
+            let _ = self.inner.kill();
+
+            // usually it isn't dead yet. SIGKILL can't be caught, so a
+            // blocking wait won't take long
+            if let Ok(None) = self.inner.try_wait() {
+                let pid = self.inner.id() as libc::pid_t;
+                drop(spawn_blocking(move || reap(pid)));
+            }
+        }
+    }
+}
+
+// a descriptor for pid that polls readable once it exits
+#[cfg(target_os = "linux")]
+fn pidfd_open(pid: u32) -> Option<OwnedFd> {
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd == -1 {
+        debug!("pidfd_open failed: {}", io::Error::last_os_error());
+        return None;
+    }
+
+    // pidfds are always close-on-exec
+    Some(unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+// collect the exit status of a killed child so it doesn't stay a zombie
+fn reap(pid: libc::pid_t) {
+    loop {
+        if unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) } != -1 {
+            debug!("reaped child {}", pid);
+            return;
+        }
+        if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+            return;
+        }
+    }
+}
+
+// read into buf until EOF, then close the pipe
+fn poll_read_to_end<R: AsyncRead + Unpin>(
+    pipe: &mut Option<R>,
+    ctx: &mut Context,
+    buf: &mut Vec<u8>,
+) -> Poll<Result<(), io::Error>> {
+    let reader = match pipe {
+        Some(reader) => reader,
+        None => return Poll::Ready(Ok(())),
+    };
+
+    let mut chunk = [0u8; 4096];
+    loop {
+        match Pin::new(&mut *reader).poll_read(ctx, &mut chunk) {
+            Poll::Ready(Ok(0)) => {
+                *pipe = None;
+                return Poll::Ready(Ok(()));
+            }
+            Poll::Ready(Ok(n)) => buf.extend_from_slice(&chunk[..n]),
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        }
+    }
+}
+
+// the write end of the child's stdin, drop it to send EOF
+#[derive(Debug)]
+pub struct ChildStdin(process::ChildStdin);
+
+#[derive(Debug)]
+pub struct ChildStdout(process::ChildStdout);
+
+#[derive(Debug)]
+pub struct ChildStderr(process::ChildStderr);
+
+impl ChildStdin {
+    fn new(pipe: process::ChildStdin) -> Result<ChildStdin, io::Error> {
+        sys::set_nonblocking(pipe.as_raw_fd(), true)?;
+        Ok(ChildStdin(pipe))
+    }
+}
+
+impl ChildStdout {
+    fn new(pipe: process::ChildStdout) -> Result<ChildStdout, io::Error> {
+        sys::set_nonblocking(pipe.as_raw_fd(), true)?;
+        Ok(ChildStdout(pipe))
+    }
+}
+
+impl ChildStderr {
+    fn new(pipe: process::ChildStderr) -> Result<ChildStderr, io::Error> {
+        sys::set_nonblocking(pipe.as_raw_fd(), true)?;
+        Ok(ChildStderr(pipe))
+    }
+}
+
+impl AsyncWrite for ChildStdin {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write() called");
+        let pipe = &mut self.get_mut().0;
+        interest::poll_io(pipe.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            pipe.write(buf)
+        })
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write_vectored() called");
+        let pipe = &mut self.get_mut().0;
+        interest::poll_io(pipe.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            pipe.write_vectored(bufs)
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // a pipe can't be half closed, the child sees EOF once this is dropped
+    fn poll_close(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for ChildStdout {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read() called");
+        let pipe = &mut self.get_mut().0;
+        interest::poll_io(pipe.as_raw_fd(), ctx, Interest::READABLE, || pipe.read(buf))
+    }
+}
+
+impl AsyncRead for ChildStderr {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read() called");
+        let pipe = &mut self.get_mut().0;
+        interest::poll_io(pipe.as_raw_fd(), ctx, Interest::READABLE, || pipe.read(buf))
+    }
+}
+
+impl Drop for ChildStdin {
+    fn drop(&mut self) {
+        deregister(self.0.as_raw_fd());
+    }
+}
+
+impl Drop for ChildStdout {
+    fn drop(&mut self) {
+        deregister(self.0.as_raw_fd());
+    }
+}
+
+impl Drop for ChildStderr {
+    fn drop(&mut self) {
+        deregister(self.0.as_raw_fd());
+    }
+}
+
+impl AsRawFd for ChildStdin {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsRawFd for ChildStdout {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl AsRawFd for ChildStderr {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+fn deregister(fd: RawFd) {
+    let _ = REACTOR.try_with(|reactor| {
+        reactor.remove_read_interest(fd);
+        reactor.remove_write_interest(fd);
+    });
+}
@@ -0,0 +1,120 @@
+// quinn runtime on top of the reactor: QUIC endpoints get their udp io and
+// timers from here. Everything quinn spawns runs on the thread that created
+// the endpoint, so endpoints must be created inside fahrenheit::run
+use std::fmt;
+use std::future::Future;
+use std::io::{self, IoSliceMut};
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use quinn::udp::{RecvMeta, Transmit, UdpSocketState};
+use quinn::{AsyncTimer, AsyncUdpSocket as QuinnUdpSocket, Runtime, UdpPoller};
+
+use crate::interest::{self, Interest};
+use crate::time::{sleep_until, Sleep};
+use crate::AsyncUdpSocket;
+
+// pass Arc::new(FahrenheitRuntime) to quinn::Endpoint::new
+#[derive(Debug)]
+pub struct FahrenheitRuntime;
+
+impl Runtime for FahrenheitRuntime {
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        Box::pin(sleep_until(deadline))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        crate::spawn(future);
+    }
+
+    fn wrap_udp_socket(
+        &self,
+        socket: std::net::UdpSocket,
+    ) -> Result<Arc<dyn QuinnUdpSocket>, io::Error> {
+        // quinn-udp knows how to do GSO/GRO and ECN on this platform, we
+        // only provide readiness
+        let state = UdpSocketState::new((&socket).into())?;
+        let io = AsyncUdpSocket::from_std(socket)?;
+
+        Ok(Arc::new(UdpSocket { io, state }))
+    }
+}
+
+impl AsyncTimer for Sleep {
+    fn reset(self: Pin<&mut Self>, deadline: Instant) {
+        Sleep::reset(self.get_mut(), deadline)
+    }
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()> {
+        Future::poll(self, ctx)
+    }
+}
+
+struct UdpSocket {
+    io: AsyncUdpSocket,
+    state: UdpSocketState,
+}
+
+impl fmt::Debug for UdpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UdpSocket").field("io", &self.io).finish()
+    }
+}
+
+impl QuinnUdpSocket for UdpSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        Box::pin(WritablePoller {
+            fd: self.io.as_raw_fd(),
+            _socket: self,
+        })
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> Result<(), io::Error> {
+        self.state.send((&self.io.0).into(), transmit)
+    }
+
+    fn poll_recv(
+        &self,
+        ctx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<Result<usize, io::Error>> {
+        interest::poll_io(self.io.as_raw_fd(), ctx, Interest::READABLE, || {
+            self.state.recv((&self.io.0).into(), bufs, meta)
+        })
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        self.io.local_addr()
+    }
+
+    fn max_transmit_segments(&self) -> usize {
+        self.state.max_gso_segments()
+    }
+
+    fn max_receive_segments(&self) -> usize {
+        self.state.gro_segments()
+    }
+
+    fn may_fragment(&self) -> bool {
+        self.state.may_fragment()
+    }
+}
+
+// quinn asks for this after try_send() returned WouldBlock
+#[derive(Debug)]
+struct WritablePoller {
+    fd: RawFd,
+    // keeps the fd open
+    _socket: Arc<UdpSocket>,
+}
+
+impl UdpPoller for WritablePoller {
+    fn poll_writable(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        interest::poll_ready(self.fd, Interest::WRITABLE, ctx).map_ok(|_| ())
+    }
+}
@@ -0,0 +1,146 @@
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+use std::mem;
+use std::net::SocketAddr;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::task::{Context, Poll};
+
+use libc::{c_int, c_void};
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::sys;
+use crate::REACTOR;
+
+// SOCK_RAW socket, sends and receives whole packets of one ip protocol.
+// Creating one needs CAP_NET_RAW (or root). For IPv4 received packets
+// start with the ip header, IPv6 raw sockets only see the payload
+#[derive(Debug)]
+pub struct AsyncRawSocket(OwnedFd);
+
+impl AsyncRawSocket {
+    // domain is libc::AF_INET or libc::AF_INET6, protocol e.g.
+    // libc::IPPROTO_ICMP
+    pub fn new(domain: c_int, protocol: c_int) -> Result<AsyncRawSocket, io::Error> {
+        let fd = sys::socket_with_protocol(domain, libc::SOCK_RAW, protocol)?;
+        Ok(AsyncRawSocket(fd))
+    }
+
+    pub fn bind(&self, addr: SocketAddr) -> Result<(), io::Error> {
+        sys::bind(self.0.as_raw_fd(), &addr)
+    }
+
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize, io::Error> {
+        poll_fn(|ctx| self.poll_send_to(ctx, buf, target)).await
+    }
+
+    pub fn poll_send_to(
+        &self,
+        ctx: &mut Context,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_send_to() called");
+        let fd = self.0.as_raw_fd();
+        let (storage, len) = sys::socket_addr(&target);
+
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            let rv = unsafe {
+                libc::sendto(
+                    fd,
+                    buf.as_ptr() as *const c_void,
+                    buf.len(),
+                    0,
+                    &storage as *const _ as *const libc::sockaddr,
+                    len,
+                )
+            };
+            if rv == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(rv as usize)
+        })
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), io::Error> {
+        poll_fn(|ctx| self.poll_recv_from(ctx, buf)).await
+    }
+
+    pub fn poll_recv_from(
+        &self,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<(usize, SocketAddr), io::Error>> {
+        debug!("poll_recv_from() called");
+        let fd = self.0.as_raw_fd();
+
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+            let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+            let rv = unsafe {
+                libc::recvfrom(
+                    fd,
+                    buf.as_mut_ptr() as *mut c_void,
+                    buf.len(),
+                    0,
+                    &mut storage as *mut _ as *mut libc::sockaddr,
+                    &mut len,
+                )
+            };
+            if rv == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok((rv as usize, sys::to_socket_addr(&storage)?))
+        })
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| interest::poll_ready(self.0.as_raw_fd(), interest, ctx)).await
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncRawSocket {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncRawSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncRawSocket {
+    fn into_raw_fd(self) -> RawFd {
+        self.deregister();
+
+        unsafe { interest::take_io(self, |this| &this.0) }.into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for AsyncRawSocket {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncRawSocket {
+        AsyncRawSocket(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncRawSocket {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncRawSocket, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(AsyncRawSocket(fd))
+    }
+}
@@ -0,0 +1,90 @@
+// Registration is the public face of the reactor for io types that live
+// outside of this crate: wrap the fd of any non-blocking descriptor, wait for
+// readiness here, do the actual io yourself and come back on WouldBlock.
+use std::future::poll_fn;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::task::{Context, Poll};
+
+use crate::interest;
+use crate::Interest;
+use crate::Ready;
+use crate::REACTOR;
+
+// doesn't own the descriptor, it must stay open for as long as the
+// registration exists. Dropping it removes the fd from the reactor
+#[derive(Debug)]
+pub struct Registration {
+    fd: RawFd,
+}
+
+impl Registration {
+    pub fn new(fd: RawFd) -> Registration {
+        Registration { fd }
+    }
+
+    pub fn from_fd<T: AsRawFd>(io: &T) -> Registration {
+        Registration::new(io.as_raw_fd())
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    // Ready immediately if the fd is ready for any of interest, otherwise
+    // the task is woken once it is
+    pub fn poll_ready(
+        &self,
+        ctx: &mut Context,
+        interest: Interest,
+    ) -> Poll<Result<Ready, io::Error>> {
+        interest::poll_ready(self.fd, interest, ctx)
+    }
+
+    pub fn poll_read_ready(&self, ctx: &mut Context) -> Poll<Result<Ready, io::Error>> {
+        self.poll_ready(ctx, Interest::READABLE)
+    }
+
+    pub fn poll_write_ready(&self, ctx: &mut Context) -> Poll<Result<Ready, io::Error>> {
+        self.poll_ready(ctx, Interest::WRITABLE)
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| self.poll_ready(ctx, interest)).await
+    }
+
+    pub async fn readable(&self) -> Result<Ready, io::Error> {
+        self.ready(Interest::READABLE).await
+    }
+
+    pub async fn writable(&self) -> Result<Ready, io::Error> {
+        self.ready(Interest::WRITABLE).await
+    }
+
+    // run a non-blocking io operation, waiting for readiness whenever it
+    // returns WouldBlock
+    pub async fn async_io<R>(
+        &self,
+        interest: Interest,
+        mut op: impl FnMut() -> Result<R, io::Error>,
+    ) -> Result<R, io::Error> {
+        loop {
+            self.ready(interest).await?;
+
+            match op() {
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => continue,
+                res => return res,
+            }
+        }
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let fd = self.fd;
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
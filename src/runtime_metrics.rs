@@ -0,0 +1,77 @@
+// reactor metrics through the metrics crate facade, any installed exporter
+// picks them up. Needs feature "metrics", otherwise these do nothing:
+//
+//   fahrenheit_tasks_spawned_total     counter
+//   fahrenheit_tasks_alive             gauge, tasks not finished yet
+//   fahrenheit_polls_total             counter
+//   fahrenheit_wakeups_total           counter, local and from other threads
+//   fahrenheit_select_duration_seconds histogram, time blocked in select
+//   fahrenheit_poll_duration_seconds   histogram, time polling tasks per iteration
+//   fahrenheit_schedule_delay_seconds  histogram, from wakeup until polled
+//   fahrenheit_registered_fds          gauge, label direction=read|write
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+pub(crate) fn task_spawned() {
+    metrics::counter!("fahrenheit_tasks_spawned_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn tasks_alive(n: usize) {
+    metrics::gauge!("fahrenheit_tasks_alive").set(n as f64);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn task_polled() {
+    metrics::counter!("fahrenheit_polls_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn task_woken() {
+    metrics::counter!("fahrenheit_wakeups_total").increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn fds_registered(read: usize, write: usize) {
+    metrics::gauge!("fahrenheit_registered_fds", "direction" => "read").set(read as f64);
+    metrics::gauge!("fahrenheit_registered_fds", "direction" => "write").set(write as f64);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn select_done(d: Duration) {
+    metrics::histogram!("fahrenheit_select_duration_seconds").record(d.as_secs_f64());
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn tasks_polled(d: Duration) {
+    metrics::histogram!("fahrenheit_poll_duration_seconds").record(d.as_secs_f64());
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn task_scheduled(delay: Duration) {
+    metrics::histogram!("fahrenheit_schedule_delay_seconds").record(delay.as_secs_f64());
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn task_spawned() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn tasks_alive(_: usize) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn task_polled() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn task_woken() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn fds_registered(_: usize, _: usize) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn select_done(_: Duration) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn tasks_polled(_: Duration) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn task_scheduled(_: Duration) {}
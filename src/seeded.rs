@@ -0,0 +1,97 @@
+// seeded scheduling for tests. Tasks woken in the same loop iteration are
+// normally polled in wakeup order, a seed shuffles them instead, so code
+// that quietly depends on one task running before another fails for some
+// seeds. Which tasks get woken in an iteration still depends on timers and
+// fds, the order among them is a function of the seed alone
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe, Location};
+
+use log::debug;
+
+use crate::rng::Rng;
+use crate::{EventLoop, REACTOR};
+
+// like run, with the order of polls picked by seed
+#[track_caller]
+pub fn run_seeded<F: Future<Output = ()> + Send + 'static>(seed: u64, f: F) {
+    let loc = Location::caller();
+    REACTOR.with(|reactor| {
+        let _seeded = Seeded::new(reactor, seed);
+        if let Err(err) = reactor.run(f, loc, None) {
+            panic!("event loop failed: {}", err);
+        }
+    })
+}
+
+// run the future made by f once for each seed in 0..runs. A panic names the
+// seed it happened with, run_seeded with that seed replays the ordering
+#[track_caller]
+pub fn explore<F, Fut>(runs: u64, mut f: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let loc = Location::caller();
+
+    for seed in 0..runs {
+        debug!("exploring seed {}", seed);
+
+        let fut = f();
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            REACTOR.with(|reactor| {
+                let _seeded = Seeded::new(reactor, seed);
+                if let Err(err) = reactor.run(fut, loc, None) {
+                    panic!("event loop failed: {}", err);
+                }
+            })
+        }));
+
+        if let Err(err) = res {
+            let msg = match err.downcast_ref::<&str>() {
+                Some(msg) => msg.to_string(),
+                None => match err.downcast_ref::<String>() {
+                    Some(msg) => msg.clone(),
+                    None => "non-string panic".to_owned(),
+                },
+            };
+            panic!("explore: failed with seed {}: {}", seed, msg);
+        }
+    }
+}
+
+// shuffles the reactor's polls until dropped, then puts back whatever was
+// there before, even if the run panicked
+struct Seeded<'a> {
+    reactor: &'a EventLoop,
+    old: Option<Rng>,
+}
+
+impl<'a> Seeded<'a> {
+    fn new(reactor: &'a EventLoop, seed: u64) -> Seeded<'a> {
+        let old = reactor.shuffle.replace(Some(Rng::new(seed)));
+        Seeded { reactor, old }
+    }
+}
+
+impl Drop for Seeded<'_> {
+    fn drop(&mut self) {
+        self.reactor.shuffle.replace(self.old.take());
+    }
+}
+
+impl EventLoop {
+    // Fisher-Yates over the first n wakeups in the run queue
+    pub(crate) fn shuffle_queued(&self, n: usize) {
+        let mut shuffle = self.shuffle.borrow_mut();
+        let rng = match shuffle.as_mut() {
+            Some(rng) => rng,
+            None => return,
+        };
+
+        let mut queue = self.run_queue.borrow_mut();
+        for i in (1..n).rev() {
+            let j = rng.below(i as u64 + 1) as usize;
+            queue.swap(i, j);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+// unix signals as async events, for graceful shutdown on SIGINT/SIGTERM,
+// config reload on SIGHUP and the like.
+//
+// self-pipe trick: the first listener for a signal installs a handler that
+// bumps a per-signal counter and writes a byte to a process wide pipe.
+// Listeners never touch the pipe, they park their waker in a list and
+// compare the counter with the last value they have seen. Each reactor with
+// listeners watches the pipe's read end, the one that finds it readable
+// drains it and wakes every listener of every signal. Once a handler is
+// installed the signal no longer has its default effect (SIGINT won't kill
+// the process), even after all listeners are gone
+use std::future::poll_fn;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+use futures_task::ArcWake;
+use libc::{c_int, c_void};
+use log::debug;
+
+use crate::sys;
+use crate::REACTOR;
+
+// larger than any signal number, real-time signals included
+const MAX_SIGNAL: usize = 65;
+
+static COUNTERS: [AtomicUsize; MAX_SIGNAL] = [const { AtomicUsize::new(0) }; MAX_SIGNAL];
+static INSTALLED: [AtomicBool; MAX_SIGNAL] = [const { AtomicBool::new(false) }; MAX_SIGNAL];
+
+// write end of the pipe, read by the handler
+static PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+// both ends are kept open for the life of the process
+static PIPE: OnceLock<(OwnedFd, OwnedFd)> = OnceLock::new();
+// serializes handler installation
+static INSTALL: Mutex<()> = Mutex::new(());
+// listeners waiting for the pipe, on any thread
+static LISTENERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+
+thread_local! {
+    // the waker this thread's reactor watches the pipe with
+    static WATCHER: Waker = futures_task::waker(Arc::new(Watcher));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SignalKind(c_int);
+
+impl SignalKind {
+    pub const fn from_raw(signum: c_int) -> SignalKind {
+        SignalKind(signum)
+    }
+
+    pub const fn as_raw(self) -> c_int {
+        self.0
+    }
+
+    pub const fn interrupt() -> SignalKind {
+        SignalKind(libc::SIGINT)
+    }
+
+    pub const fn terminate() -> SignalKind {
+        SignalKind(libc::SIGTERM)
+    }
+
+    pub const fn hangup() -> SignalKind {
+        SignalKind(libc::SIGHUP)
+    }
+
+    pub const fn quit() -> SignalKind {
+        SignalKind(libc::SIGQUIT)
+    }
+
+    pub const fn user_defined1() -> SignalKind {
+        SignalKind(libc::SIGUSR1)
+    }
+
+    pub const fn user_defined2() -> SignalKind {
+        SignalKind(libc::SIGUSR2)
+    }
+
+    pub const fn child() -> SignalKind {
+        SignalKind(libc::SIGCHLD)
+    }
+
+    pub const fn pipe() -> SignalKind {
+        SignalKind(libc::SIGPIPE)
+    }
+
+    pub const fn alarm() -> SignalKind {
+        SignalKind(libc::SIGALRM)
+    }
+
+    pub const fn window_change() -> SignalKind {
+        SignalKind(libc::SIGWINCH)
+    }
+}
+
+// a stream that yields () every time the signal arrives. Signals that come
+// in quick succession may be merged into one
+#[derive(Debug)]
+pub struct Signal {
+    signum: usize,
+    seen: usize,
+    pipe: RawFd,
+}
+
+// listen for kind. Only deliveries after this call are reported
+pub fn signal(kind: SignalKind) -> Result<Signal, io::Error> {
+    let signum = kind.0;
+    let forbidden = [
+        libc::SIGKILL,
+        libc::SIGSTOP,
+        libc::SIGSEGV,
+        libc::SIGBUS,
+        libc::SIGILL,
+        libc::SIGFPE,
+    ];
+    if signum <= 0 || signum as usize >= MAX_SIGNAL || forbidden.contains(&signum) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't listen for signal {}", signum),
+        ));
+    }
+
+    let pipe = install(signum)?;
+    let signum = signum as usize;
+
+    Ok(Signal {
+        signum,
+        seen: COUNTERS[signum].load(Ordering::Acquire),
+        pipe,
+    })
+}
+
+// wait for the next SIGINT (ctrl-c in a terminal)
+pub async fn ctrl_c() -> Result<(), io::Error> {
+    signal(SignalKind::interrupt())?.recv().await;
+    Ok(())
+}
+
+impl Signal {
+    pub async fn recv(&mut self) {
+        poll_fn(|ctx| self.poll_recv(ctx)).await
+    }
+
+    pub fn poll_recv(&mut self, ctx: &mut Context) -> Poll<()> {
+        debug!("poll_recv() called for signal {}", self.signum);
+
+        // park before looking at the counter, a signal arriving after this
+        // wakes us even if another thread's reactor drains the pipe first
+        {
+            let mut listeners = LISTENERS.lock().unwrap_or_else(|err| err.into_inner());
+            if !listeners.iter().any(|w| w.will_wake(ctx.waker())) {
+                listeners.push(ctx.waker().clone());
+            }
+        }
+
+        let count = COUNTERS[self.signum].load(Ordering::Acquire);
+        if count != self.seen {
+            // the parked waker only causes a spurious poll
+            self.seen = count;
+            return Poll::Ready(());
+        }
+
+        // the watcher belongs to no task, so it stays registered when this
+        // listener's task finishes; registering it again just replaces it
+        let watcher = WATCHER.with(|watcher| watcher.clone());
+        REACTOR.with(|reactor| reactor.add_detached_read_interest(self.pipe, watcher));
+        Poll::Pending
+    }
+}
+
+impl Stream for Signal {
+    type Item = ();
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<()>> {
+        self.get_mut().poll_recv(ctx).map(Some)
+    }
+}
+
+// woken by a reactor that found the pipe readable
+struct Watcher;
+
+impl ArcWake for Watcher {
+    fn wake_by_ref(_: &Arc<Self>) {
+        if let Some((read, _)) = PIPE.get() {
+            drain(read.as_raw_fd());
+        }
+
+        let listeners = mem::take(&mut *LISTENERS.lock().unwrap_or_else(|err| err.into_inner()));
+        debug!("signal pipe readable, waking {} listeners", listeners.len());
+        for waker in listeners {
+            waker.wake();
+        }
+    }
+}
+
+extern "C" fn handler(signum: c_int) {
+    // only async-signal-safe work in here: an atomic add and a write. The
+    // write may clobber errno under the code the signal interrupted
+    let errno = unsafe { *errno_location() };
+    COUNTERS[signum as usize].fetch_add(1, Ordering::Release);
+
+    let fd = PIPE_WRITE.load(Ordering::Relaxed);
+    if fd != -1 {
+        // a full pipe already wakes the reactor, so errors don't matter
+        let byte = 1u8;
+        unsafe { libc::write(fd, &byte as *const u8 as *const c_void, 1) };
+    }
+
+    unsafe { *errno_location() = errno };
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__errno_location()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+unsafe fn errno_location() -> *mut c_int {
+    libc::__error()
+}
+
+// set up the pipe and the handler for signum, returns the pipe's read end
+fn install(signum: c_int) -> Result<RawFd, io::Error> {
+    let _guard = INSTALL.lock().unwrap_or_else(|err| err.into_inner());
+
+    let (read, _) = match PIPE.get() {
+        Some(pipe) => pipe,
+        None => {
+            let pipe = sys::pipe()?;
+            PIPE_WRITE.store(pipe.1.as_raw_fd(), Ordering::Relaxed);
+            PIPE.get_or_init(|| pipe)
+        }
+    };
+
+    if !INSTALLED[signum as usize].load(Ordering::Relaxed) {
+        let mut action: libc::sigaction = unsafe { mem::zeroed() };
+        action.sa_sigaction = handler as extern "C" fn(c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+
+        if unsafe { libc::sigaction(signum, &action, std::ptr::null_mut()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        debug!("installed handler for signal {}", signum);
+        INSTALLED[signum as usize].store(true, Ordering::Relaxed);
+    }
+
+    Ok(read.as_raw_fd())
+}
+
+fn drain(fd: RawFd) {
+    let mut buf = [0u8; 64];
+    while unsafe { libc::read(fd, buf.as_mut_ptr() as *mut c_void, buf.len()) } > 0 {}
+}
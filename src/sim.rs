@@ -0,0 +1,532 @@
+// an in-memory network for testing distributed logic without sockets, in
+// the spirit of turmoil. Hosts are plain IP addresses on a Network, their
+// TcpListener and TcpStream work like AsyncTcpListener and AsyncTcpStream
+// but every segment written takes the network's latency to arrive, may be
+// lost (and then arrives one retransmission timeout later, the stream stays
+// reliable) and is held while the two hosts are partitioned. Loss is drawn
+// from a generator seeded by the caller, so a run with the same seed and
+// the same writes loses the same segments. Delays are reactor timers on
+// time::now, so with the clock paused (time::pause or
+// #[fahrenheit::test(start_paused = true)]) latency and retransmissions
+// take no real time. The whole network has to live on one reactor thread.
+//
+// These are types of their own, AsyncTcpStream and AsyncTcpListener don't
+// switch to the simulated network: they are fds, with socket options,
+// AsRawFd and splice that a simulated connection has no answer for. Code
+// meant to run on both should be generic over AsyncRead + AsyncWrite and
+// take its connections from a Stream. Needs feature "sim"
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+use futures_io::{AsyncRead, AsyncWrite};
+use log::debug;
+
+use crate::rng::Rng;
+use crate::time::{self, sleep, sleep_until, Sleep};
+
+// bytes in flight per direction before writes wait for the reader
+const WINDOW: usize = 64 * 1024;
+// added to the latency for every time a segment is lost, like linux' minimum
+const RTO: Duration = Duration::from_millis(200);
+// where ports for connecting streams and port 0 binds start
+const EPHEMERAL_PORTS: u16 = 49152;
+
+#[derive(Clone)]
+pub struct Network {
+    net: Arc<Mutex<Net>>,
+}
+
+struct Net {
+    latency: Duration,
+    loss: f64,
+    rng: Rng,
+    // host pairs, lower address first
+    partitions: HashSet<(IpAddr, IpAddr)>,
+    listeners: HashMap<SocketAddr, Listener>,
+    // two per connection, one for each direction
+    pipes: HashMap<u64, Pipe>,
+    next_pipe: u64,
+    next_port: HashMap<IpAddr, u16>,
+}
+
+struct Listener {
+    backlog: VecDeque<TcpStream>,
+    waker: Option<Waker>,
+}
+
+// one direction of a connection
+struct Pipe {
+    from: IpAddr,
+    to: IpAddr,
+    segments: VecDeque<Segment>,
+    // bytes written and not read yet
+    buffered: usize,
+    // the reading side is gone, writes fail
+    read_closed: bool,
+    write_closed: bool,
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+}
+
+struct Segment {
+    data: Vec<u8>,
+    // read from data so far
+    pos: usize,
+    // an empty segment closing the direction
+    fin: bool,
+    arrives: Instant,
+}
+
+fn link(a: IpAddr, b: IpAddr) -> (IpAddr, IpAddr) {
+    (cmp::min(a, b), cmp::max(a, b))
+}
+
+impl Network {
+    // no latency, no loss, no partitions
+    pub fn new(seed: u64) -> Network {
+        Network {
+            net: Arc::new(Mutex::new(Net {
+                latency: Duration::ZERO,
+                loss: 0.0,
+                rng: Rng::new(seed),
+                partitions: HashSet::new(),
+                listeners: HashMap::new(),
+                pipes: HashMap::new(),
+                next_pipe: 0,
+                next_port: HashMap::new(),
+            })),
+        }
+    }
+
+    pub fn host<A: Into<IpAddr>>(&self, ip: A) -> Host {
+        Host {
+            net: self.clone(),
+            ip: ip.into(),
+        }
+    }
+
+    // one way delay of every segment from now on
+    pub fn set_latency(&self, latency: Duration) {
+        self.lock().latency = latency;
+    }
+
+    // chance in 0..=1 that a segment is lost, every loss delays it by RTO
+    pub fn set_loss(&self, rate: f64) {
+        self.lock().loss = rate.clamp(0.0, 0.99);
+    }
+
+    // cut a and b off from each other. Segments between them are held,
+    // connects fail with TimedOut
+    pub fn partition<A: Into<IpAddr>, B: Into<IpAddr>>(&self, a: A, b: B) {
+        let (a, b) = (a.into(), b.into());
+        debug!("partition {} <-> {}", a, b);
+
+        self.lock().partitions.insert(link(a, b));
+    }
+
+    // undo partition, held segments arrive one latency from now
+    pub fn repair<A: Into<IpAddr>, B: Into<IpAddr>>(&self, a: A, b: B) {
+        let (a, b) = (a.into(), b.into());
+        debug!("repair {} <-> {}", a, b);
+
+        let mut net = self.lock();
+        net.partitions.remove(&link(a, b));
+
+        let arrives = time::now() + net.latency;
+        for pipe in net.pipes.values_mut() {
+            if link(pipe.from, pipe.to) != link(a, b) {
+                continue;
+            }
+            for segment in &mut pipe.segments {
+                segment.arrives = cmp::max(segment.arrives, arrives);
+            }
+            if let Some(waker) = pipe.reader.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Net> {
+        self.net.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl fmt::Debug for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let net = self.lock();
+        f.debug_struct("Network")
+            .field("latency", &net.latency)
+            .field("loss", &net.loss)
+            .field("partitions", &net.partitions)
+            .field("connections", &(net.pipes.len() / 2))
+            .finish()
+    }
+}
+
+impl Net {
+    fn partitioned(&self, a: IpAddr, b: IpAddr) -> bool {
+        self.partitions.contains(&link(a, b))
+    }
+
+    fn ephemeral_port(&mut self, ip: IpAddr) -> u16 {
+        let port = self.next_port.entry(ip).or_insert(EPHEMERAL_PORTS);
+        let next = *port;
+        *port = port.checked_add(1).unwrap_or(EPHEMERAL_PORTS);
+        next
+    }
+
+    // when a segment written now shows up, never before the ones ahead of it
+    fn arrival(&mut self, id: u64) -> Instant {
+        let mut arrives = time::now() + self.latency;
+        while self.rng.chance(self.loss) {
+            arrives += RTO;
+        }
+
+        match self.pipes[&id].segments.back() {
+            Some(last) => cmp::max(arrives, last.arrives),
+            None => arrives,
+        }
+    }
+
+    fn push(&mut self, id: u64, data: Vec<u8>, fin: bool) {
+        let arrives = self.arrival(id);
+        let pipe = self.pipes.get_mut(&id).unwrap();
+
+        pipe.buffered += data.len();
+        pipe.segments.push_back(Segment {
+            data,
+            pos: 0,
+            fin,
+            arrives,
+        });
+        if let Some(waker) = pipe.reader.take() {
+            waker.wake();
+        }
+    }
+}
+
+// an address on the network
+#[derive(Debug, Clone)]
+pub struct Host {
+    net: Network,
+    ip: IpAddr,
+}
+
+impl Host {
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    // port 0 picks a free one
+    pub fn bind(&self, port: u16) -> Result<TcpListener, io::Error> {
+        let mut net = self.net.lock();
+        let port = match port {
+            0 => net.ephemeral_port(self.ip),
+            port => port,
+        };
+        let addr = SocketAddr::new(self.ip, port);
+
+        if net.listeners.contains_key(&addr) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        net.listeners.insert(
+            addr,
+            Listener {
+                backlog: VecDeque::new(),
+                waker: None,
+            },
+        );
+
+        Ok(TcpListener {
+            net: self.net.clone(),
+            addr,
+        })
+    }
+
+    // takes a round trip. Nobody listening is ConnectionRefused, a
+    // partition between the hosts TimedOut
+    pub async fn connect(&self, addr: SocketAddr) -> Result<TcpStream, io::Error> {
+        let latency = self.net.lock().latency;
+        sleep(latency * 2).await;
+
+        let mut net = self.net.lock();
+        if net.partitioned(self.ip, addr.ip()) {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        if !net.listeners.contains_key(&addr) {
+            return Err(io::ErrorKind::ConnectionRefused.into());
+        }
+
+        let local = SocketAddr::new(self.ip, net.ephemeral_port(self.ip));
+        let up = net.next_pipe;
+        net.next_pipe += 2;
+        for (id, from, to) in [(up, local, addr), (up + 1, addr, local)] {
+            net.pipes.insert(
+                id,
+                Pipe {
+                    from: from.ip(),
+                    to: to.ip(),
+                    segments: VecDeque::new(),
+                    buffered: 0,
+                    read_closed: false,
+                    write_closed: false,
+                    reader: None,
+                    writer: None,
+                },
+            );
+        }
+        debug!("connection {} -> {}", local, addr);
+
+        let accepted = TcpStream::new(&self.net, addr, local, up, up + 1);
+        let listener = net.listeners.get_mut(&addr).unwrap();
+        listener.backlog.push_back(accepted);
+        if let Some(waker) = listener.waker.take() {
+            waker.wake();
+        }
+
+        Ok(TcpStream::new(&self.net, local, addr, up + 1, up))
+    }
+}
+
+pub struct TcpListener {
+    net: Network,
+    addr: SocketAddr,
+}
+
+impl TcpListener {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub async fn accept(&self) -> Result<(TcpStream, SocketAddr), io::Error> {
+        poll_fn(|ctx| self.poll_accept(ctx)).await
+    }
+
+    pub fn poll_accept(
+        &self,
+        ctx: &mut Context,
+    ) -> Poll<Result<(TcpStream, SocketAddr), io::Error>> {
+        let mut net = self.net.lock();
+        let listener = net.listeners.get_mut(&self.addr).unwrap();
+
+        match listener.backlog.pop_front() {
+            Some(stream) => {
+                let peer = stream.peer;
+                Poll::Ready(Ok((stream, peer)))
+            }
+            None => {
+                listener.waker = Some(ctx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Stream for TcpListener {
+    type Item = Result<TcpStream, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.poll_accept(ctx)
+            .map(|res| Some(res.map(|(stream, _)| stream)))
+    }
+}
+
+// connections nobody accepted are reset
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        let listener = self.net.lock().listeners.remove(&self.addr);
+        drop(listener);
+    }
+}
+
+impl fmt::Debug for TcpListener {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TcpListener")
+            .field("addr", &self.addr)
+            .finish()
+    }
+}
+
+pub struct TcpStream {
+    net: Network,
+    local: SocketAddr,
+    peer: SocketAddr,
+    read: u64,
+    write: u64,
+    // waiting for the next segment to arrive
+    delay: Sleep,
+}
+
+impl TcpStream {
+    fn new(net: &Network, local: SocketAddr, peer: SocketAddr, read: u64, write: u64) -> TcpStream {
+        TcpStream {
+            net: net.clone(),
+            local,
+            peer,
+            read,
+            write,
+            delay: sleep_until(time::now()),
+        }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        loop {
+            let mut net = this.net.lock();
+            let held = net.partitioned(this.local.ip(), this.peer.ip());
+            let pipe = net.pipes.get_mut(&this.read).unwrap();
+
+            let segment = match pipe.segments.front_mut() {
+                Some(segment) => segment,
+                None if buf.is_empty() => return Poll::Ready(Ok(0)),
+                None => {
+                    pipe.reader = Some(ctx.waker().clone());
+                    return Poll::Pending;
+                }
+            };
+
+            // repair wakes us
+            if held {
+                pipe.reader = Some(ctx.waker().clone());
+                return Poll::Pending;
+            }
+
+            if segment.arrives > time::now() {
+                // an earlier arrival after repair wakes us through the pipe
+                let arrives = segment.arrives;
+                pipe.reader = Some(ctx.waker().clone());
+                drop(net);
+
+                this.delay.reset(arrives);
+                match Pin::new(&mut this.delay).poll(ctx) {
+                    Poll::Ready(()) => continue,
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            // stays in front, every read after it sees EOF
+            if segment.fin {
+                return Poll::Ready(Ok(0));
+            }
+
+            let n = cmp::min(buf.len(), segment.data.len() - segment.pos);
+            buf[..n].copy_from_slice(&segment.data[segment.pos..segment.pos + n]);
+            segment.pos += n;
+            if segment.pos == segment.data.len() {
+                pipe.segments.pop_front();
+            }
+
+            pipe.buffered -= n;
+            if let Some(waker) = pipe.writer.take() {
+                waker.wake();
+            }
+            return Poll::Ready(Ok(n));
+        }
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let mut net = self.net.lock();
+        let pipe = net.pipes.get_mut(&self.write).unwrap();
+
+        if pipe.write_closed {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "write after close",
+            )));
+        }
+        if pipe.read_closed {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let n = cmp::min(buf.len(), WINDOW.saturating_sub(pipe.buffered));
+        if n == 0 {
+            pipe.writer = Some(ctx.waker().clone());
+            return Poll::Pending;
+        }
+
+        net.push(self.write, buf[..n].to_vec(), false);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // the peer reads EOF once everything before it has arrived
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), io::Error>> {
+        let mut net = self.net.lock();
+        let pipe = net.pipes.get_mut(&self.write).unwrap();
+
+        if !pipe.write_closed {
+            pipe.write_closed = true;
+            net.push(self.write, Vec::new(), true);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+// close both directions, the connection is gone once both ends are
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        let mut net = self.net.lock();
+
+        let pipe = net.pipes.get_mut(&self.write).unwrap();
+        if !pipe.write_closed {
+            pipe.write_closed = true;
+            net.push(self.write, Vec::new(), true);
+        }
+
+        let pipe = net.pipes.get_mut(&self.read).unwrap();
+        pipe.read_closed = true;
+        if let Some(waker) = pipe.writer.take() {
+            waker.wake();
+        }
+
+        if net.pipes[&self.write].read_closed {
+            net.pipes.remove(&self.read);
+            net.pipes.remove(&self.write);
+        }
+    }
+}
+
+impl fmt::Debug for TcpStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TcpStream")
+            .field("local", &self.local)
+            .field("peer", &self.peer)
+            .finish()
+    }
+}
@@ -0,0 +1,247 @@
+// zero-copy proxying between two tcp streams.
+//
+// on Linux the bytes are moved socket -> pipe -> socket with splice(2) and
+// never enter user space. Elsewhere we fall back to a plain buffered copy.
+use std::future::poll_fn;
+use std::io;
+use std::net::Shutdown;
+use std::task::{Context, Poll};
+
+use log::debug;
+
+use crate::AsyncTcpStream;
+
+// copy data in both directions until both sides reach EOF. When one side
+// finishes sending, the write half of the other side is shut down so
+// the half-close propagates. Returns the number of bytes sent a -> b and b -> a
+pub async fn splice_bidirectional(
+    a: &mut AsyncTcpStream,
+    b: &mut AsyncTcpStream,
+) -> Result<(u64, u64), io::Error> {
+    let mut a_to_b = Transfer::new()?;
+    let mut b_to_a = Transfer::new()?;
+
+    poll_fn(|ctx| {
+        let a_to_b = a_to_b.poll(ctx, a, b)?;
+        let b_to_a = b_to_a.poll(ctx, b, a)?;
+
+        match (a_to_b, b_to_a) {
+            (Poll::Ready(a_to_b), Poll::Ready(b_to_a)) => Poll::Ready(Ok((a_to_b, b_to_a))),
+            _ => Poll::Pending,
+        }
+    })
+    .await
+}
+
+// one direction of the copy
+#[cfg(any(target_os = "linux", target_os = "android"))]
+struct Transfer {
+    pipe: Pipe,
+    // bytes sitting in the pipe
+    buffered: usize,
+    total: u64,
+    eof: bool,
+    done: bool,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Transfer {
+    fn new() -> Result<Transfer, io::Error> {
+        Ok(Transfer {
+            pipe: Pipe::new()?,
+            buffered: 0,
+            total: 0,
+            eof: false,
+            done: false,
+        })
+    }
+
+    fn poll(
+        &mut self,
+        ctx: &mut Context,
+        src: &AsyncTcpStream,
+        dst: &AsyncTcpStream,
+    ) -> Poll<Result<u64, io::Error>> {
+        use std::os::unix::io::AsRawFd;
+
+        if self.done {
+            return Poll::Ready(Ok(self.total));
+        }
+
+        let src_fd = src.0.as_raw_fd();
+        let dst_fd = dst.0.as_raw_fd();
+
+        loop {
+            // only refill an empty pipe, so EAGAIN always means
+            // the source socket has nothing to read
+            if self.buffered == 0 && !self.eof {
+                match splice(src_fd, self.pipe.write, PIPE_SIZE) {
+                    Ok(0) => self.eof = true,
+                    Ok(len) => self.buffered = len,
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        src.register_read(ctx);
+
+                        return Poll::Pending;
+                    }
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+
+            while self.buffered > 0 {
+                match splice(self.pipe.read, dst_fd, self.buffered) {
+                    Ok(len) => {
+                        self.buffered -= len;
+                        self.total += len as u64;
+                    }
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        dst.register_write(ctx);
+
+                        return Poll::Pending;
+                    }
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+
+            if self.eof {
+                debug!("splice {} -> {} done, {} bytes", src_fd, dst_fd, self.total);
+
+                self.done = true;
+                dst.shutdown(Shutdown::Write)?;
+
+                return Poll::Ready(Ok(self.total));
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const PIPE_SIZE: usize = 64 * 1024;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn splice(from: libc::c_int, to: libc::c_int, len: usize) -> Result<usize, io::Error> {
+    let rv = unsafe {
+        libc::splice(
+            from,
+            std::ptr::null_mut(),
+            to,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_NONBLOCK,
+        )
+    };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(rv as usize)
+}
+
+// non-blocking pipe used as the in-kernel buffer
+#[cfg(any(target_os = "linux", target_os = "android"))]
+struct Pipe {
+    read: libc::c_int,
+    write: libc::c_int,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Pipe {
+    fn new() -> Result<Pipe, io::Error> {
+        let mut fds = [0; 2];
+
+        let rv = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Pipe {
+            read: fds[0],
+            write: fds[1],
+        })
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+// buffered fallback for systems without splice
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+struct Transfer {
+    buf: Box<[u8]>,
+    pos: usize,
+    cap: usize,
+    total: u64,
+    eof: bool,
+    done: bool,
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+impl Transfer {
+    fn new() -> Result<Transfer, io::Error> {
+        Ok(Transfer {
+            buf: vec![0; 8 * 1024].into_boxed_slice(),
+            pos: 0,
+            cap: 0,
+            total: 0,
+            eof: false,
+            done: false,
+        })
+    }
+
+    fn poll(
+        &mut self,
+        ctx: &mut Context,
+        src: &AsyncTcpStream,
+        dst: &AsyncTcpStream,
+    ) -> Poll<Result<u64, io::Error>> {
+        if self.done {
+            return Poll::Ready(Ok(self.total));
+        }
+
+        loop {
+            if self.pos == self.cap && !self.eof {
+                match src.try_read(&mut self.buf) {
+                    Ok(0) => self.eof = true,
+                    Ok(len) => {
+                        self.pos = 0;
+                        self.cap = len;
+                    }
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        src.register_read(ctx);
+
+                        return Poll::Pending;
+                    }
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+
+            while self.pos < self.cap {
+                match dst.try_write(&self.buf[self.pos..self.cap]) {
+                    Ok(len) => {
+                        self.pos += len;
+                        self.total += len as u64;
+                    }
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        dst.register_write(ctx);
+
+                        return Poll::Pending;
+                    }
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+
+            if self.eof {
+                self.done = true;
+                dst.shutdown(Shutdown::Write)?;
+
+                return Poll::Ready(Ok(self.total));
+            }
+        }
+    }
+}
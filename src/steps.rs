@@ -0,0 +1,87 @@
+// a bounded run for tests: a future that never finishes fails the test
+// after a number of loop iterations instead of hanging CI
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::panic::Location;
+
+use log::debug;
+
+use crate::{Dump, EventLoop, REACTOR};
+
+// like run, but gives up after max_iterations trips through select. The
+// tasks still pending are dropped and the reactor is clean for the next run.
+// An iteration with nothing to do waits up to a second in select. A select
+// failure gives up the same way, with the error in Incomplete
+#[track_caller]
+pub fn run_steps<F: Future<Output = ()> + Send + 'static>(
+    f: F,
+    max_iterations: u64,
+) -> Result<(), Incomplete> {
+    let loc = Location::caller();
+
+    REACTOR.with(|reactor| {
+        let error = match reactor.run(f, loc, Some(max_iterations)) {
+            Ok(true) => return Ok(()),
+            Ok(false) => None,
+            Err(err) => {
+                debug!("event loop failed: {}", err);
+                Some(err)
+            }
+        };
+
+        let dump = reactor.dump();
+        reactor.abandon();
+        Err(Incomplete {
+            iterations: max_iterations,
+            dump,
+            error,
+        })
+    })
+}
+
+#[derive(Debug)]
+pub struct Incomplete {
+    pub iterations: u64,
+    // what the tasks were up to when the run was given up
+    pub dump: Dump,
+    // set if the event loop failed before running out of iterations
+    pub error: Option<io::Error>,
+}
+
+impl fmt::Display for Incomplete {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.error {
+            Some(ref err) => write!(f, "event loop failed: {}, {}", err, self.dump),
+            None => write!(
+                f,
+                "did not complete in {} iterations, {}",
+                self.iterations, self.dump
+            ),
+        }
+    }
+}
+
+impl Error for Incomplete {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.as_ref().map(|err| err as &(dyn Error + 'static))
+    }
+}
+
+impl EventLoop {
+    // drop every task and interest
+    pub(crate) fn abandon(&self) {
+        // dropped futures deregister their fds and timers, so don't hold
+        // any borrow while they go
+        let tasks = mem::take(&mut *self.wait_queue.borrow_mut());
+        let wakeups = mem::take(&mut *self.run_queue.borrow_mut());
+        drop(tasks);
+        drop(wakeups);
+
+        self.read.borrow_mut().clear();
+        self.write.borrow_mut().clear();
+        self.timers.borrow_mut().clear();
+    }
+}
@@ -0,0 +1,32 @@
+// lets plain threads wait on the sync primitives. The future is polled on
+// the calling thread, which parks until the waker unparks it. Calling this
+// from a task blocks the whole reactor
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread::{self, Thread};
+
+use futures_task::ArcWake;
+
+struct Unpark(Thread);
+
+impl ArcWake for Unpark {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+pub(crate) fn block_on<F: Future>(f: F) -> F::Output {
+    let mut f = pin!(f);
+    let waker = futures_task::waker(Arc::new(Unpark(thread::current())));
+    let mut ctx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(out) = f.as_mut().poll(&mut ctx) {
+            return out;
+        }
+        // an unpark that came early makes this return right away
+        thread::park();
+    }
+}
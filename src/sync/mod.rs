@@ -0,0 +1,16 @@
+// synchronization between tasks. Wakers reach their reactor from any
+// thread, so the other end of a channel may live on another thread
+pub mod mpsc;
+pub mod watch;
+
+mod blocking;
+mod mutex;
+mod once_cell;
+mod semaphore;
+mod wait_group;
+pub use self::mutex::{Mutex, MutexGuard, TryLockError};
+pub use self::once_cell::{Lazy, OnceCell};
+pub use self::semaphore::{
+    AcquireError, OwnedSemaphorePermit, Semaphore, SemaphorePermit, TryAcquireError,
+};
+pub use self::wait_group::WaitGroup;
@@ -0,0 +1,463 @@
+// multi-producer, single-consumer channels
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::sync::blocking::block_on;
+
+// a channel holding at most capacity messages. send() waits while it's
+// full, so a slow receiver slows the senders down
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "mpsc channel capacity must be non-zero");
+
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            queue: VecDeque::new(),
+            capacity,
+            reserved: 0,
+            senders: 1,
+            receiver_alive: true,
+            recv_waker: None,
+            send_wakers: Vec::new(),
+        }),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+            reserved: false,
+        },
+        Receiver { shared },
+    )
+}
+
+// a channel without a limit, send() never waits. Nothing slows the senders
+// down, a receiver that falls behind lets the queue grow
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let (tx, rx) = channel(usize::MAX);
+    (UnboundedSender(tx), UnboundedReceiver(rx))
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    // slots promised to senders by Sink::poll_ready
+    reserved: usize,
+    senders: usize,
+    receiver_alive: bool,
+    recv_waker: Option<Waker>,
+    // senders waiting for room
+    send_wakers: Vec<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl<T> State<T> {
+    fn has_room(&self) -> bool {
+        self.queue.len() + self.reserved < self.capacity
+    }
+
+    fn wake_receiver(&mut self) {
+        if let Some(waker) = self.recv_waker.take() {
+            waker.wake();
+        }
+    }
+
+    // all of them, one of the senders may have given up waiting and
+    // would swallow the wakeup
+    fn wake_senders(&mut self) {
+        for waker in self.send_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    // this sender holds one of the reserved slots
+    reserved: bool,
+}
+
+impl<T> Sender<T> {
+    // wait for room and queue value. Fails, handing value back, once the
+    // receiver is gone
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        poll_fn(|ctx| self.poll_send(ctx, &mut value)).await
+    }
+
+    fn poll_send(
+        &self,
+        ctx: &mut Context,
+        value: &mut Option<T>,
+    ) -> Poll<Result<(), SendError<T>>> {
+        let mut state = self.shared.lock();
+        let v = value.take().expect("polled after completion");
+
+        if !state.receiver_alive {
+            return Poll::Ready(Err(SendError(v)));
+        }
+
+        if state.has_room() {
+            state.queue.push_back(v);
+            state.wake_receiver();
+            return Poll::Ready(Ok(()));
+        }
+
+        *value = Some(v);
+        let waker = ctx.waker();
+        if !state.send_wakers.iter().any(|w| w.will_wake(waker)) {
+            state.send_wakers.push(waker.clone());
+        }
+        Poll::Pending
+    }
+
+    // send from a plain thread, e.g. inside spawn_blocking, parking it
+    // while the channel is full. Must not be called from a task
+    pub fn blocking_send(&self, value: T) -> Result<(), SendError<T>> {
+        block_on(self.send(value))
+    }
+
+    // queue value if there's room right now
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+
+        if !state.receiver_alive {
+            return Err(TrySendError::Closed(value));
+        }
+        if !state.has_room() {
+            return Err(TrySendError::Full(value));
+        }
+
+        state.queue.push_back(value);
+        state.wake_receiver();
+        Ok(())
+    }
+
+    // the receiver was dropped or closed
+    pub fn is_closed(&self) -> bool {
+        !self.shared.lock().receiver_alive
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.lock().capacity
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Sender<T> {
+        self.shared.lock().senders += 1;
+
+        Sender {
+            shared: self.shared.clone(),
+            reserved: false,
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+
+        if self.reserved {
+            state.reserved -= 1;
+            state.wake_senders();
+        }
+
+        // the receiver sees the end of the stream
+        if state.senders == 0 {
+            state.wake_receiver();
+        }
+    }
+}
+
+// poll_ready reserves a slot so start_send can't find the channel full.
+// Once the receiver is gone poll_ready is Ready and start_send hands the
+// value back
+impl<T> Sink<T> for Sender<T> {
+    type Error = SendError<T>;
+
+    fn poll_ready(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), SendError<T>>> {
+        let this = self.get_mut();
+        if this.reserved {
+            return Poll::Ready(Ok(()));
+        }
+
+        let mut state = this.shared.lock();
+        if !state.receiver_alive {
+            return Poll::Ready(Ok(()));
+        }
+        if state.has_room() {
+            state.reserved += 1;
+            this.reserved = true;
+            return Poll::Ready(Ok(()));
+        }
+
+        let waker = ctx.waker();
+        if !state.send_wakers.iter().any(|w| w.will_wake(waker)) {
+            state.send_wakers.push(waker.clone());
+        }
+        Poll::Pending
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), SendError<T>> {
+        let this = self.get_mut();
+        if !this.reserved {
+            return this
+                .try_send(item)
+                .map_err(|err| SendError(err.into_inner()));
+        }
+
+        let mut state = this.shared.lock();
+        state.reserved -= 1;
+        this.reserved = false;
+
+        if !state.receiver_alive {
+            return Err(SendError(item));
+        }
+        state.queue.push_back(item);
+        state.wake_receiver();
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), SendError<T>>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), SendError<T>>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sender").finish()
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Receiver<T> {
+    // the next message, None once all senders are gone and the queue is empty
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|ctx| self.poll_recv(ctx)).await
+    }
+
+    pub fn poll_recv(&mut self, ctx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.shared.lock();
+
+        if let Some(value) = state.queue.pop_front() {
+            state.wake_senders();
+            return Poll::Ready(Some(value));
+        }
+
+        if state.senders == 0 || !state.receiver_alive {
+            return Poll::Ready(None);
+        }
+
+        state.recv_waker = Some(ctx.waker().clone());
+        Poll::Pending
+    }
+
+    // recv from a plain thread, parking it until a message arrives. Must
+    // not be called from a task
+    pub fn blocking_recv(&mut self) -> Option<T> {
+        block_on(self.recv())
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+
+        match state.queue.pop_front() {
+            Some(value) => {
+                state.wake_senders();
+                Ok(value)
+            }
+            None if state.senders == 0 || !state.receiver_alive => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    // refuse new messages, what's queued can still be received
+    pub fn close(&mut self) {
+        let mut state = self.shared.lock();
+        state.receiver_alive = false;
+        state.wake_senders();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.close();
+
+        // drop the queued messages now rather than with the last sender
+        let queue = std::mem::take(&mut self.shared.lock().queue);
+        drop(queue);
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(ctx)
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Receiver").finish()
+    }
+}
+
+pub struct UnboundedSender<T>(Sender<T>);
+
+impl<T> UnboundedSender<T> {
+    // queue value, fails only once the receiver is gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.0
+            .try_send(value)
+            .map_err(|err| SendError(err.into_inner()))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> UnboundedSender<T> {
+        UnboundedSender(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for UnboundedSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnboundedSender").finish()
+    }
+}
+
+pub struct UnboundedReceiver<T>(Receiver<T>);
+
+impl<T> UnboundedReceiver<T> {
+    pub async fn recv(&mut self) -> Option<T> {
+        self.0.recv().await
+    }
+
+    pub fn poll_recv(&mut self, ctx: &mut Context) -> Poll<Option<T>> {
+        self.0.poll_recv(ctx)
+    }
+
+    pub fn blocking_recv(&mut self) -> Option<T> {
+        self.0.blocking_recv()
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        self.0.try_recv()
+    }
+
+    pub fn close(&mut self) {
+        self.0.close()
+    }
+}
+
+impl<T> Stream for UnboundedReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().0.poll_recv(ctx)
+    }
+}
+
+impl<T> fmt::Debug for UnboundedReceiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UnboundedReceiver").finish()
+    }
+}
+
+// the receiver is gone, the message comes back
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "channel closed")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(value) | TrySendError::Closed(value) => value,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "Full(..)"),
+            TrySendError::Closed(_) => write!(f, "Closed(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => write!(f, "channel full"),
+            TrySendError::Closed(_) => write!(f, "channel closed"),
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => write!(f, "channel empty"),
+            TryRecvError::Disconnected => write!(f, "channel disconnected"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
@@ -0,0 +1,138 @@
+// values set once, with async initialization. When many tasks race to
+// initialize, one of them runs the init future and the rest wait for it;
+// if it's cancelled or fails the next waiter gets its turn
+use std::fmt;
+use std::future::Future;
+use std::sync::OnceLock;
+
+use crate::sync::Semaphore;
+
+pub struct OnceCell<T> {
+    value: OnceLock<T>,
+    // held by the task running the initializer
+    init: Semaphore,
+}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> OnceCell<T> {
+        OnceCell {
+            value: OnceLock::new(),
+            init: Semaphore::new(1),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    pub fn initialized(&self) -> bool {
+        self.get().is_some()
+    }
+
+    // hands value back if the cell is already set. An initializer running
+    // at the same time loses, its value is dropped
+    pub fn set(&self, value: T) -> Result<(), T> {
+        self.value.set(value)
+    }
+
+    // the value, running f to make it if nobody has yet
+    pub async fn get_or_init<F, Fut>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        // the semaphore is never closed
+        let _permit = self.init.acquire().await.unwrap();
+        if let Some(value) = self.get() {
+            return value;
+        }
+
+        let value = f().await;
+        self.value.get_or_init(|| value)
+    }
+
+    // like get_or_init, an error leaves the cell empty for the next caller
+    pub async fn get_or_try_init<F, Fut, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        let _permit = self.init.acquire().await.unwrap();
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+
+        let value = f().await?;
+        Ok(self.value.get_or_init(|| value))
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        self.value.take()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> OnceCell<T> {
+        OnceCell::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T> {
+    fn from(value: T) -> OnceCell<T> {
+        let cell = OnceCell::new();
+        let _ = cell.set(value);
+        cell
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OnceCell").field(&self.get()).finish()
+    }
+}
+
+// a value made by init on first use
+pub struct Lazy<T, F> {
+    cell: OnceCell<T>,
+    init: F,
+}
+
+impl<T, F, Fut> Lazy<T, F>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = T>,
+{
+    pub const fn new(init: F) -> Lazy<T, F> {
+        Lazy {
+            cell: OnceCell::new(),
+            init,
+        }
+    }
+
+    // the value, initializing it on the first call
+    pub async fn force(&self) -> &T {
+        self.cell.get_or_init(&self.init).await
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.cell.get()
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Lazy").field(&self.cell.get()).finish()
+    }
+}
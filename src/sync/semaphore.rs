@@ -0,0 +1,285 @@
+// a counting semaphore, e.g. to cap how many requests are in flight.
+// Waiters are served in order, a task asking for a permit can't be starved
+// by others that keep grabbing released ones
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+pub struct Semaphore {
+    state: Mutex<State>,
+}
+
+struct State {
+    permits: usize,
+    closed: bool,
+    // tasks waiting for a permit, oldest first
+    waiters: VecDeque<(u64, Waker)>,
+    next_id: u64,
+}
+
+impl State {
+    // the oldest waiter is next in line for a free permit
+    fn wake_next(&mut self) {
+        if self.permits > 0 {
+            if let Some((_, waker)) = self.waiters.front() {
+                waker.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Semaphore {
+        Semaphore {
+            state: Mutex::new(State {
+                permits,
+                closed: false,
+                waiters: VecDeque::new(),
+                next_id: 0,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // wait for a permit, it's handed back when the guard is dropped
+    pub async fn acquire(&self) -> Result<SemaphorePermit<'_>, AcquireError> {
+        self.wait().await?;
+        Ok(SemaphorePermit { sem: self })
+    }
+
+    // like acquire but the permit keeps the semaphore alive, so it can be
+    // moved into a spawned task
+    pub async fn acquire_owned(self: Arc<Self>) -> Result<OwnedSemaphorePermit, AcquireError> {
+        self.wait().await?;
+        Ok(OwnedSemaphorePermit {
+            sem: self,
+            forgotten: false,
+        })
+    }
+
+    pub fn try_acquire(&self) -> Result<SemaphorePermit<'_>, TryAcquireError> {
+        self.take()?;
+        Ok(SemaphorePermit { sem: self })
+    }
+
+    pub fn try_acquire_owned(self: Arc<Self>) -> Result<OwnedSemaphorePermit, TryAcquireError> {
+        self.take()?;
+        Ok(OwnedSemaphorePermit {
+            sem: self,
+            forgotten: false,
+        })
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.lock().permits
+    }
+
+    pub fn add_permits(&self, n: usize) {
+        let mut state = self.lock();
+        state.permits += n;
+        state.wake_next();
+    }
+
+    // fail everyone waiting and all future acquires. Permits already held
+    // stay valid
+    pub fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+
+        for (_, waker) in state.waiters.drain(..) {
+            waker.wake();
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    // a permit right now, without jumping the queue
+    fn take(&self) -> Result<(), TryAcquireError> {
+        let mut state = self.lock();
+
+        if state.closed {
+            return Err(TryAcquireError::Closed);
+        }
+        if state.permits == 0 || !state.waiters.is_empty() {
+            return Err(TryAcquireError::NoPermits);
+        }
+
+        state.permits -= 1;
+        Ok(())
+    }
+
+    async fn wait(&self) -> Result<(), AcquireError> {
+        let mut waiter = Waiter {
+            sem: self,
+            id: None,
+        };
+        poll_fn(|ctx| waiter.poll(ctx)).await
+    }
+
+    fn release(&self) {
+        let mut state = self.lock();
+        state.permits += 1;
+        state.wake_next();
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("Semaphore")
+            .field("permits", &state.permits)
+            .field("waiters", &state.waiters.len())
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+// a place in the queue, given up when the acquire future is dropped
+struct Waiter<'a> {
+    sem: &'a Semaphore,
+    id: Option<u64>,
+}
+
+impl Waiter<'_> {
+    fn poll(&mut self, ctx: &mut Context) -> Poll<Result<(), AcquireError>> {
+        let mut state = self.sem.lock();
+
+        if state.closed {
+            self.id = None;
+            return Poll::Ready(Err(AcquireError(())));
+        }
+
+        let first = match (self.id, state.waiters.front()) {
+            (_, None) => true,
+            (Some(id), Some(&(front, _))) => id == front,
+            (None, Some(_)) => false,
+        };
+
+        if first && state.permits > 0 {
+            state.permits -= 1;
+            if self.id.take().is_some() {
+                state.waiters.pop_front();
+            }
+            state.wake_next();
+            return Poll::Ready(Ok(()));
+        }
+
+        let waker = ctx.waker().clone();
+        match self.id {
+            Some(id) => {
+                if let Some(entry) = state.waiters.iter_mut().find(|(i, _)| *i == id) {
+                    entry.1 = waker;
+                }
+            }
+            None => {
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiters.push_back((id, waker));
+                self.id = Some(id);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.sem.lock();
+            state.waiters.retain(|(i, _)| *i != id);
+            // we may have been woken for a permit we won't take
+            state.wake_next();
+        }
+    }
+}
+
+// a permit borrowed from a semaphore, released on drop
+pub struct SemaphorePermit<'a> {
+    sem: &'a Semaphore,
+}
+
+impl SemaphorePermit<'_> {
+    // keep the permit, the semaphore has one less from now on
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.sem.release();
+    }
+}
+
+impl fmt::Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SemaphorePermit").finish()
+    }
+}
+
+// a permit holding on to its semaphore, released on drop
+pub struct OwnedSemaphorePermit {
+    sem: Arc<Semaphore>,
+    forgotten: bool,
+}
+
+impl OwnedSemaphorePermit {
+    pub fn semaphore(&self) -> &Arc<Semaphore> {
+        &self.sem
+    }
+
+    pub fn forget(mut self) {
+        self.forgotten = true;
+    }
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        if !self.forgotten {
+            self.sem.release();
+        }
+    }
+}
+
+impl fmt::Debug for OwnedSemaphorePermit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OwnedSemaphorePermit").finish()
+    }
+}
+
+// the semaphore was closed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AcquireError(());
+
+impl fmt::Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "semaphore closed")
+    }
+}
+
+impl Error for AcquireError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryAcquireError {
+    Closed,
+    NoPermits,
+}
+
+impl fmt::Display for TryAcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TryAcquireError::Closed => write!(f, "semaphore closed"),
+            TryAcquireError::NoPermits => write!(f, "no permits available"),
+        }
+    }
+}
+
+impl Error for TryAcquireError {}
@@ -0,0 +1,100 @@
+// waits for a group of tasks to finish. Every clone is one piece of work,
+// hand one to each connection handler and wait() once they're all dropped
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+pub struct WaitGroup {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    state: Mutex<State>,
+}
+
+struct State {
+    count: usize,
+    wakers: Vec<Waker>,
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl WaitGroup {
+    pub fn new() -> WaitGroup {
+        WaitGroup {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    count: 1,
+                    wakers: Vec::new(),
+                }),
+            }),
+        }
+    }
+
+    // clones still alive, this one included
+    pub fn count(&self) -> usize {
+        self.inner.lock().count
+    }
+
+    // drop this handle and wait for all the others to be dropped
+    pub async fn wait(self) {
+        let inner = self.inner.clone();
+        drop(self);
+
+        poll_fn(|ctx| {
+            let mut state = inner.lock();
+            if state.count == 0 {
+                return Poll::Ready(());
+            }
+
+            let waker = ctx.waker();
+            if !state.wakers.iter().any(|w| w.will_wake(waker)) {
+                state.wakers.push(waker.clone());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> WaitGroup {
+        WaitGroup::new()
+    }
+}
+
+impl Clone for WaitGroup {
+    fn clone(&self) -> WaitGroup {
+        self.inner.lock().count += 1;
+
+        WaitGroup {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl Drop for WaitGroup {
+    fn drop(&mut self) {
+        let mut state = self.inner.lock();
+        state.count -= 1;
+
+        if state.count == 0 {
+            for waker in state.wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl fmt::Debug for WaitGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WaitGroup")
+            .field("count", &self.count())
+            .finish()
+    }
+}
@@ -0,0 +1,284 @@
+// a single value many tasks can watch. Receivers always see the latest
+// value and changed() tells them when there's a newer one; values sent in
+// between are skipped
+use std::error::Error;
+use std::fmt;
+use std::future::poll_fn;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard};
+use std::task::{Context, Poll, Waker};
+
+use futures_core::Stream;
+
+pub fn channel<T>(init: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        value: RwLock::new(init),
+        state: Mutex::new(State {
+            version: 0,
+            receivers: 1,
+            sender_alive: true,
+            wakers: Vec::new(),
+        }),
+    });
+
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, seen: 0 },
+    )
+}
+
+struct Shared<T> {
+    value: RwLock<T>,
+    state: Mutex<State>,
+}
+
+struct State {
+    // bumped on every send
+    version: u64,
+    receivers: usize,
+    sender_alive: bool,
+    // receivers waiting in changed()
+    wakers: Vec<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn read(&self) -> Ref<'_, T> {
+        Ref(self.value.read().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    // replace the value and tell the receivers
+    fn modify<F: FnOnce(&mut T)>(&self, f: F) {
+        {
+            let mut value = self.value.write().unwrap_or_else(|err| err.into_inner());
+            f(&mut value);
+        }
+
+        let mut state = self.lock();
+        state.version += 1;
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+// read access to the current value. Holding it blocks senders, don't keep
+// it across an await
+pub struct Ref<'a, T>(RwLockReadGuard<'a, T>);
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Ref<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T> {
+    // store a new value. Fails, handing it back, if there are no receivers
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        if self.shared.lock().receivers == 0 {
+            return Err(SendError(value));
+        }
+
+        self.shared.modify(|old| *old = value);
+        Ok(())
+    }
+
+    // store a new value even without receivers, returns the old one
+    pub fn send_replace(&self, value: T) -> T {
+        let mut value = Some(value);
+        let mut old = None;
+        self.shared.modify(|current| {
+            old = Some(std::mem::replace(current, value.take().unwrap()));
+        });
+        old.unwrap()
+    }
+
+    // change the value in place, receivers are told it changed
+    pub fn send_modify<F: FnOnce(&mut T)>(&self, f: F) {
+        self.shared.modify(f);
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.shared.read()
+    }
+
+    // a new receiver that has seen the current value
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.lock();
+        state.receivers += 1;
+
+        Receiver {
+            shared: self.shared.clone(),
+            seen: state.version,
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.shared.lock().receivers
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.receiver_count() == 0
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.sender_alive = false;
+
+        for waker in state.wakers.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sender")
+            .field("value", &*self.borrow())
+            .finish()
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    // version of the value this receiver saw last
+    seen: u64,
+}
+
+impl<T> Receiver<T> {
+    // the current value, doesn't count as seen
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.shared.read()
+    }
+
+    // the current value, marked as seen
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        self.seen = self.shared.lock().version;
+        self.shared.read()
+    }
+
+    // a value newer than the one seen last is waiting. Err once the sender
+    // is gone
+    pub fn has_changed(&self) -> Result<bool, RecvError> {
+        let state = self.shared.lock();
+
+        if !state.sender_alive {
+            return Err(RecvError(()));
+        }
+        Ok(state.version != self.seen)
+    }
+
+    // wait for a value newer than the one seen last and mark it as seen.
+    // Err once the sender is gone, the last value can still be borrowed
+    pub async fn changed(&mut self) -> Result<(), RecvError> {
+        poll_fn(|ctx| self.poll_changed(ctx)).await
+    }
+
+    pub(crate) fn poll_changed(&mut self, ctx: &mut Context) -> Poll<Result<(), RecvError>> {
+        let mut state = self.shared.lock();
+
+        if state.version != self.seen {
+            self.seen = state.version;
+            return Poll::Ready(Ok(()));
+        }
+        if !state.sender_alive {
+            return Poll::Ready(Err(RecvError(())));
+        }
+
+        let waker = ctx.waker();
+        if !state.wakers.iter().any(|w| w.will_wake(waker)) {
+            state.wakers.push(waker.clone());
+        }
+        Poll::Pending
+    }
+}
+
+// yields a copy of each new value, ends once the sender is gone
+impl<T: Clone> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Option<T>> {
+        let this = self.get_mut();
+
+        match this.poll_changed(ctx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Some(this.borrow().clone())),
+            Poll::Ready(Err(_)) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Receiver<T> {
+        self.shared.lock().receivers += 1;
+
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receivers -= 1;
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Receiver")
+            .field("value", &*self.borrow())
+            .finish()
+    }
+}
+
+// there are no receivers, the value comes back
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SendError").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "channel closed")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+// the sender is gone
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError(());
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "channel closed")
+    }
+}
+
+impl Error for RecvError {}
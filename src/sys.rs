@@ -0,0 +1,764 @@
+// thin wrappers around the libc calls the io types need
+use std::io;
+use std::mem;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::net::Shutdown;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{FromRawFd, OwnedFd, RawFd};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::path::Path;
+use std::time::Duration;
+
+use libc::{c_int, c_void, pollfd, socklen_t, POLLIN, POLLOUT};
+use log::debug;
+
+use crate::interest::{Interest, Ready};
+
+// check readiness of a single fd without blocking (poll(2) with zero timeout).
+// errors and hangups are always reported
+pub(crate) fn poll_now(fd: RawFd, interest: Interest) -> Result<Ready, io::Error> {
+    let mut events = 0;
+    if interest.is_readable() {
+        events |= POLLIN;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            events |= libc::POLLRDHUP;
+        }
+    }
+    if interest.is_writable() {
+        events |= POLLOUT;
+    }
+
+    let mut pfd = pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+
+    let rv = unsafe { libc::poll(&mut pfd, 1, 0) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(Ready::from_revents(pfd.revents))
+}
+
+pub(crate) fn setsockopt<T>(
+    fd: RawFd,
+    level: c_int,
+    name: c_int,
+    value: T,
+) -> Result<(), io::Error> {
+    let rv = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const T as *const c_void,
+            mem::size_of::<T>() as socklen_t,
+        )
+    };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn getsockopt<T: Copy>(fd: RawFd, level: c_int, name: c_int) -> Result<T, io::Error> {
+    let mut value: T = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<T>() as socklen_t;
+
+    let rv = unsafe {
+        libc::getsockopt(
+            fd,
+            level,
+            name,
+            &mut value as *mut T as *mut c_void,
+            &mut len,
+        )
+    };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(value)
+}
+
+// socket options counted in whole seconds. Rounded up, so a short non-zero
+// timeout doesn't turn into 0, which usually means something else entirely
+pub(crate) fn secs_rounded_up(d: Duration) -> c_int {
+    let secs = d.as_secs() + (d.subsec_nanos() > 0) as u64;
+    secs.min(c_int::MAX as u64) as c_int
+}
+
+// the same for options counted in milliseconds. Even unsigned ones are
+// read as an int by the kernel, which rejects negative values
+pub(crate) fn millis_rounded_up(d: Duration) -> c_int {
+    let millis = d.as_nanos().div_ceil(1_000_000);
+    millis.min(c_int::MAX as u128) as c_int
+}
+
+// darwin calls the keepalive idle time option TCP_KEEPALIVE
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+pub(crate) const TCP_KEEPIDLE: c_int = libc::TCP_KEEPIDLE;
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub(crate) const TCP_KEEPIDLE: c_int = libc::TCP_KEEPALIVE;
+
+// create a non-blocking, close-on-exec socket
+pub(crate) fn socket(domain: c_int, ty: c_int) -> Result<OwnedFd, io::Error> {
+    socket_with_protocol(domain, ty, 0)
+}
+
+pub(crate) fn socket_with_protocol(
+    domain: c_int,
+    ty: c_int,
+    protocol: c_int,
+) -> Result<OwnedFd, io::Error> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let ty = ty | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+
+    let fd = unsafe { libc::socket(domain, ty, protocol) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let owned = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        set_cloexec(fd)?;
+        set_nonblocking(fd, true)?;
+    }
+
+    Ok(owned)
+}
+
+pub(crate) fn set_nonblocking(fd: RawFd, nonblocking: bool) -> Result<(), io::Error> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let flags = if nonblocking {
+        flags | libc::O_NONBLOCK
+    } else {
+        flags & !libc::O_NONBLOCK
+    };
+
+    if unsafe { libc::fcntl(fd, libc::F_SETFL, flags) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// take fd over for a FromRawFd impl, which can't fail. fcntl only fails on
+// a descriptor that isn't open, the first io on it reports that instead
+pub(crate) unsafe fn adopt_fd(fd: RawFd) -> OwnedFd {
+    if let Err(err) = set_nonblocking(fd, true) {
+        debug!("can't set fd#{} non-blocking: {}", fd, err);
+    }
+    OwnedFd::from_raw_fd(fd)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn set_cloexec(fd: RawFd) -> Result<(), io::Error> {
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// convert a std socket address into its C representation
+pub(crate) fn socket_addr(addr: &SocketAddr) -> (libc::sockaddr_storage, socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr = libc::in_addr {
+                s_addr: u32::from_ne_bytes(addr.ip().octets()),
+            };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_addr = libc::in6_addr {
+                s6_addr: addr.ip().octets(),
+            };
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    (storage, len as socklen_t)
+}
+
+// and back
+pub(crate) fn to_socket_addr(storage: &libc::sockaddr_storage) -> Result<SocketAddr, io::Error> {
+    match storage.ss_family as c_int {
+        libc::AF_INET => {
+            let sin = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(sin.sin_addr.s_addr.to_ne_bytes());
+            Ok(SocketAddr::V4(SocketAddrV4::new(
+                ip,
+                u16::from_be(sin.sin_port),
+            )))
+        }
+        libc::AF_INET6 => {
+            let sin6 = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+            let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+            Ok(SocketAddr::V6(SocketAddrV6::new(
+                ip,
+                u16::from_be(sin6.sin6_port),
+                sin6.sin6_flowinfo,
+                sin6.sin6_scope_id,
+            )))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unsupported address family",
+        )),
+    }
+}
+
+pub(crate) fn bind(fd: RawFd, addr: &SocketAddr) -> Result<(), io::Error> {
+    let (storage, len) = socket_addr(addr);
+
+    let rv = unsafe { libc::bind(fd, &storage as *const _ as *const libc::sockaddr, len) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// non-blocking connect, returns Ok(false) if the connection is in progress
+pub(crate) fn connect(fd: RawFd, addr: &SocketAddr) -> Result<bool, io::Error> {
+    let (storage, len) = socket_addr(addr);
+
+    let rv = unsafe { libc::connect(fd, &storage as *const _ as *const libc::sockaddr, len) };
+    if rv == -1 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EINPROGRESS) {
+            return Ok(false);
+        }
+        return Err(err);
+    }
+
+    Ok(true)
+}
+
+pub(crate) fn local_addr(fd: RawFd) -> Result<SocketAddr, io::Error> {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as socklen_t;
+
+    let rv =
+        unsafe { libc::getsockname(fd, &mut storage as *mut _ as *mut libc::sockaddr, &mut len) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    to_socket_addr(&storage)
+}
+
+// pending error on the socket, e.g. the result of a non-blocking connect
+pub(crate) fn take_error(fd: RawFd) -> Result<Option<io::Error>, io::Error> {
+    let err: c_int = getsockopt(fd, libc::SOL_SOCKET, libc::SO_ERROR)?;
+
+    if err == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(io::Error::from_raw_os_error(err)))
+    }
+}
+
+// SO_BINDTODEVICE, None removes the binding
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn bind_device(fd: RawFd, interface: Option<&str>) -> Result<(), io::Error> {
+    let name = interface.map_or(&[][..], |name| name.as_bytes());
+    if name.len() >= libc::IFNAMSIZ {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "interface name too long",
+        ));
+    }
+
+    let rv = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            name.as_ptr() as *const c_void,
+            name.len() as socklen_t,
+        )
+    };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn device(fd: RawFd) -> Result<Option<Vec<u8>>, io::Error> {
+    let mut name = [0u8; libc::IFNAMSIZ];
+    let mut len = name.len() as socklen_t;
+
+    let rv = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_BINDTODEVICE,
+            name.as_mut_ptr() as *mut c_void,
+            &mut len,
+        )
+    };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // the returned length may or may not include the trailing NUL
+    let name = &name[..len as usize];
+    let name = match name.iter().position(|&b| b == 0) {
+        Some(end) => &name[..end],
+        None => name,
+    };
+
+    if name.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(name.to_vec()))
+    }
+}
+
+// receive up to bufs.len() datagrams with one recvmmsg(2) call. For every
+// datagram (len, sender) is pushed onto out, returns how many were received
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn recvmmsg(
+    fd: RawFd,
+    bufs: &mut [&mut [u8]],
+    out: &mut Vec<(usize, SocketAddr)>,
+) -> Result<usize, io::Error> {
+    let mut iovecs: Vec<libc::iovec> = bufs
+        .iter_mut()
+        .map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut c_void,
+            iov_len: buf.len(),
+        })
+        .collect();
+    let mut addrs: Vec<libc::sockaddr_storage> = vec![unsafe { mem::zeroed() }; bufs.len()];
+
+    let mut msgs: Vec<libc::mmsghdr> = iovecs
+        .iter_mut()
+        .zip(addrs.iter_mut())
+        .map(|(iov, addr)| {
+            let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+            msg.msg_hdr.msg_name = addr as *mut _ as *mut c_void;
+            msg.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as socklen_t;
+            msg.msg_hdr.msg_iov = iov;
+            msg.msg_hdr.msg_iovlen = 1;
+            msg
+        })
+        .collect();
+
+    let rv = unsafe {
+        libc::recvmmsg(
+            fd,
+            msgs.as_mut_ptr(),
+            msgs.len() as _,
+            0,
+            std::ptr::null_mut(),
+        )
+    };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let n = rv as usize;
+    for (msg, addr) in msgs.iter().zip(addrs.iter()).take(n) {
+        out.push((msg.msg_len as usize, to_socket_addr(addr)?));
+    }
+
+    Ok(n)
+}
+
+// send every (datagram, destination) with one sendmmsg(2) call, returns
+// how many datagrams went out
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn sendmmsg(fd: RawFd, bufs: &[(&[u8], SocketAddr)]) -> Result<usize, io::Error> {
+    let mut iovecs: Vec<libc::iovec> = bufs
+        .iter()
+        .map(|(buf, _)| libc::iovec {
+            iov_base: buf.as_ptr() as *mut c_void,
+            iov_len: buf.len(),
+        })
+        .collect();
+    let mut addrs: Vec<(libc::sockaddr_storage, socklen_t)> =
+        bufs.iter().map(|(_, addr)| socket_addr(addr)).collect();
+
+    let mut msgs: Vec<libc::mmsghdr> = iovecs
+        .iter_mut()
+        .zip(addrs.iter_mut())
+        .map(|(iov, (addr, len))| {
+            let mut msg: libc::mmsghdr = unsafe { mem::zeroed() };
+            msg.msg_hdr.msg_name = addr as *mut _ as *mut c_void;
+            msg.msg_hdr.msg_namelen = *len;
+            msg.msg_hdr.msg_iov = iov;
+            msg.msg_hdr.msg_iovlen = 1;
+            msg
+        })
+        .collect();
+
+    let rv = unsafe { libc::sendmmsg(fd, msgs.as_mut_ptr(), msgs.len() as _, 0) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(rv as usize)
+}
+
+// sendmsg(2) with a UDP_SEGMENT control message: the kernel cuts buf into
+// datagrams of segment bytes each (the last one may be shorter)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn send_segmented(
+    fd: RawFd,
+    buf: &[u8],
+    addr: &SocketAddr,
+    segment: u16,
+) -> Result<usize, io::Error> {
+    let (mut storage, len) = socket_addr(addr);
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [0u64; 4];
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut storage as *mut _ as *mut c_void;
+    msg.msg_namelen = len;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut c_void;
+    msg.msg_controllen = unsafe { libc::CMSG_SPACE(mem::size_of::<u16>() as u32) } as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_UDP;
+        (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, segment);
+    }
+
+    let rv = unsafe { libc::sendmsg(fd, &msg, 0) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(rv as usize)
+}
+
+// recvmsg(2) that also picks up the UDP_GRO control message. Returns the
+// received length, the sender and the size of the coalesced segments, if
+// the kernel merged several datagrams
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn recv_gro(
+    fd: RawFd,
+    buf: &mut [u8],
+) -> Result<(usize, SocketAddr, Option<usize>), io::Error> {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [0u64; 4];
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = &mut storage as *mut _ as *mut c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as socklen_t;
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut c_void;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    let rv = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut segment = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let hdr = unsafe { &*cmsg };
+        if hdr.cmsg_level == libc::SOL_UDP && hdr.cmsg_type == libc::UDP_GRO {
+            let size = unsafe { std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const c_int) };
+            segment = Some(size as usize);
+        }
+
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok((rv as usize, to_socket_addr(&storage)?, segment))
+}
+
+// the kernel's limit on descriptors in one SCM_RIGHTS message
+pub(crate) const SCM_MAX_FD: usize = 253;
+
+// sendmsg(2) with the descriptors attached as SCM_RIGHTS. The receiver gets
+// duplicates, ours stay open
+pub(crate) fn send_with_fds(fd: RawFd, buf: &[u8], fds: &[RawFd]) -> Result<usize, io::Error> {
+    if fds.len() > SCM_MAX_FD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "too many file descriptors",
+        ));
+    }
+
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [0u64; (SCM_MAX_FD * 4 + 64) / 8];
+    let fds_len = mem::size_of_val(fds) as u32;
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+
+    if !fds.is_empty() {
+        msg.msg_control = control.as_mut_ptr() as *mut c_void;
+        msg.msg_controllen = unsafe { libc::CMSG_SPACE(fds_len) } as _;
+
+        unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = libc::SOL_SOCKET;
+            (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(fds_len) as _;
+            std::ptr::copy_nonoverlapping(
+                fds.as_ptr() as *const u8,
+                libc::CMSG_DATA(cmsg),
+                fds_len as usize,
+            );
+        }
+    }
+
+    let rv = unsafe { libc::sendmsg(fd, &msg, MSG_NOSIGNAL) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(rv as usize)
+}
+
+// recvmsg(2) that collects SCM_RIGHTS descriptors into fds. They're
+// close-on-exec, and closed when the OwnedFds are dropped
+pub(crate) fn recv_with_fds(
+    fd: RawFd,
+    buf: &mut [u8],
+    fds: &mut Vec<OwnedFd>,
+) -> Result<usize, io::Error> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut c_void,
+        iov_len: buf.len(),
+    };
+    let mut control = [0u64; (SCM_MAX_FD * 4 + 64) / 8];
+
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut c_void;
+    msg.msg_controllen = mem::size_of_val(&control) as _;
+
+    let rv = unsafe { libc::recvmsg(fd, &mut msg, MSG_CMSG_CLOEXEC) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let hdr = unsafe { &*cmsg };
+        if hdr.cmsg_level == libc::SOL_SOCKET && hdr.cmsg_type == libc::SCM_RIGHTS {
+            let data = unsafe { libc::CMSG_DATA(cmsg) };
+            let header = data as usize - cmsg as usize;
+            let count = (hdr.cmsg_len as usize - header) / mem::size_of::<RawFd>();
+
+            for i in 0..count {
+                let raw = unsafe { std::ptr::read_unaligned((data as *const RawFd).add(i)) };
+                fds.push(unsafe { OwnedFd::from_raw_fd(raw) });
+
+                // no MSG_CMSG_CLOEXEC here, there's a small window for exec
+                #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                set_cloexec(raw)?;
+            }
+        }
+
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok(rv as usize)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MSG_NOSIGNAL: c_int = libc::MSG_NOSIGNAL;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const MSG_NOSIGNAL: c_int = 0;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const MSG_CMSG_CLOEXEC: c_int = libc::MSG_CMSG_CLOEXEC;
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const MSG_CMSG_CLOEXEC: c_int = 0;
+
+// sockaddr_un for a filesystem path
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn unix_socket_addr(path: &Path) -> Result<(libc::sockaddr_un, socklen_t), io::Error> {
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    let bytes = path.as_os_str().as_bytes();
+    // leave room for the trailing NUL
+    if bytes.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path must be shorter than SUN_LEN",
+        ));
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(bytes) {
+        *dst = *src as libc::c_char;
+    }
+
+    let offset = addr.sun_path.as_ptr() as usize - &addr as *const _ as usize;
+    Ok((addr, (offset + bytes.len() + 1) as socklen_t))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn bind_unix(fd: RawFd, path: &Path) -> Result<(), io::Error> {
+    let (addr, len) = unix_socket_addr(path)?;
+
+    let rv = unsafe { libc::bind(fd, &addr as *const _ as *const libc::sockaddr, len) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// non-blocking connect like connect above, Ok(false) if the connection is
+// in progress. A full listener backlog is WouldBlock, the connect didn't
+// happen and has to be tried again
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn connect_unix(fd: RawFd, path: &Path) -> Result<bool, io::Error> {
+    let (addr, len) = unix_socket_addr(path)?;
+
+    let rv = unsafe { libc::connect(fd, &addr as *const _ as *const libc::sockaddr, len) };
+    if rv == -1 {
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EINPROGRESS) {
+            return Ok(false);
+        }
+        return Err(err);
+    }
+
+    Ok(true)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn listen(fd: RawFd, backlog: u32) -> Result<(), io::Error> {
+    if unsafe { libc::listen(fd, backlog as c_int) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// accept a connection as a non-blocking, close-on-exec socket
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn accept(fd: RawFd) -> Result<OwnedFd, io::Error> {
+    let flags = libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+
+    let conn = unsafe { libc::accept4(fd, std::ptr::null_mut(), std::ptr::null_mut(), flags) };
+    if conn == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { OwnedFd::from_raw_fd(conn) })
+}
+
+// socketpair(2), both ends non-blocking and close-on-exec
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn socketpair(domain: c_int, ty: c_int) -> Result<(OwnedFd, OwnedFd), io::Error> {
+    let ty = ty | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC;
+    let mut fds = [0; 2];
+
+    if unsafe { libc::socketpair(domain, ty, 0, fds.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn send(fd: RawFd, buf: &[u8]) -> Result<usize, io::Error> {
+    let rv = unsafe { libc::send(fd, buf.as_ptr() as *const c_void, buf.len(), MSG_NOSIGNAL) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(rv as usize)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn recv(fd: RawFd, buf: &mut [u8]) -> Result<usize, io::Error> {
+    let rv = unsafe { libc::recv(fd, buf.as_mut_ptr() as *mut c_void, buf.len(), 0) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(rv as usize)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn shutdown(fd: RawFd, how: Shutdown) -> Result<(), io::Error> {
+    let how = match how {
+        Shutdown::Read => libc::SHUT_RD,
+        Shutdown::Write => libc::SHUT_WR,
+        Shutdown::Both => libc::SHUT_RDWR,
+    };
+
+    if unsafe { libc::shutdown(fd, how) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+// pipe(2), both ends non-blocking and close-on-exec. Returns (read, write)
+pub(crate) fn pipe() -> Result<(OwnedFd, OwnedFd), io::Error> {
+    let mut fds = [0; 2];
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let rv = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) };
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let rv = unsafe { libc::pipe(fds.as_mut_ptr()) };
+
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    for fd in fds {
+        set_cloexec(fd)?;
+        set_nonblocking(fd, true)?;
+    }
+
+    Ok((read, write))
+}
@@ -0,0 +1,91 @@
+// task instrumentation in the shape tokio emits it, so console-subscriber
+// and similar live task viewers work with this reactor: a "runtime.spawn"
+// span per task (target tokio::task), entered around every poll, and
+// "runtime::waker" events for wakeups. Without feature "tracing" all of
+// this compiles to nothing
+use std::panic::Location;
+
+use crate::TaskId;
+
+#[cfg(feature = "tracing")]
+pub(crate) struct TaskSpan(tracing::Span);
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct TaskSpan;
+
+// what a waker needs to say which task it wakes, the span id
+#[cfg(feature = "tracing")]
+#[derive(Clone, Copy)]
+pub(crate) struct WakeId(Option<u64>);
+
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone, Copy)]
+pub(crate) struct WakeId;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Entered;
+
+#[cfg(feature = "tracing")]
+impl TaskSpan {
+    pub(crate) fn new(id: TaskId, name: Option<&str>, loc: &'static Location<'static>) -> TaskSpan {
+        TaskSpan(tracing::trace_span!(
+            target: "tokio::task",
+            // a task outlives whatever spawned it
+            parent: None,
+            "runtime.spawn",
+            kind = "task",
+            task.name = name.unwrap_or_default(),
+            task.id = id as u64,
+            loc.file = loc.file(),
+            loc.line = loc.line(),
+            loc.col = loc.column(),
+        ))
+    }
+
+    // entered while the task is polled, busy and idle times come from this
+    pub(crate) fn enter(&self) -> tracing::span::Entered<'_> {
+        self.0.enter()
+    }
+
+    pub(crate) fn wake_id(&self) -> WakeId {
+        WakeId(self.0.id().map(|id| id.into_u64()))
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl TaskSpan {
+    pub(crate) fn new(_: TaskId, _: Option<&str>, _: &'static Location<'static>) -> TaskSpan {
+        TaskSpan
+    }
+
+    pub(crate) fn enter(&self) -> Entered {
+        Entered
+    }
+
+    pub(crate) fn wake_id(&self) -> WakeId {
+        WakeId
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl WakeId {
+    // a task nobody traces, e.g. one that's already finished
+    pub(crate) fn none() -> WakeId {
+        WakeId(None)
+    }
+
+    pub(crate) fn woken(self) {
+        if let Some(id) = self.0 {
+            tracing::trace!(target: "runtime::waker", op = "waker.wake_by_ref", task.id = id);
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl WakeId {
+    pub(crate) fn none() -> WakeId {
+        WakeId
+    }
+
+    pub(crate) fn woken(self) {}
+}
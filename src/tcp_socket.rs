@@ -0,0 +1,222 @@
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+
+use log::debug;
+
+use crate::sys;
+use crate::AsyncTcpListener;
+use crate::AsyncTcpStream;
+use crate::Registration;
+
+// TcpSocket is a not yet bound/connected socket. It exists so options that
+// only make sense before bind(2) or connect(2) can be set, after that it
+// turns into an AsyncTcpListener or an AsyncTcpStream
+#[derive(Debug)]
+pub struct TcpSocket(OwnedFd);
+
+impl TcpSocket {
+    pub fn new_v4() -> Result<TcpSocket, io::Error> {
+        let fd = sys::socket(libc::AF_INET, libc::SOCK_STREAM)?;
+        Ok(TcpSocket(fd))
+    }
+
+    pub fn new_v6() -> Result<TcpSocket, io::Error> {
+        let fd = sys::socket(libc::AF_INET6, libc::SOCK_STREAM)?;
+        Ok(TcpSocket(fd))
+    }
+
+    // socket of the same family as addr
+    pub fn new_for_addr(addr: &SocketAddr) -> Result<TcpSocket, io::Error> {
+        match addr {
+            SocketAddr::V4(_) => TcpSocket::new_v4(),
+            SocketAddr::V6(_) => TcpSocket::new_v6(),
+        }
+    }
+
+    // SO_REUSEADDR: allow binding while old connections are in TIME_WAIT
+    pub fn set_reuseaddr(&self, reuseaddr: bool) -> Result<(), io::Error> {
+        let value = reuseaddr as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_REUSEADDR,
+            value,
+        )
+    }
+
+    pub fn reuseaddr(&self) -> Result<bool, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::SOL_SOCKET, libc::SO_REUSEADDR)?;
+        Ok(value != 0)
+    }
+
+    // SO_REUSEPORT: allow several sockets to bind the same address,
+    // the kernel load balances incoming connections between them
+    pub fn set_reuseport(&self, reuseport: bool) -> Result<(), io::Error> {
+        let value = reuseport as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_REUSEPORT,
+            value,
+        )
+    }
+
+    pub fn reuseport(&self) -> Result<bool, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::SOL_SOCKET, libc::SO_REUSEPORT)?;
+        Ok(value != 0)
+    }
+
+    // SO_BINDTODEVICE: only send and receive through the given interface
+    // (e.g. "eth0"). Usually requires CAP_NET_RAW
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn bind_device(&self, interface: Option<&str>) -> Result<(), io::Error> {
+        sys::bind_device(self.0.as_raw_fd(), interface)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn device(&self) -> Result<Option<Vec<u8>>, io::Error> {
+        sys::device(self.0.as_raw_fd())
+    }
+
+    // IPV6_V6ONLY: with false an AF_INET6 socket bound to [::] also accepts
+    // IPv4 connections (as v4-mapped addresses), with true it's IPv6 only.
+    // the default depends on the net.ipv6.bindv6only sysctl
+    pub fn set_only_v6(&self, only_v6: bool) -> Result<(), io::Error> {
+        let value = only_v6 as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_V6ONLY,
+            value,
+        )
+    }
+
+    pub fn only_v6(&self) -> Result<bool, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::IPPROTO_IPV6, libc::IPV6_V6ONLY)?;
+        Ok(value != 0)
+    }
+
+    pub fn bind(&self, addr: SocketAddr) -> Result<(), io::Error> {
+        sys::bind(self.0.as_raw_fd(), &addr)
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+        sys::local_addr(self.0.as_raw_fd())
+    }
+
+    // start listening on the bound address
+    pub fn listen(self, backlog: u32) -> Result<AsyncTcpListener, io::Error> {
+        let fd = self.0.into_raw_fd();
+        let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+        if unsafe { libc::listen(fd, backlog as libc::c_int) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        AsyncTcpListener::from_std(listener)
+    }
+
+    // connect without blocking the event loop: the socket is non-blocking,
+    // so connect(2) returns EINPROGRESS and we wait for it to become writable
+    pub async fn connect(self, addr: SocketAddr) -> Result<AsyncTcpStream, io::Error> {
+        let fd = self.0.into_raw_fd();
+        let stream = unsafe { TcpStream::from_raw_fd(fd) };
+
+        if !sys::connect(fd, &addr)? {
+            wait_connected(fd).await?;
+        }
+
+        AsyncTcpStream::from_std(stream)
+    }
+
+    // TCP Fast Open: send the first chunk of data in the SYN (sendto(2) with
+    // MSG_FASTOPEN). Without a cached cookie the kernel does a regular
+    // handshake and nothing is sent, so the returned count may be 0 and the
+    // caller has to write the rest as usual
+    #[cfg(all(feature = "tcp-fastopen", target_os = "linux"))]
+    pub async fn connect_with_data(
+        self,
+        addr: SocketAddr,
+        data: &[u8],
+    ) -> Result<(AsyncTcpStream, usize), io::Error> {
+        let fd = self.0.into_raw_fd();
+        let stream = unsafe { TcpStream::from_raw_fd(fd) };
+        let (storage, len) = sys::socket_addr(&addr);
+
+        let rv = unsafe {
+            libc::sendto(
+                fd,
+                data.as_ptr() as *const libc::c_void,
+                data.len(),
+                libc::MSG_FASTOPEN,
+                &storage as *const _ as *const libc::sockaddr,
+                len,
+            )
+        };
+
+        let sent = if rv == -1 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(err);
+            }
+            0
+        } else {
+            rv as usize
+        };
+
+        wait_connected(fd).await?;
+
+        Ok((AsyncTcpStream::from_std(stream)?, sent))
+    }
+}
+
+// wait for an in-progress connect to finish and report its result. The
+// registration takes the write interest back out if the connect future is
+// dropped halfway, so select never gets handed the closed fd
+async fn wait_connected(fd: RawFd) -> Result<(), io::Error> {
+    let registration = Registration::new(fd);
+    poll_fn(|ctx| {
+        debug!("waiting for connect on {}", fd);
+        registration.poll_write_ready(ctx)
+    })
+    .await?;
+
+    match sys::take_error(fd)? {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+impl AsRawFd for TcpSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for TcpSocket {
+    fn into_raw_fd(self) -> RawFd {
+        self.0.into_raw_fd()
+    }
+}
+
+// the fd is switched to non-blocking mode
+impl FromRawFd for TcpSocket {
+    unsafe fn from_raw_fd(fd: RawFd) -> TcpSocket {
+        TcpSocket(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for TcpSocket {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<TcpSocket, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(TcpSocket(fd))
+    }
+}
@@ -0,0 +1,71 @@
+// what #[fahrenheit::test] expands to calls. With a timeout the test runs
+// on a thread of its own, so a test stuck outside the reactor (a blocking
+// call, a busy loop) fails too instead of hanging the test binary
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe, Location};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use crate::time;
+use crate::REACTOR;
+
+#[track_caller]
+pub fn run_test<F: Future<Output = ()> + Send + 'static>(
+    f: F,
+    start_paused: bool,
+    timeout: Option<Duration>,
+) {
+    let loc = Location::caller();
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return run(f, start_paused, loc),
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut builder = thread::Builder::new();
+    if let Some(name) = thread::current().name() {
+        builder = builder.name(name.to_owned());
+    }
+    builder
+        .spawn(move || {
+            let res = panic::catch_unwind(AssertUnwindSafe(|| run(f, start_paused, loc)));
+            let _ = tx.send(res);
+        })
+        .expect("can't spawn test thread");
+
+    match rx.recv_timeout(timeout) {
+        Ok(Ok(())) => {}
+        Ok(Err(panic)) => panic::resume_unwind(panic),
+        Err(RecvTimeoutError::Timeout) => panic!("test timed out after {:?}", timeout),
+        Err(RecvTimeoutError::Disconnected) => panic!("test thread died"),
+    }
+}
+
+fn run<F: Future<Output = ()> + Send + 'static>(
+    f: F,
+    start_paused: bool,
+    loc: &'static Location<'static>,
+) {
+    let _clock = RestoreClock(time::is_paused());
+    if start_paused {
+        time::pause();
+    }
+    REACTOR.with(|reactor| {
+        if let Err(err) = reactor.run(f, loc, None) {
+            panic!("event loop failed: {}", err);
+        }
+    })
+}
+
+// the harness may run the next test on this thread, it gets the clock back
+// the way it was, even if this one panicked
+struct RestoreClock(bool);
+
+impl Drop for RestoreClock {
+    fn drop(&mut self) {
+        if !self.0 {
+            time::resume();
+        }
+    }
+}
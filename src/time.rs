@@ -0,0 +1,164 @@
+// timers. They live in the event loop next to the fd interests: select's
+// timeout is cut short at the nearest deadline and expired timers wake
+// their tasks after select returns.
+//
+// Each reactor thread has a clock that tests can pause. A paused clock
+// only moves on advance, or when every task waits on a timer and no
+// blocking pool job or io_uring op started here is still running: then
+// the reactor jumps it to the nearest deadline instead of sleeping, so
+// tests full of timeouts finish instantly. Deadlines taken from
+// Instant::now instead of now() don't see the jumps
+use std::cell::Cell;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use crate::TimerKey;
+use crate::REACTOR;
+
+thread_local! {
+    static CLOCK: Cell<Clock> = const { Cell::new(Clock { paused: None, offset: Duration::ZERO }) };
+}
+
+#[derive(Clone, Copy)]
+struct Clock {
+    // the frozen time while paused
+    paused: Option<Instant>,
+    // how far advance has moved the clock past the real one
+    offset: Duration,
+}
+
+// what time it is on this thread's clock
+pub fn now() -> Instant {
+    let clock = CLOCK.with(Cell::get);
+    clock
+        .paused
+        .unwrap_or_else(|| Instant::now() + clock.offset)
+}
+
+// freeze this thread's clock, see advance
+pub fn pause() {
+    CLOCK.with(|clock| {
+        let mut c = clock.get();
+        if c.paused.is_none() {
+            c.paused = Some(Instant::now() + c.offset);
+            clock.set(c);
+        }
+    })
+}
+
+// let the clock run again from where it was paused
+pub fn resume() {
+    CLOCK.with(|clock| {
+        let mut c = clock.get();
+        if let Some(paused) = c.paused.take() {
+            c.offset = paused.saturating_duration_since(Instant::now());
+            clock.set(c);
+        }
+    })
+}
+
+pub fn is_paused() -> bool {
+    CLOCK.with(Cell::get).paused.is_some()
+}
+
+// move a paused clock forward, then yield so the timers that expired fire
+// before the caller carries on. Panics if the clock isn't paused
+pub async fn advance(duration: Duration) {
+    CLOCK.with(|clock| {
+        let mut c = clock.get();
+        let paused = c.paused.expect("time isn't paused");
+        c.paused = Some(paused + duration);
+        clock.set(c);
+    });
+
+    let mut yielded = false;
+    poll_fn(|ctx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        ctx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+// the reactor found nothing to do but wait for deadline
+pub(crate) fn skip_to(deadline: Instant) {
+    CLOCK.with(|clock| {
+        let mut c = clock.get();
+        if let Some(paused) = c.paused {
+            c.paused = Some(paused.max(deadline));
+            clock.set(c);
+        }
+    })
+}
+
+// wait until duration has elapsed
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(now() + duration)
+}
+
+// wait until deadline
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep {
+        deadline,
+        timer: None,
+    }
+}
+
+#[derive(Debug)]
+pub struct Sleep {
+    deadline: Instant,
+    // registered timer, if the future has been polled
+    timer: Option<TimerKey>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        now() >= self.deadline
+    }
+
+    // move the deadline without allocating a new Sleep
+    pub fn reset(&mut self, deadline: Instant) {
+        self.cancel();
+        self.deadline = deadline;
+    }
+
+    fn cancel(&mut self) {
+        if let Some(key) = self.timer.take() {
+            let _ = REACTOR.try_with(|reactor| reactor.remove_timer(key));
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<()> {
+        if self.is_elapsed() {
+            self.cancel();
+            return Poll::Ready(());
+        }
+
+        // re-register on every poll, the task may have moved to another waker
+        self.cancel();
+        let deadline = self.deadline;
+        let key = REACTOR.with(|reactor| reactor.add_timer(deadline, ctx.waker().clone()));
+        self.timer = Some(key);
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
@@ -0,0 +1,334 @@
+// TLS over any byte stream, usually an AsyncTcpStream, using rustls. The
+// connector and acceptor run the handshake and hand back a TlsStream that
+// reads and writes plaintext. Needs feature "tls"
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+use log::debug;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, Connection, ServerConfig, ServerConnection};
+
+// for building configs without depending on rustls directly
+pub use rustls;
+
+#[derive(Clone)]
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+
+impl TlsConnector {
+    pub fn new(config: Arc<ClientConfig>) -> TlsConnector {
+        TlsConnector { config }
+    }
+
+    // domain is checked against the server's certificate and sent as SNI
+    pub async fn connect<S>(&self, domain: &str, stream: S) -> Result<TlsStream<S>, io::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let name = ServerName::try_from(domain.to_owned())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let conn = ClientConnection::new(self.config.clone(), name).map_err(tls_error)?;
+
+        TlsStream::handshake(stream, conn.into()).await
+    }
+}
+
+impl From<Arc<ClientConfig>> for TlsConnector {
+    fn from(config: Arc<ClientConfig>) -> TlsConnector {
+        TlsConnector::new(config)
+    }
+}
+
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TlsConnector").finish()
+    }
+}
+
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsAcceptor {
+    pub fn new(config: Arc<ServerConfig>) -> TlsAcceptor {
+        TlsAcceptor { config }
+    }
+
+    pub async fn accept<S>(&self, stream: S) -> Result<TlsStream<S>, io::Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let conn = ServerConnection::new(self.config.clone()).map_err(tls_error)?;
+
+        TlsStream::handshake(stream, conn.into()).await
+    }
+}
+
+impl From<Arc<ServerConfig>> for TlsAcceptor {
+    fn from(config: Arc<ServerConfig>) -> TlsAcceptor {
+        TlsAcceptor::new(config)
+    }
+}
+
+impl fmt::Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TlsAcceptor").finish()
+    }
+}
+
+fn tls_error(err: rustls::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+// a stream after a completed handshake, reads and writes are plaintext
+pub struct TlsStream<S> {
+    io: S,
+    conn: Connection,
+    // close_notify has been queued
+    closing: bool,
+}
+
+impl<S> TlsStream<S> {
+    pub fn get_ref(&self) -> &S {
+        &self.io
+    }
+
+    // io done through this reference corrupts the TLS session
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.io
+    }
+
+    // negotiated protocol version, ALPN, peer certificates...
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    pub fn into_inner(self) -> (S, Connection) {
+        (self.io, self.conn)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TlsStream<S> {
+    async fn handshake(io: S, conn: Connection) -> Result<TlsStream<S>, io::Error> {
+        let mut stream = TlsStream {
+            io,
+            conn,
+            closing: false,
+        };
+        poll_fn(|ctx| stream.poll_handshake(ctx)).await?;
+
+        debug!("tls handshake done, {:?}", stream.conn.protocol_version());
+        Ok(stream)
+    }
+
+    fn poll_handshake(&mut self, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        loop {
+            while self.conn.wants_write() {
+                match self.poll_write_tls(ctx) {
+                    Poll::Ready(Ok(_)) => {}
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            if !self.conn.is_handshaking() {
+                return Poll::Ready(Ok(()));
+            }
+
+            match self.poll_read_tls(ctx) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "tls handshake eof",
+                    )))
+                }
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    // feed ciphertext from io to rustls, 0 means EOF
+    fn poll_read_tls(&mut self, ctx: &mut Context) -> Poll<Result<usize, io::Error>> {
+        let mut io = SyncIo {
+            io: &mut self.io,
+            ctx,
+        };
+        let n = match self.conn.read_tls(&mut io) {
+            Ok(n) => n,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Poll::Pending,
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+
+        if let Err(err) = self.conn.process_new_packets() {
+            // best effort to tell the peer why
+            let _ = self.conn.write_tls(&mut io);
+            return Poll::Ready(Err(tls_error(err)));
+        }
+
+        Poll::Ready(Ok(n))
+    }
+
+    // write ciphertext from rustls to io
+    fn poll_write_tls(&mut self, ctx: &mut Context) -> Poll<Result<usize, io::Error>> {
+        let mut io = SyncIo {
+            io: &mut self.io,
+            ctx,
+        };
+        match self.conn.write_tls(&mut io) {
+            Ok(0) => Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+            Ok(n) => Poll::Ready(Ok(n)),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+
+    // write out everything rustls has queued
+    fn poll_write_all_tls(&mut self, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        while self.conn.wants_write() {
+            match self.poll_write_tls(ctx) {
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for TlsStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        loop {
+            // Ok(0) after close_notify, UnexpectedEof if the peer just hung up
+            match this.conn.reader().read(buf) {
+                Ok(n) => return Poll::Ready(Ok(n)),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+
+            // records rustls wants to send in response, e.g. key updates
+            if let Poll::Ready(Err(err)) = this.poll_write_all_tls(ctx) {
+                return Poll::Ready(Err(err));
+            }
+
+            match this.poll_read_tls(ctx) {
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for TlsStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        let this = self.get_mut();
+
+        loop {
+            let n = this.conn.writer().write(buf)?;
+            let flushed = this.poll_write_all_tls(ctx);
+
+            if n > 0 || buf.is_empty() {
+                // the data is queued, a write error shows up next time
+                return Poll::Ready(Ok(n));
+            }
+
+            // rustls' buffer is full, wait until some of it is written
+            match flushed {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+
+        this.conn.writer().flush()?;
+        match this.poll_write_all_tls(ctx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.io).poll_flush(ctx),
+            other => other,
+        }
+    }
+
+    // send close_notify, then close the underlying stream
+    fn poll_close(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        let this = self.get_mut();
+
+        if !this.closing {
+            this.conn.send_close_notify();
+            this.closing = true;
+        }
+        match this.poll_write_all_tls(ctx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.io).poll_close(ctx),
+            other => other,
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for TlsStream<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TlsStream")
+            .field("io", &self.io)
+            .field("version", &self.conn.protocol_version())
+            .finish()
+    }
+}
+
+// rustls does blocking io, this turns Pending into WouldBlock
+struct SyncIo<'a, 'b, S> {
+    io: &'a mut S,
+    ctx: &'a mut Context<'b>,
+}
+
+impl<S: AsyncRead + Unpin> Read for SyncIo<'_, '_, S> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        match Pin::new(&mut *self.io).poll_read(self.ctx, buf) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> Write for SyncIo<'_, '_, S> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        match Pin::new(&mut *self.io).poll_write(self.ctx, buf) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice]) -> Result<usize, io::Error> {
+        match Pin::new(&mut *self.io).poll_write_vectored(self.ctx, bufs) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        match Pin::new(&mut *self.io).poll_flush(self.ctx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
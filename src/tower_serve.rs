@@ -0,0 +1,151 @@
+// serve a tower Service over TCP: every connection is framed with the
+// codec, each decoded frame is a request and the service's response is
+// encoded back. Requests on one connection are answered in order, one at a
+// time; a semaphore caps how many are in flight across all connections.
+// Needs feature "tower"
+use std::error::Error;
+use std::fmt;
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use log::debug;
+use tower_service::Service;
+
+use crate::codec::{Decoder, Encoder, Framed};
+use crate::sync::Semaphore;
+use crate::{AsyncTcpListener, AsyncTcpStream};
+
+// default cap on requests being handled at once
+const MAX_CONCURRENT_REQUESTS: usize = 1024;
+
+// serve with the default settings, runs as long as the listener accepts
+pub async fn serve<C, S>(listener: AsyncTcpListener, codec: C, service: S) -> Result<(), io::Error>
+where
+    C: Decoder + Encoder<S::Response> + Clone + Send + 'static,
+    C::Item: Send,
+    <C as Decoder>::Error: fmt::Display,
+    <C as Encoder<S::Response>>::Error: fmt::Display,
+    S: Service<C::Item> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    Server::new(codec, service).serve(listener).await
+}
+
+pub struct Server<C, S> {
+    codec: C,
+    service: S,
+    max_concurrent_requests: usize,
+}
+
+impl<C, S> Server<C, S> {
+    pub fn new(codec: C, service: S) -> Server<C, S> {
+        Server {
+            codec,
+            service,
+            max_concurrent_requests: MAX_CONCURRENT_REQUESTS,
+        }
+    }
+
+    // requests handled at once across all connections. Connections with a
+    // request waiting for its turn stop reading
+    pub fn max_concurrent_requests(mut self, max: usize) -> Server<C, S> {
+        self.max_concurrent_requests = max;
+        self
+    }
+}
+
+impl<C, S> Server<C, S>
+where
+    C: Decoder + Encoder<S::Response> + Clone + Send + 'static,
+    C::Item: Send,
+    <C as Decoder>::Error: fmt::Display,
+    <C as Encoder<S::Response>>::Error: fmt::Display,
+    S: Service<C::Item> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Send,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    // accept connections and spawn a task for each
+    pub async fn serve(self, listener: AsyncTcpListener) -> Result<(), io::Error> {
+        let limit = Arc::new(Semaphore::new(self.max_concurrent_requests));
+        let mut incoming = listener.incoming();
+
+        while let Some(stream) = poll_fn(|ctx| Pin::new(&mut incoming).poll_next(ctx)).await {
+            // one failed accept, e.g. a connection reset while queued
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    debug!("accept failed: {}", err);
+                    continue;
+                }
+            };
+            let framed = Framed::new(stream, self.codec.clone());
+            let service = self.service.clone();
+            let limit = limit.clone();
+
+            crate::spawn(async move {
+                if let Err(err) = serve_connection(framed, service, limit).await {
+                    debug!("connection closed: {}", err);
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: fmt::Debug, S> fmt::Debug for Server<C, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Server")
+            .field("codec", &self.codec)
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .finish()
+    }
+}
+
+// requests until the peer hangs up, a frame can't be decoded or the
+// service fails
+async fn serve_connection<C, S>(
+    mut framed: Framed<AsyncTcpStream, C>,
+    mut service: S,
+    limit: Arc<Semaphore>,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    C: Decoder + Encoder<S::Response>,
+    <C as Decoder>::Error: fmt::Display,
+    <C as Encoder<S::Response>>::Error: fmt::Display,
+    S: Service<C::Item>,
+    S::Error: Into<Box<dyn Error + Send + Sync>>,
+{
+    loop {
+        let request = match poll_fn(|ctx| Pin::new(&mut framed).poll_next(ctx)).await {
+            Some(Ok(request)) => request,
+            Some(Err(err)) => return Err(err.to_string().into()),
+            None => return Ok(()),
+        };
+
+        // the semaphore is never closed
+        let permit = limit.acquire().await.unwrap();
+        poll_fn(|ctx| service.poll_ready(ctx))
+            .await
+            .map_err(Into::into)?;
+        let response = service.call(request).await.map_err(Into::into)?;
+        drop(permit);
+
+        poll_fn(|ctx| Pin::new(&mut framed).poll_ready(ctx))
+            .await
+            .map_err(|err| err.to_string())?;
+        Pin::new(&mut framed)
+            .start_send(response)
+            .map_err(|err| err.to_string())?;
+        poll_fn(|ctx| Pin::new(&mut framed).poll_flush(ctx))
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+}
@@ -0,0 +1,310 @@
+// io_uring for file reads and writes (feature "io-uring"). Every reactor
+// thread gets its own ring with an eventfd attached, the eventfd becomes
+// readable when completions arrive and the waiting ops pick up their
+// results. If the kernel (or a seccomp filter) refuses io_uring, fs::File
+// keeps using the blocking pool
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+use io_uring::{opcode, squeue, types, IoUring};
+use libc::c_void;
+use log::debug;
+
+use crate::{PendingWork, REACTOR};
+
+const ENTRIES: u32 = 64;
+
+// offset -1: use and advance the file position, like read(2)/write(2)
+const CURRENT_POSITION: u64 = u64::MAX;
+
+thread_local! {
+    static RING: Option<Arc<Ring>> = match Ring::new() {
+        Ok(ring) => Some(Arc::new(ring)),
+        Err(err) => {
+            debug!("io_uring not available: {}", err);
+            None
+        }
+    };
+}
+
+// the kernel owns a slot's buffer until the completion is reaped, even if
+// the Op was dropped in the meantime
+enum Slot {
+    Vacant,
+    // the waker of the op's last poll, woken by whoever reaps it
+    Waiting(Vec<u8>, Option<Waker>),
+    Done(i32, Vec<u8>),
+    // the buffer is only held, the kernel may still write to it
+    Cancelled(Vec<u8>),
+}
+
+// a ring is made on the thread that first uses it, but an Op keeps a handle
+// to its own, so a File with a read in flight can move to another thread
+pub(crate) struct Ring {
+    eventfd: OwnedFd,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    ring: IoUring,
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+}
+
+impl Ring {
+    fn new() -> Result<Ring, io::Error> {
+        let ring = IoUring::new(ENTRIES)?;
+
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let eventfd = unsafe { OwnedFd::from_raw_fd(fd) };
+        ring.submitter().register_eventfd(eventfd.as_raw_fd())?;
+
+        Ok(Ring {
+            eventfd,
+            inner: Mutex::new(Inner {
+                ring,
+                slots: Vec::new(),
+                free: Vec::new(),
+            }),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    // read up to len bytes from the current position of fd into buf
+    pub(crate) fn read(self: &Arc<Self>, fd: RawFd, mut buf: Vec<u8>, len: usize) -> Op {
+        buf.resize(len, 0);
+        let entry = opcode::Read::new(types::Fd(fd), buf.as_mut_ptr(), len as u32)
+            .offset(CURRENT_POSITION)
+            .build();
+        Op::submit(self.clone(), entry, buf)
+    }
+
+    // write buf[start..] at the current position of fd
+    pub(crate) fn write(self: &Arc<Self>, fd: RawFd, buf: Vec<u8>, start: usize) -> Op {
+        let data = &buf[start..];
+        let entry = opcode::Write::new(types::Fd(fd), data.as_ptr(), data.len() as u32)
+            .offset(CURRENT_POSITION)
+            .build();
+        Op::submit(self.clone(), entry, buf)
+    }
+
+    // move finished completions into their slots and wake their ops, which
+    // may be waiting on other threads
+    fn reap(&self) {
+        let mut counter = 0u64;
+        unsafe {
+            libc::read(
+                self.eventfd.as_raw_fd(),
+                &mut counter as *mut u64 as *mut c_void,
+                mem::size_of::<u64>(),
+            )
+        };
+
+        let mut wakers = Vec::new();
+        {
+            let inner = &mut *self.lock();
+
+            for cqe in inner.ring.completion() {
+                let slot = cqe.user_data() as usize;
+
+                match mem::replace(&mut inner.slots[slot], Slot::Vacant) {
+                    Slot::Waiting(buf, waker) => {
+                        inner.slots[slot] = Slot::Done(cqe.result(), buf);
+                        wakers.extend(waker);
+                    }
+                    // the kernel is done with it at last
+                    Slot::Cancelled(buf) => {
+                        drop(buf);
+                        inner.free.push(slot);
+                    }
+                    other => inner.slots[slot] = other,
+                }
+            }
+        }
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl Inner {
+    // an error means the entry never made it into the submission queue and
+    // the slot's buffer is the caller's again. Once it's queued the kernel
+    // will see it sooner or later, so failing to submit isn't an error then
+    fn submit(&mut self, entry: squeue::Entry, slot: usize) -> Result<(), io::Error> {
+        let entry = entry.user_data(slot as u64);
+
+        // a full queue is flushed to the kernel once
+        if unsafe { self.ring.submission().push(&entry) }.is_err() {
+            self.ring.submit()?;
+            unsafe { self.ring.submission().push(&entry) }
+                .map_err(|_| io::Error::new(io::ErrorKind::WouldBlock, "submission queue full"))?;
+        }
+
+        if let Err(err) = self.ring.submit() {
+            debug!("io_uring submit failed, retrying on the next poll: {}", err);
+        }
+        Ok(())
+    }
+
+    // hand entries a failed submit left behind to the kernel, false if some
+    // are still queued
+    fn flush(&mut self) -> bool {
+        if self.ring.submission().is_empty() {
+            return true;
+        }
+
+        match self.ring.submit() {
+            Ok(_) => self.ring.submission().is_empty(),
+            Err(err) => {
+                debug!("io_uring submit failed: {}", err);
+                false
+            }
+        }
+    }
+
+    fn alloc(&mut self, buf: Vec<u8>) -> usize {
+        match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Slot::Waiting(buf, None);
+                slot
+            }
+            None => {
+                self.slots.push(Slot::Waiting(buf, None));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn release(&mut self, slot: usize) {
+        self.slots[slot] = Slot::Vacant;
+        self.free.push(slot);
+    }
+}
+
+// this thread's ring, None if the kernel refused one
+pub(crate) fn ring() -> Option<Arc<Ring>> {
+    RING.with(|ring| ring.clone())
+}
+
+// one submitted operation, resolves to the result and the buffer
+pub(crate) struct Op {
+    ring: Arc<Ring>,
+    _pending: PendingWork,
+    slot: Option<usize>,
+    // submitting failed, reported on the first poll
+    error: Option<(io::Error, Vec<u8>)>,
+}
+
+impl Op {
+    fn submit(ring: Arc<Ring>, entry: squeue::Entry, buf: Vec<u8>) -> Op {
+        let res = {
+            let mut inner = ring.lock();
+            let slot = inner.alloc(buf);
+
+            match inner.submit(entry, slot) {
+                Ok(()) => Ok(slot),
+                Err(err) => {
+                    let buf = match mem::replace(&mut inner.slots[slot], Slot::Vacant) {
+                        Slot::Waiting(buf, _) => buf,
+                        _ => Vec::new(),
+                    };
+                    inner.release(slot);
+                    Err((err, buf))
+                }
+            }
+        };
+
+        Op {
+            ring,
+            _pending: PendingWork::new(),
+            slot: res.as_ref().ok().copied(),
+            error: res.err(),
+        }
+    }
+}
+
+impl Future for Op {
+    type Output = (Result<usize, io::Error>, Vec<u8>);
+
+    fn poll(mut self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        if let Some((err, buf)) = self.error.take() {
+            return Poll::Ready((Err(err), buf));
+        }
+
+        let slot = self.slot.expect("polled after completion");
+        let flushed = self.ring.lock().flush();
+        self.ring.reap();
+
+        let done = {
+            let mut inner = self.ring.lock();
+            match mem::replace(&mut inner.slots[slot], Slot::Vacant) {
+                Slot::Done(res, buf) => {
+                    inner.release(slot);
+                    Some((res, buf))
+                }
+                Slot::Waiting(buf, _) => {
+                    inner.slots[slot] = Slot::Waiting(buf, Some(ctx.waker().clone()));
+                    None
+                }
+                other => {
+                    inner.slots[slot] = other;
+                    None
+                }
+            }
+        };
+
+        match done {
+            Some((res, buf)) => {
+                self.slot = None;
+
+                let res = if res < 0 {
+                    Err(io::Error::from_raw_os_error(-res))
+                } else {
+                    Ok(res as usize)
+                };
+                Poll::Ready((res, buf))
+            }
+            // no completion comes for an entry still in the queue, so
+            // try submitting it again next time round
+            None if !flushed => {
+                ctx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            // this thread's reactor watches the ring, whichever thread it
+            // was made on
+            None => {
+                let fd = self.ring.eventfd.as_raw_fd();
+                REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Op {
+    fn drop(&mut self) {
+        let slot = match self.slot {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        let mut inner = self.ring.lock();
+        match mem::replace(&mut inner.slots[slot], Slot::Vacant) {
+            Slot::Waiting(buf, _) => inner.slots[slot] = Slot::Cancelled(buf),
+            _ => inner.release(slot),
+        }
+    }
+}
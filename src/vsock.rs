@@ -0,0 +1,360 @@
+// AF_VSOCK stream sockets for talking between a VM and its host without a
+// network setup (Firecracker, cloud-hypervisor, QEMU vhost-vsock). Addresses
+// are a context id (the VM or the host) plus a port
+use std::convert::TryFrom;
+use std::future::poll_fn;
+use std::io::{self, IoSlice, IoSliceMut};
+use std::mem;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_io::{AsyncRead, AsyncWrite};
+
+use log::debug;
+
+use crate::interest::{self, Interest, Ready};
+use crate::sys;
+use crate::REACTOR;
+
+// same as std
+const DEFAULT_BACKLOG: u32 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VsockAddr {
+    pub cid: u32,
+    pub port: u32,
+}
+
+impl VsockAddr {
+    // bind to whatever cid we have
+    pub const CID_ANY: u32 = libc::VMADDR_CID_ANY;
+    // the hypervisor
+    pub const CID_HYPERVISOR: u32 = libc::VMADDR_CID_HYPERVISOR;
+    // loopback, needs the vsock_loopback module
+    pub const CID_LOCAL: u32 = libc::VMADDR_CID_LOCAL;
+    // the host, as seen from a guest
+    pub const CID_HOST: u32 = libc::VMADDR_CID_HOST;
+
+    pub const PORT_ANY: u32 = libc::VMADDR_PORT_ANY;
+
+    pub fn new(cid: u32, port: u32) -> VsockAddr {
+        VsockAddr { cid, port }
+    }
+
+    fn to_raw(self) -> libc::sockaddr_vm {
+        let mut addr: libc::sockaddr_vm = unsafe { mem::zeroed() };
+        addr.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+        addr.svm_cid = self.cid;
+        addr.svm_port = self.port;
+        addr
+    }
+
+    fn from_raw(addr: &libc::sockaddr_vm) -> VsockAddr {
+        VsockAddr {
+            cid: addr.svm_cid,
+            port: addr.svm_port,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AsyncVsockListener(OwnedFd);
+
+impl AsyncVsockListener {
+    pub fn bind(addr: VsockAddr) -> Result<AsyncVsockListener, io::Error> {
+        let fd = sys::socket(libc::AF_VSOCK, libc::SOCK_STREAM)?;
+        let raw = addr.to_raw();
+
+        let rv = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &raw as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        sys::listen(fd.as_raw_fd(), DEFAULT_BACKLOG)?;
+        Ok(AsyncVsockListener(fd))
+    }
+
+    pub fn local_addr(&self) -> Result<VsockAddr, io::Error> {
+        local_addr(self.0.as_raw_fd())
+    }
+
+    pub async fn accept(&self) -> Result<(AsyncVsockStream, VsockAddr), io::Error> {
+        poll_fn(|ctx| self.poll_accept(ctx)).await
+    }
+
+    pub fn poll_accept(
+        &self,
+        ctx: &mut Context,
+    ) -> Poll<Result<(AsyncVsockStream, VsockAddr), io::Error>> {
+        debug!("poll_accept() called");
+        let fd = self.0.as_raw_fd();
+
+        match sys::accept(fd) {
+            Ok(conn) => {
+                let stream = AsyncVsockStream(conn);
+                Poll::Ready(stream.peer_addr().map(|addr| (stream, addr)))
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                REACTOR.with(|reactor| reactor.add_read_interest(fd, ctx.waker().clone()));
+
+                Poll::Pending
+            }
+            Err(err) => Poll::Ready(Err(err)),
+        }
+    }
+}
+
+impl Drop for AsyncVsockListener {
+    fn drop(&mut self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| reactor.remove_read_interest(fd));
+    }
+}
+
+impl AsRawFd for AsyncVsockListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncVsockListener {
+    fn into_raw_fd(self) -> RawFd {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| reactor.remove_read_interest(fd));
+
+        unsafe { interest::take_io(self, |this| &this.0) }.into_raw_fd()
+    }
+}
+
+// the fd must be a listening vsock socket, it's switched to non-blocking mode
+impl FromRawFd for AsyncVsockListener {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncVsockListener {
+        AsyncVsockListener(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncVsockListener {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncVsockListener, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(AsyncVsockListener(fd))
+    }
+}
+
+#[derive(Debug)]
+pub struct AsyncVsockStream(OwnedFd);
+
+impl AsyncVsockStream {
+    // connect without blocking the event loop, same as TcpSocket::connect
+    pub async fn connect(addr: VsockAddr) -> Result<AsyncVsockStream, io::Error> {
+        let fd = sys::socket(libc::AF_VSOCK, libc::SOCK_STREAM)?;
+        let raw = addr.to_raw();
+
+        let rv = unsafe {
+            libc::connect(
+                fd.as_raw_fd(),
+                &raw as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t,
+            )
+        };
+        if rv == -1 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(err);
+            }
+
+            let stream = AsyncVsockStream(fd);
+            stream.writable().await?;
+            if let Some(err) = sys::take_error(stream.as_raw_fd())? {
+                return Err(err);
+            }
+            return Ok(stream);
+        }
+
+        Ok(AsyncVsockStream(fd))
+    }
+
+    pub fn local_addr(&self) -> Result<VsockAddr, io::Error> {
+        local_addr(self.0.as_raw_fd())
+    }
+
+    pub fn peer_addr(&self) -> Result<VsockAddr, io::Error> {
+        let mut addr: libc::sockaddr_vm = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+
+        let rv = unsafe {
+            libc::getpeername(
+                self.0.as_raw_fd(),
+                &mut addr as *mut _ as *mut libc::sockaddr,
+                &mut len,
+            )
+        };
+        if rv == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(VsockAddr::from_raw(&addr))
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> Result<(), io::Error> {
+        sys::shutdown(self.0.as_raw_fd(), how)
+    }
+
+    pub async fn ready(&self, interest: Interest) -> Result<Ready, io::Error> {
+        poll_fn(|ctx| interest::poll_ready(self.0.as_raw_fd(), interest, ctx)).await
+    }
+
+    pub async fn readable(&self) -> Result<(), io::Error> {
+        self.ready(Interest::READABLE).await.map(|_| ())
+    }
+
+    pub async fn writable(&self) -> Result<(), io::Error> {
+        self.ready(Interest::WRITABLE).await.map(|_| ())
+    }
+
+    fn deregister(&self) {
+        let fd = self.0.as_raw_fd();
+        let _ = REACTOR.try_with(|reactor| {
+            reactor.remove_read_interest(fd);
+            reactor.remove_write_interest(fd);
+        });
+    }
+}
+
+impl Drop for AsyncVsockStream {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+impl AsRawFd for AsyncVsockStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl IntoRawFd for AsyncVsockStream {
+    fn into_raw_fd(self) -> RawFd {
+        self.deregister();
+
+        unsafe { interest::take_io(self, |this| &this.0) }.into_raw_fd()
+    }
+}
+
+// the fd must be a connected vsock socket, it's switched to non-blocking mode
+impl FromRawFd for AsyncVsockStream {
+    unsafe fn from_raw_fd(fd: RawFd) -> AsyncVsockStream {
+        AsyncVsockStream(sys::adopt_fd(fd))
+    }
+}
+
+impl TryFrom<OwnedFd> for AsyncVsockStream {
+    type Error = io::Error;
+
+    fn try_from(fd: OwnedFd) -> Result<AsyncVsockStream, io::Error> {
+        sys::set_nonblocking(fd.as_raw_fd(), true)?;
+        Ok(AsyncVsockStream(fd))
+    }
+}
+
+impl AsyncRead for AsyncVsockStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            sys::recv(fd, buf)
+        })
+    }
+
+    fn poll_read_vectored(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &mut [IoSliceMut],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_read_vectored() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::READABLE, || {
+            let rv = unsafe {
+                libc::readv(
+                    fd,
+                    bufs.as_ptr() as *const libc::iovec,
+                    bufs.len() as libc::c_int,
+                )
+            };
+            if rv == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(rv as usize)
+        })
+    }
+}
+
+impl AsyncWrite for AsyncVsockStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            sys::send(fd, buf)
+        })
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        ctx: &mut Context,
+        bufs: &[IoSlice],
+    ) -> Poll<Result<usize, io::Error>> {
+        debug!("poll_write_vectored() called");
+        let fd = self.0.as_raw_fd();
+        interest::poll_io(self.as_raw_fd(), ctx, Interest::WRITABLE, || {
+            let rv = unsafe {
+                libc::writev(
+                    fd,
+                    bufs.as_ptr() as *const libc::iovec,
+                    bufs.len() as libc::c_int,
+                )
+            };
+            if rv == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(rv as usize)
+        })
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    // closing the writer half-closes the connection, the read side stays open
+    fn poll_close(self: Pin<&mut Self>, _ctx: &mut Context) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(self.shutdown(Shutdown::Write))
+    }
+}
+
+fn local_addr(fd: RawFd) -> Result<VsockAddr, io::Error> {
+    let mut addr: libc::sockaddr_vm = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t;
+
+    let rv = unsafe { libc::getsockname(fd, &mut addr as *mut _ as *mut libc::sockaddr, &mut len) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(VsockAddr::from_raw(&addr))
+}
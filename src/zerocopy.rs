@@ -0,0 +1,155 @@
+// MSG_ZEROCOPY sends (Linux only).
+//
+// with MSG_ZEROCOPY the kernel pins the user pages and transmits straight
+// from them instead of copying into socket buffers. The buffer must not be
+// touched until the kernel reports completion on the socket's error queue,
+// so send_zerocopy() doesn't return until all its sends are acknowledged
+use std::future::poll_fn;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::task::Poll;
+
+use log::debug;
+
+use crate::sys;
+use crate::AsyncTcpStream;
+
+// not exported by libc
+const SO_EE_ORIGIN_ZEROCOPY: u8 = 5;
+
+// completion bookkeeping. The kernel numbers every successful zerocopy
+// send on a socket, starting from 0, and reports ranges of completed ids
+#[derive(Debug, Default)]
+pub(crate) struct ZeroCopy {
+    // id the next send will get
+    next: u32,
+    // all ids below this one are completed
+    completed: u32,
+}
+
+impl AsyncTcpStream {
+    // SO_ZEROCOPY has to be enabled before send_zerocopy() can be used.
+    // it only pays off for large writes, for small ones the page pinning
+    // and completion handling cost more than the copy
+    pub fn set_zerocopy(&self, zerocopy: bool) -> Result<(), io::Error> {
+        let value = zerocopy as libc::c_int;
+        sys::setsockopt(
+            self.0.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_ZEROCOPY,
+            value,
+        )
+    }
+
+    pub fn zerocopy(&self) -> Result<bool, io::Error> {
+        let value: libc::c_int =
+            sys::getsockopt(self.0.as_raw_fd(), libc::SOL_SOCKET, libc::SO_ZEROCOPY)?;
+        Ok(value != 0)
+    }
+
+    // write the whole buffer with MSG_ZEROCOPY and wait until the kernel is
+    // done with it. If the future is dropped before completing, the kernel
+    // may still be reading from buf.
+    // the kernel falls back to copying silently (e.g. on loopback), the
+    // data is delivered either way. Fails with InvalidInput unless
+    // set_zerocopy(true) was called: the kernel then ignores MSG_ZEROCOPY
+    // and no completion would ever arrive
+    pub async fn send_zerocopy(&mut self, buf: &[u8]) -> Result<(), io::Error> {
+        let fd = self.0.as_raw_fd();
+        let mut written = 0;
+
+        if !self.zerocopy()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SO_ZEROCOPY isn't enabled, see set_zerocopy",
+            ));
+        }
+
+        poll_fn(|ctx| {
+            while written < buf.len() {
+                let rest = &buf[written..];
+                let rv = unsafe {
+                    libc::send(
+                        fd,
+                        rest.as_ptr() as *const libc::c_void,
+                        rest.len(),
+                        libc::MSG_ZEROCOPY,
+                    )
+                };
+
+                if rv == -1 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::WouldBlock {
+                        self.register_write(ctx);
+
+                        return Poll::Pending;
+                    }
+                    return Poll::Ready(Err(err));
+                }
+
+                written += rv as usize;
+                self.1.next = self.1.next.wrapping_add(1);
+            }
+
+            Poll::Ready(Ok(()))
+        })
+        .await?;
+
+        poll_fn(|ctx| loop {
+            if self.1.completed == self.1.next {
+                return Poll::Ready(Ok(()));
+            }
+
+            match recv_completion(fd) {
+                Ok(Some((_, hi))) => {
+                    debug!("zerocopy completion on {} up to {}", fd, hi);
+                    self.1.completed = hi.wrapping_add(1);
+                }
+                Ok(None) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    // error queue events are reported as readable by select
+                    self.register_read(ctx);
+
+                    return Poll::Pending;
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        })
+        .await
+    }
+}
+
+// read one notification from the error queue. Returns the completed id
+// range, or None for messages that aren't zerocopy completions
+fn recv_completion(fd: RawFd) -> Result<Option<(u32, u32)>, io::Error> {
+    let mut control = [0u64; 16];
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = mem::size_of_val(&control);
+
+    let rv = unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE) };
+    if rv == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+    while !cmsg.is_null() {
+        let hdr = unsafe { &*cmsg };
+        let is_recverr = (hdr.cmsg_level == libc::SOL_IP && hdr.cmsg_type == libc::IP_RECVERR)
+            || (hdr.cmsg_level == libc::SOL_IPV6 && hdr.cmsg_type == libc::IPV6_RECVERR);
+
+        if is_recverr {
+            let err = unsafe {
+                std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err)
+            };
+            if err.ee_origin == SO_EE_ORIGIN_ZEROCOPY {
+                return Ok(Some((err.ee_info, err.ee_data)));
+            }
+        }
+
+        cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,18 @@
+use std::sync::mpsc;
+use std::time::Duration;
+
+use fahrenheit::spawn_blocking;
+
+#[fahrenheit::test(timeout = "10s")]
+async fn a_burst_of_jobs_gets_its_own_threads() {
+    // leaves one idle thread behind
+    spawn_blocking(|| ()).await.unwrap();
+
+    // the first job can only finish once the second one has run
+    let (tx, rx) = mpsc::channel();
+    let waiting = spawn_blocking(move || rx.recv_timeout(Duration::from_secs(5)));
+    let sending = spawn_blocking(move || tx.send(()).unwrap());
+
+    sending.await.unwrap();
+    assert_eq!(waiting.await.unwrap(), Ok(()));
+}
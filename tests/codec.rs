@@ -0,0 +1,52 @@
+use std::net::UdpSocket;
+
+use futures::StreamExt;
+
+use fahrenheit::codec::{LengthDelimitedCodec, UdpFramed};
+use fahrenheit::AsyncUdpSocket;
+
+// a 4 byte big endian length, then the payload
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut buf = (payload.len() as u32).to_be_bytes().to_vec();
+    buf.extend_from_slice(payload);
+    buf
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn udp_framed_decodes_every_frame_in_a_datagram() {
+    let socket = AsyncUdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let mut framed = UdpFramed::new(socket, LengthDelimitedCodec::new());
+
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let from = sender.local_addr().unwrap();
+    let datagram = [frame(b"one"), frame(b"two"), frame(b"three")].concat();
+    sender.send_to(&datagram, addr).unwrap();
+
+    for expected in [&b"one"[..], b"two", b"three"] {
+        let (got, got_from) = framed.next().await.unwrap().unwrap();
+        assert_eq!(got, expected);
+        assert_eq!(got_from, from);
+    }
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn udp_framed_drops_a_partial_frame() {
+    let socket = AsyncUdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    let mut framed = UdpFramed::new(socket, LengthDelimitedCodec::new());
+
+    let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+    // a whole frame followed by half of one
+    let mut datagram = frame(b"whole");
+    datagram.extend_from_slice(&frame(b"cut short")[..6]);
+    sender.send_to(&datagram, addr).unwrap();
+    // a datagram holding only part of a frame, not completed by the next
+    sender.send_to(&frame(b"partial")[..5], addr).unwrap();
+    sender.send_to(&frame(b"next"), addr).unwrap();
+
+    let (got, _) = framed.next().await.unwrap().unwrap();
+    assert_eq!(got, b"whole");
+    let (got, _) = framed.next().await.unwrap().unwrap();
+    assert_eq!(got, b"next");
+}
@@ -0,0 +1,38 @@
+use std::io::Write;
+use std::os::unix::io::OwnedFd;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use futures::{poll, AsyncReadExt};
+
+use fahrenheit::fs::File;
+
+// a read started on one thread finishes on another, wherever it runs
+#[test]
+fn read_in_flight_moves_to_another_thread() {
+    let (reader, mut writer) = std::io::pipe().unwrap();
+    let file = File::from_std(OwnedFd::from(reader).into());
+    let slot = Arc::new(Mutex::new(Some(file)));
+
+    let started = slot.clone();
+    fahrenheit::run(async move {
+        let mut file = started.lock().unwrap().take().unwrap();
+        let mut buf = [0u8; 5];
+        // the pipe is empty, the read stays in flight when this is dropped
+        assert!(poll!(file.read(&mut buf)).is_pending());
+        *started.lock().unwrap() = Some(file);
+    });
+
+    let mut file = slot.lock().unwrap().take().unwrap();
+    let other = thread::spawn(move || {
+        fahrenheit::run(async move {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data).await.unwrap();
+            assert_eq!(data, b"hello");
+        })
+    });
+
+    writer.write_all(b"hello").unwrap();
+    drop(writer);
+    other.join().unwrap();
+}
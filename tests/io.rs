@@ -0,0 +1,90 @@
+use std::io::{self, ErrorKind};
+use std::time::{Duration, Instant};
+
+use futures::io::Cursor;
+use futures::{AsyncReadExt, AsyncWriteExt};
+
+use fahrenheit::io::{Faulty, MockStream};
+use fahrenheit::time;
+
+#[fahrenheit::test(start_paused = true, timeout = "10s")]
+async fn mock_stream_plays_its_script() {
+    let mut stream = MockStream::builder()
+        .write(b"PING\r\n")
+        .wait(Duration::from_secs(30))
+        .read(b"+PONG\r\n")
+        .read_error(io::Error::new(ErrorKind::ConnectionReset, "reset"))
+        .build();
+
+    let start = time::now();
+    stream.write_all(b"PI").await.unwrap();
+    stream.write_all(b"NG\r\n").await.unwrap();
+
+    let mut buf = [0u8; 7];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"+PONG\r\n");
+    assert!(time::now() - start >= Duration::from_secs(30));
+
+    let err = stream.read(&mut buf).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+    assert_eq!(stream.remaining(), 0);
+    assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+}
+
+#[fahrenheit::test(timeout = "10s")]
+#[should_panic(expected = "mock stream got an unexpected write")]
+async fn mock_stream_rejects_an_unexpected_write() {
+    let mut stream = MockStream::builder().write(b"PING").build();
+    let _ = stream.write_all(b"PONG").await;
+}
+
+#[fahrenheit::test(timeout = "10s")]
+#[should_panic(expected = "mock stream dropped with 1 steps left")]
+async fn mock_stream_dropped_early() {
+    let _stream = MockStream::builder().read(b"hello").build();
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn faulty_errors_leave_the_stream_usable() {
+    let mut stream = Faulty::new(Cursor::new(Vec::new()), 7)
+        .error_rate(0.5)
+        .error_kind(ErrorKind::Interrupted);
+
+    // write_all retries Interrupted
+    stream.write_all(b"hello world").await.unwrap();
+    assert_eq!(stream.get_ref().get_ref(), b"hello world");
+}
+
+// how a seeded Faulty splits a write_all into writes
+async fn short_writes(seed: u64) -> Vec<usize> {
+    let mut stream = Faulty::new(Cursor::new(Vec::new()), seed).short_rate(0.9);
+    let data = [7u8; 1000];
+    let mut sizes = Vec::new();
+    let mut written = 0;
+    while written < data.len() {
+        let n = stream.write(&data[written..]).await.unwrap();
+        sizes.push(n);
+        written += n;
+    }
+    assert_eq!(stream.into_inner().into_inner(), data);
+    sizes
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn faulty_is_repeatable_per_seed() {
+    let first = short_writes(3).await;
+    assert!(first.len() > 1);
+    assert_eq!(first, short_writes(3).await);
+}
+
+#[fahrenheit::test(start_paused = true, timeout = "10s")]
+async fn faulty_delays_follow_the_clock() {
+    let real = Instant::now();
+    let mut stream =
+        Faulty::new(Cursor::new(b"data".to_vec()), 1).delay(1.0, Duration::from_secs(60));
+
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(buf, b"data");
+    assert!(real.elapsed() < Duration::from_secs(5));
+}
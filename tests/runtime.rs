@@ -0,0 +1,160 @@
+use std::future::{pending, poll_fn};
+use std::io::{self, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::panic;
+use std::task::Poll;
+
+use fahrenheit::sync::mpsc;
+use fahrenheit::{explore, run_seeded, run_steps, AsyncFd};
+
+// back of the run queue, polled again next iteration
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|ctx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        ctx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+#[test]
+fn run_steps_gives_up_on_a_busy_task() {
+    let err = run_steps(
+        async {
+            loop {
+                yield_now().await;
+            }
+        },
+        10,
+    )
+    .unwrap_err();
+
+    assert_eq!(err.iterations, 10);
+    let msg = err.to_string();
+    assert!(msg.contains("did not complete in 10 iterations"));
+
+    // the abandoned task is gone, the reactor runs again
+    run_steps(async {}, 1).unwrap();
+}
+
+#[test]
+fn run_steps_drops_tasks_left_waiting() {
+    let (tx, mut rx) = mpsc::channel::<()>(1);
+    let err = run_steps(
+        async move {
+            fahrenheit::spawn(async move {
+                let _tx = tx;
+                pending::<()>().await;
+            });
+            yield_now().await;
+            yield_now().await;
+            yield_now().await;
+        },
+        2,
+    );
+    assert!(err.is_err());
+
+    // dropping the spawned task closed the channel
+    run_steps(async move { assert_eq!(rx.recv().await, None) }, 1).unwrap();
+}
+
+// a copy of fd numbered past what select can watch
+fn dup_past_fd_setsize(fd: &OwnedFd) -> Result<OwnedFd, io::Error> {
+    let min = libc::FD_SETSIZE as libc::c_int;
+    let dup = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_DUPFD_CLOEXEC, min) };
+    if dup < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(dup) })
+}
+
+#[test]
+fn run_steps_reports_a_failed_event_loop() {
+    let (reader, _writer) = io::pipe().unwrap();
+    let high = match dup_past_fd_setsize(&reader.into()) {
+        Ok(fd) => fd,
+        // RLIMIT_NOFILE is too low to get there
+        Err(err) => {
+            eprintln!("skipped: {}", err);
+            return;
+        }
+    };
+
+    let err = run_steps(
+        async move {
+            let fd = AsyncFd::new(high).unwrap();
+            let _ = fd.readable().await;
+        },
+        10,
+    )
+    .unwrap_err();
+
+    let failure = err.error.as_ref().unwrap();
+    assert_eq!(failure.kind(), ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("event loop failed"));
+
+    // the task holding the fd is gone, the reactor runs again
+    run_steps(async {}, 1).unwrap();
+}
+
+// two tasks woken in the same iteration report in the order they're polled
+async fn race() -> Vec<u32> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    for id in 0..2 {
+        let tx = tx.clone();
+        fahrenheit::spawn(async move {
+            yield_now().await;
+            tx.send(id).unwrap();
+        });
+    }
+    drop(tx);
+
+    let mut order = Vec::new();
+    while let Some(id) = rx.recv().await {
+        order.push(id);
+    }
+    order
+}
+
+#[test]
+fn explore_passes_order_independent_code() {
+    explore(20, || async {
+        let mut order = race().await;
+        order.sort();
+        assert_eq!(order, [0, 1]);
+    });
+}
+
+#[test]
+#[should_panic(expected = "explore: failed with seed")]
+fn explore_finds_an_ordering_bug() {
+    explore(20, || async {
+        assert_eq!(race().await, [0, 1]);
+    });
+}
+
+#[test]
+fn a_panicking_seeded_run_stops_shuffling() {
+    let res = panic::catch_unwind(|| {
+        run_seeded(1, async {
+            yield_now().await;
+            panic!("boom");
+        })
+    });
+    assert!(res.is_err());
+
+    // polled in wakeup order again
+    run_steps(
+        async {
+            for _ in 0..20 {
+                assert_eq!(race().await, [0, 1]);
+            }
+        },
+        1000,
+    )
+    .unwrap();
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "sim")]
+
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use futures::{AsyncReadExt, AsyncWriteExt};
+
+use fahrenheit::sim::Network;
+use fahrenheit::time;
+
+const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const CLIENT: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+
+#[fahrenheit::test(start_paused = true, timeout = "10s")]
+async fn latency_runs_on_the_paused_clock() {
+    let net = Network::new(1);
+    net.set_latency(Duration::from_secs(30));
+    net.set_loss(0.5);
+
+    let listener = net.host(SERVER).bind(80).unwrap();
+    fahrenheit::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        stream.write_all(&buf).await.unwrap();
+    });
+
+    let real = Instant::now();
+    let start = time::now();
+
+    let addr = SocketAddr::from((SERVER, 80));
+    let mut stream = net.host(CLIENT).connect(addr).await.unwrap();
+    stream.write_all(b"ping").await.unwrap();
+    let mut buf = [0u8; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+
+    assert_eq!(&buf, b"ping");
+    // connect and the echo are two round trips
+    assert!(time::now() - start >= Duration::from_secs(120));
+    assert!(real.elapsed() < Duration::from_secs(5));
+}
+
+#[fahrenheit::test(start_paused = true, timeout = "10s")]
+async fn partitioned_connect_times_out() {
+    let net = Network::new(1);
+    let _listener = net.host(SERVER).bind(80).unwrap();
+    net.partition(SERVER, CLIENT);
+
+    let addr = SocketAddr::from((SERVER, 80));
+    let err = net.host(CLIENT).connect(addr).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+}
@@ -0,0 +1,100 @@
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use futures::{poll, Sink};
+
+use fahrenheit::sync::mpsc::{self, TrySendError};
+use fahrenheit::sync::{watch, Semaphore};
+
+#[fahrenheit::test(timeout = "10s")]
+async fn wake_from_another_thread() {
+    let (tx, mut rx) = mpsc::channel(1);
+
+    let sender = thread::spawn(move || {
+        for i in 0..3 {
+            thread::sleep(Duration::from_millis(10));
+            tx.blocking_send(i).unwrap();
+        }
+    });
+
+    for i in 0..3 {
+        assert_eq!(rx.recv().await, Some(i));
+    }
+    assert_eq!(rx.recv().await, None);
+    sender.join().unwrap();
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn permit_released_to_cancelled_waiter_goes_to_the_next() {
+    let sem = Arc::new(Semaphore::new(1));
+    let held = sem.clone().try_acquire_owned().unwrap();
+
+    // first in line
+    let mut cancelled = Box::pin(sem.clone().acquire_owned());
+    assert!(poll!(cancelled.as_mut()).is_pending());
+
+    // second in line, a task reporting back once it has the permit
+    let (tx, mut rx) = mpsc::channel(1);
+    let next = sem.clone();
+    fahrenheit::spawn(async move {
+        let permit = next.acquire_owned().await.unwrap();
+        tx.send(()).await.unwrap();
+        drop(permit);
+    });
+
+    // the permit is handed to the first waiter, which goes away unpolled
+    drop(held);
+    drop(cancelled);
+
+    assert_eq!(rx.recv().await, Some(()));
+    assert_eq!(rx.recv().await, None);
+    assert_eq!(sem.available_permits(), 1);
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn dropping_a_sender_with_a_reserved_slot_frees_it() {
+    let (mut tx, mut rx) = mpsc::channel(1);
+    let other = tx.clone();
+
+    poll_fn(|ctx| Pin::new(&mut tx).poll_ready(ctx))
+        .await
+        .unwrap();
+    assert!(matches!(other.try_send(1), Err(TrySendError::Full(1))));
+
+    drop(tx);
+    other.try_send(2).unwrap();
+    drop(other);
+
+    assert_eq!(rx.recv().await, Some(2));
+    assert_eq!(rx.recv().await, None);
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn watch_changed_after_sender_dropped() {
+    let (tx, mut rx) = watch::channel(0);
+    let mut waiting = rx.clone();
+    waiting.borrow_and_update();
+
+    let (done_tx, mut done_rx) = mpsc::channel(1);
+    fahrenheit::spawn(async move {
+        waiting.changed().await.unwrap();
+        done_tx
+            .send(waiting.changed().await.is_err())
+            .await
+            .unwrap();
+    });
+
+    tx.send(1).unwrap();
+    drop(tx);
+
+    // the waiting task sees the last value, then the sender going away
+    assert_eq!(done_rx.recv().await, Some(true));
+
+    // a receiver that hasn't seen the last value still gets it
+    assert!(rx.changed().await.is_ok());
+    assert_eq!(*rx.borrow(), 1);
+    assert!(rx.changed().await.is_err());
+}
@@ -0,0 +1,49 @@
+use std::net::TcpListener;
+use std::time::Duration;
+
+use fahrenheit::AsyncTcpStream;
+
+// a stream connected over loopback, and the listener keeping it open
+fn connected() -> (AsyncTcpStream, TcpListener) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = AsyncTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    (stream, listener)
+}
+
+#[test]
+fn keepalive_times_round_up_to_seconds() {
+    let (stream, _listener) = connected();
+
+    stream
+        .set_keepalive_idle(Duration::from_millis(500))
+        .unwrap();
+    assert_eq!(stream.keepalive_idle().unwrap(), Duration::from_secs(1));
+
+    stream
+        .set_keepalive_interval(Duration::from_millis(1500))
+        .unwrap();
+    assert_eq!(stream.keepalive_interval().unwrap(), Duration::from_secs(2));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn user_timeout_rounds_up_and_saturates() {
+    let (stream, _listener) = connected();
+
+    stream
+        .set_user_timeout(Some(Duration::from_micros(100)))
+        .unwrap();
+    assert_eq!(
+        stream.user_timeout().unwrap(),
+        Some(Duration::from_millis(1))
+    );
+
+    stream
+        .set_user_timeout(Some(Duration::from_secs(100 * 24 * 3600)))
+        .unwrap();
+    let max = Duration::from_millis(i32::MAX as u64);
+    assert_eq!(stream.user_timeout().unwrap(), Some(max));
+
+    stream.set_user_timeout(None).unwrap();
+    assert_eq!(stream.user_timeout().unwrap(), None);
+}
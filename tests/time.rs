@@ -0,0 +1,80 @@
+use std::future::pending;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use fahrenheit::time::{self, sleep};
+
+#[fahrenheit::test(start_paused = true)]
+async fn paused_sleep_is_instant() {
+    let real = Instant::now();
+    let start = time::now();
+
+    sleep(Duration::from_secs(3600)).await;
+
+    assert!(time::now() - start >= Duration::from_secs(3600));
+    assert!(real.elapsed() < Duration::from_secs(5));
+}
+
+#[fahrenheit::test(start_paused = true)]
+async fn advance_fires_timers() {
+    let fired = Arc::new(AtomicBool::new(false));
+    let flag = fired.clone();
+    fahrenheit::spawn(async move {
+        sleep(Duration::from_secs(10)).await;
+        flag.store(true, Ordering::SeqCst);
+    });
+
+    time::advance(Duration::from_secs(5)).await;
+    assert!(!fired.load(Ordering::SeqCst));
+
+    time::advance(Duration::from_secs(5)).await;
+    // the sleeper's timer fired, give it a turn to run
+    sleep(Duration::from_millis(1)).await;
+    assert!(fired.load(Ordering::SeqCst));
+}
+
+#[fahrenheit::test(start_paused = true, timeout = "10s")]
+async fn paused_clock_waits_for_blocking_jobs() {
+    let fired = Arc::new(AtomicBool::new(false));
+    let flag = fired.clone();
+    fahrenheit::spawn(async move {
+        sleep(Duration::from_secs(1)).await;
+        flag.store(true, Ordering::SeqCst);
+    });
+
+    // the timer would win if the clock skipped ahead while the job runs
+    fahrenheit::spawn_blocking(|| std::thread::sleep(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    assert!(!fired.load(Ordering::SeqCst));
+}
+
+#[fahrenheit::test]
+async fn resume_keeps_time_monotonic() {
+    time::pause();
+    let paused = time::now();
+    time::advance(Duration::from_secs(60)).await;
+    time::resume();
+
+    assert!(!time::is_paused());
+    assert!(time::now() >= paused + Duration::from_secs(60));
+}
+
+#[fahrenheit::test(timeout = "100ms")]
+#[should_panic(expected = "timed out")]
+async fn timeout_fails_a_hung_test() {
+    pending::<()>().await;
+}
+
+#[test]
+fn test_attribute_resumes_the_clock() {
+    fahrenheit::__run_test(async {}, true, None);
+    assert!(!time::is_paused());
+
+    let panicked = std::panic::catch_unwind(|| {
+        fahrenheit::__run_test(async { panic!("test failed") }, true, None)
+    });
+    assert!(panicked.is_err());
+    assert!(!time::is_paused());
+}
@@ -0,0 +1,46 @@
+#![cfg(target_os = "linux")]
+
+use std::io::{ErrorKind, Read};
+use std::net::TcpListener;
+use std::thread;
+
+use fahrenheit::AsyncTcpStream;
+
+// a connected loopback stream and a thread reading everything the other
+// end sends
+fn pair() -> (AsyncTcpStream, thread::JoinHandle<Vec<u8>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = AsyncTcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut peer, _) = listener.accept().unwrap();
+
+    let reader = thread::spawn(move || {
+        let mut data = Vec::new();
+        peer.read_to_end(&mut data).unwrap();
+        data
+    });
+    (stream, reader)
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn send_zerocopy_needs_so_zerocopy() {
+    let (mut stream, reader) = pair();
+
+    let err = stream.send_zerocopy(b"hello").await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+    drop(stream);
+    assert!(reader.join().unwrap().is_empty());
+}
+
+#[fahrenheit::test(timeout = "10s")]
+async fn send_zerocopy_waits_for_completions() {
+    let (mut stream, reader) = pair();
+    stream.set_zerocopy(true).unwrap();
+    assert!(stream.zerocopy().unwrap());
+
+    let data: Vec<u8> = (0..1 << 20).map(|i| i as u8).collect();
+    stream.send_zerocopy(&data).await.unwrap();
+
+    drop(stream);
+    assert_eq!(reader.join().unwrap(), data);
+}